@@ -0,0 +1,2268 @@
+//! The walking and jumping itself, independent of where it ends up drawn.
+
+use std::{
+    error::Error,
+    f64::consts::TAU,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use image::{codecs::gif::GifDecoder, AnimationDecoder, ImageFormat, ImageReader};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    config::{Accelerate, Color, Config, Corner, Easing, EdgeMode, JumpEase, Outline, Pulse},
+    glob,
+};
+
+/// Height above the ground during a jump, along with the height this jump
+/// tops out at.
+enum JumpState {
+    NotJumping,
+    Ascending { y: u64, limit: u64 },
+    Descending { y: u64, limit: u64 },
+}
+
+impl JumpState {
+    fn next(&mut self, jump_step: u64, ease: JumpEase) {
+        if ease == JumpEase::Parabolic {
+            return self.next_parabolic(jump_step);
+        }
+        // Slow down over the top 40% of the jump, or for a linear one never.
+        let top = match ease {
+            JumpEase::EaseOut => 0.6,
+            _ => 1.0,
+        };
+        let easing = |limit: u64| (limit as f64 * top) as u64;
+        *self = match *self {
+            JumpState::Ascending { y, limit } if y >= limit => JumpState::Descending {
+                y: limit.saturating_sub(jump_step),
+                limit,
+            },
+            JumpState::Ascending { y, limit } if y >= easing(limit) => JumpState::Ascending {
+                y: y + jump_step / 4,
+                limit,
+            },
+            JumpState::Ascending { y, limit } => JumpState::Ascending {
+                y: y + jump_step,
+                limit,
+            },
+            JumpState::Descending { y: 0, .. } => JumpState::NotJumping,
+            JumpState::Descending { y, limit } if y >= easing(limit) => JumpState::Descending {
+                y: y.saturating_sub(jump_step / 4),
+                limit,
+            },
+            JumpState::Descending { y, limit } => JumpState::Descending {
+                y: y.saturating_sub(jump_step),
+                limit,
+            },
+            JumpState::NotJumping => JumpState::NotJumping,
+        };
+    }
+
+    /// Takes as many ticks as a linear jump to get to the top, rising
+    /// ever slower and falling ever faster, by at least a pixel a tick.
+    fn next_parabolic(&mut self, jump_step: u64) {
+        let ticks = |limit: u64| (limit as f64 / jump_step as f64).max(1.0);
+        // The height `from_top` ticks before or after the top, and how far
+        // from the top `y` is.
+        let height = |from_top: f64, limit: u64| {
+            let t = (from_top / ticks(limit)).clamp(0.0, 1.0);
+            (limit as f64 * (1.0 - t * t)).round() as u64
+        };
+        let from_top =
+            |y: u64, limit: u64| ticks(limit) * (1.0 - y.min(limit) as f64 / limit as f64).sqrt();
+        // Heights are only ever taken whole ticks from the ground on the way
+        // up, or from the top on the way down, so rounding to that undoes
+        // the rounding of the height itself.
+        *self = match *self {
+            JumpState::Ascending { y, limit } if y >= limit => JumpState::Descending {
+                y: height(1.0, limit).min(limit - 1),
+                limit,
+            },
+            JumpState::Ascending { y, limit } => {
+                let risen = (ticks(limit) - from_top(y, limit)).round();
+                JumpState::Ascending {
+                    y: height(ticks(limit) - risen - 1.0, limit).clamp(y + 1, limit),
+                    limit,
+                }
+            }
+            JumpState::Descending { y: 0, .. } => JumpState::NotJumping,
+            JumpState::Descending { y, limit } => JumpState::Descending {
+                y: height(from_top(y, limit).round() + 1.0, limit).min(y - 1),
+                limit,
+            },
+            JumpState::NotJumping => JumpState::NotJumping,
+        };
+    }
+}
+
+/// The walk step from `min` to `max`, the first thing picked with a
+/// gopher's seed.
+pub(crate) fn pick_walk_step((min, max): (u64, u64), rng: &mut StdRng) -> u64 {
+    if min == max {
+        min
+    } else {
+        rng.sample(rand::distributions::Uniform::new_inclusive(min, max))
+    }
+}
+
+/// Loads the frames and sets up the animation as configured. Buffers will
+/// have `padding` extra pixels on top of the frames.
+pub(crate) fn build_animation(config: &Config, padding: u32) -> Result<Animation, Box<dyn Error>> {
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let walk_step = pick_walk_step(config.walk_step_range(), &mut rng);
+    // --dig-frames and --popup-frames are loaded like a --skin.
+    let dig_images = |frames: &Option<PathBuf>| match frames {
+        Some(frames) => {
+            let dig_config = Config {
+                frames: Some(frames.clone()),
+                ..config.clone()
+            };
+            load_images(&dig_config, padding).map(Some)
+        }
+        None => Ok(None),
+    };
+
+    Animation::builder()
+        .frames(load_images(config, padding)?)
+        .walk_step(walk_step)
+        .jump_step(15)
+        .jump_count(6)
+        .jump_heights(config.jump_height_range())
+        .jump_ease(config.jump_ease)
+        .speed(config.speed)
+        .edge(config.edge, config.mirror_on_wrap)
+        .edge_pause(config.edge_pause_ms)
+        .ease_edges(config.edge_easing())
+        .center_start(config.center_start)
+        .rng(rng)
+        .threads(config.load_threads())
+        .flip(!config.no_flip)
+        .levels(config.brightness, config.gamma)
+        .palette(config.palette.as_deref().map(load_palette).transpose()?)
+        .outline(config.outline)
+        .pulse(config.pulse)
+        .accelerate(config.accelerate)
+        .flip_threshold(config.flip_threshold)
+        .hop_only(config.hop_on_key)
+        .jump_frames(config.jump_up_frame, config.jump_down_frame)
+        .pin(config.anchor, config.no_walk)
+        .dig(
+            config.dig_every,
+            dig_images(&config.dig_frames)?.unwrap_or_default(),
+            dig_images(&config.popup_frames)?,
+        )
+        .build()
+}
+
+/// Loads the frames and scales them as configured, for
+/// [`build_animation`] or to replace the frames of a running animation.
+pub(crate) fn load_images(
+    config: &Config,
+    padding: u32,
+) -> Result<Vec<image::RgbaImage>, Box<dyn Error>> {
+    let mut images = load_frames(
+        config.frames.as_deref(),
+        config.max_frames,
+        config.load_threads(),
+    )?;
+    // Keyed before resizing, which would blend the key into its neighbours.
+    if let Some(Color([r, g, b, _])) = config.color_key {
+        for image in &mut images {
+            apply_color_key(image, [r, g, b], config.color_key_tolerance);
+        }
+    }
+    let images = scale_frames(images, config.scale);
+    // The outline comes on top of the frames' size.
+    let outline_width = config.outline.map_or(0, |outline| outline.width * 2);
+    let max_dim = config
+        .max_buffer_dim
+        .saturating_sub(padding + outline_width);
+    Ok(fit_frames(images, max_dim.max(1)))
+}
+
+/// Loads the frames as configured and prints one `INDEX WIDTHxHEIGHT` line
+/// per frame, warning if they aren't all the same size.
+pub(crate) fn check_frames(config: &Config) -> Result<(), Box<dyn Error>> {
+    let images = load_frames(
+        config.frames.as_deref(),
+        config.max_frames,
+        config.load_threads(),
+    )?;
+    for (i, image) in images.iter().enumerate() {
+        println!("{} {}x{}", i, image.width(), image.height());
+    }
+
+    if images
+        .iter()
+        .any(|image| image.dimensions() != images[0].dimensions())
+    {
+        eprintln!(
+            "wlgopher: warning: the frames differ in size; they're lined up at the \
+             bottom center of each and the buffers fit the largest"
+        );
+    }
+    Ok(())
+}
+
+/// Frames used when no `--frames` path is given.
+const DEFAULT_FRAMES: [&str; 3] = ["image/out01.png", "image/out02.png", "image/out03.png"];
+
+/// Loads the animation frames from `path`, which may be a directory of
+/// images (taken in file name order), an animated GIF or a single image.
+/// A nonzero `max_frames` caps how many are loaded. Directories are decoded
+/// on up to `threads` threads.
+fn load_frames(
+    path: Option<&Path>,
+    max_frames: usize,
+    threads: usize,
+) -> Result<Vec<image::RgbaImage>, Box<dyn Error>> {
+    let limit = if max_frames == 0 {
+        usize::MAX
+    } else {
+        max_frames
+    };
+    let warn_truncated = |path: &Path, found: &str| {
+        eprintln!(
+            "wlgopher: warning: {} has {} frames, only loading the first {}",
+            path.display(),
+            found,
+            limit
+        );
+    };
+
+    let frames = match path {
+        None => DEFAULT_FRAMES
+            .iter()
+            .map(|path| decode_image(Path::new(path)))
+            .collect::<Result<_, _>>()?,
+        Some(path) if path.is_dir() || !path.exists() && glob::is_pattern(path) => {
+            let mut paths = if path.is_dir() {
+                std::fs::read_dir(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .collect()
+            } else {
+                glob::expand(path)?
+            };
+            paths.retain(|path| path.is_file() && ImageFormat::from_path(path).is_ok());
+            paths.sort();
+            if paths.is_empty() && !path.is_dir() {
+                return Err(format!("{} matches no images", path.display()).into());
+            }
+
+            if paths.len() > limit {
+                warn_truncated(path, &paths.len().to_string());
+                paths.truncate(limit);
+            }
+
+            // Box<dyn Error> can't cross threads, so keep just the message.
+            parallel_map(&paths, threads, |path| {
+                decode_image(path).map_err(|e| e.to_string())
+            })
+            .into_iter()
+            .collect::<Result<_, _>>()?
+        }
+        Some(path) if ImageFormat::from_path(path).ok() == Some(ImageFormat::Gif) => {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let decoder = GifDecoder::new(BufReader::new(file))?;
+            // Decode one past the limit to find out whether we're dropping any.
+            let mut frames = decoder
+                .into_frames()
+                .take(limit.saturating_add(1))
+                .map(|frame| frame.map(image::Frame::into_buffer))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if frames.len() > limit {
+                warn_truncated(path, "more than that");
+                frames.truncate(limit);
+            }
+
+            frames
+        }
+        Some(path) => vec![decode_image(path)?],
+    };
+
+    if frames.is_empty() {
+        return Err(format!(
+            "no frames found in {}",
+            path.unwrap_or(Path::new(".")).display()
+        )
+        .into());
+    }
+
+    Ok(frames)
+}
+
+/// Scales `size` down, keeping its aspect ratio, until neither side is
+/// longer than `max_dim`.
+pub(crate) fn fit_size((width, height): (u32, u32), max_dim: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_dim {
+        return (width, height);
+    }
+
+    let scale = max_dim as f64 / longest as f64;
+    let fit = |side: u32| ((side as f64 * scale).floor() as u32).clamp(1, max_dim);
+    (fit(width), fit(height))
+}
+
+/// Resizes every frame by `scale`, for gophers of different sizes.
+fn scale_frames(images: Vec<image::RgbaImage>, scale: f64) -> Vec<image::RgbaImage> {
+    if scale == 1.0 {
+        return images;
+    }
+    images
+        .iter()
+        .map(|image| {
+            let width = ((image.width() as f64 * scale).round() as u32).max(1);
+            let height = ((image.height() as f64 * scale).round() as u32).max(1);
+            image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle)
+        })
+        .collect()
+}
+
+/// Scales all frames down by the same factor if any of them is bigger than
+/// the compositor could be expected to accept.
+fn fit_frames(images: Vec<image::RgbaImage>, max_dim: u32) -> Vec<image::RgbaImage> {
+    let longest = images
+        .iter()
+        .map(|image| image.width().max(image.height()))
+        .max()
+        .unwrap_or(0);
+    if longest <= max_dim {
+        return images;
+    }
+
+    eprintln!(
+        "wlgopher: warning: frames up to {} pixels across exceed --max-buffer-dim {}, \
+         scaling them down",
+        longest, max_dim
+    );
+    let scale = max_dim as f64 / longest as f64;
+    images
+        .iter()
+        .map(|image| {
+            let (width, height) = fit_size(
+                image.dimensions(),
+                (image.width().max(image.height()) as f64 * scale).floor() as u32,
+            );
+            image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle)
+        })
+        .collect()
+}
+
+fn apply_color_key(image: &mut image::RgbaImage, key: [u8; 3], tolerance: u8) {
+    for pixel in image.pixels_mut() {
+        let matches = pixel.0[..3]
+            .iter()
+            .zip(key)
+            .all(|(&c, k)| c.abs_diff(k) <= tolerance);
+        if matches {
+            pixel.0 = [0; 4];
+        }
+    }
+}
+
+/// Replaces every pixel's color with the nearest of `palette`'s, leaving
+/// its alpha as it is.
+fn quantize(image: &mut image::RgbaImage, palette: &[[u8; 3]]) {
+    let distance = |a: &[u8], b: [u8; 3]| -> u32 {
+        a.iter()
+            .zip(b)
+            .map(|(&a, b)| (a.abs_diff(b) as u32).pow(2))
+            .sum()
+    };
+    for pixel in image.pixels_mut() {
+        let rgb = &pixel.0[..3];
+        let nearest = *(palette.iter())
+            .min_by_key(|&&color| distance(rgb, color))
+            .unwrap();
+        pixel.0[..3].copy_from_slice(&nearest);
+    }
+}
+
+/// What each channel value becomes once multiplied by `brightness` and then
+/// raised to the power of 1 / `gamma`, so a gamma above 1 lightens the
+/// midtones.
+fn levels_table(brightness: f64, gamma: f64) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (c, out) in table.iter_mut().enumerate() {
+        let level = (c as f64 / 255.0 * brightness).min(1.0);
+        *out = (level.powf(1.0 / gamma) * 255.0).round() as u8;
+    }
+    table
+}
+
+/// Draws `outline` around the opaque parts of `image`, beneath any
+/// translucent edge, growing it by the outline's width on every side to
+/// make room.
+fn apply_outline(image: &image::RgbaImage, outline: Outline) -> image::RgbaImage {
+    let Outline {
+        color: Color(color),
+        width,
+    } = outline;
+    let reach = width as i64;
+    let source = |x: i64, y: i64| {
+        let inside = x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64;
+        inside.then(|| image.get_pixel(x as u32, y as u32).0)
+    };
+    let opaque = |x, y| source(x, y).is_some_and(|pixel| pixel[3] >= 0x80);
+
+    let mut outlined = image::RgbaImage::new(image.width() + width * 2, image.height() + width * 2);
+    for (x, y, pixel) in outlined.enumerate_pixels_mut() {
+        let (x, y) = (x as i64 - reach, y as i64 - reach);
+        let src = source(x, y).unwrap_or([0; 4]);
+        let near = (-reach..=reach).any(|dy| {
+            (-reach..=reach).any(|dx| dx * dx + dy * dy <= reach * reach && opaque(x + dx, y + dy))
+        });
+        pixel.0 = if near { over(src, color) } else { src };
+    }
+    outlined
+}
+
+/// Composites the straight-alpha pixel `top` over `bottom`.
+fn over(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+    let (top_a, bottom_a) = (top[3] as f32 / 255.0, bottom[3] as f32 / 255.0);
+    let a = top_a + bottom_a * (1.0 - top_a);
+    if a == 0.0 {
+        return [0; 4];
+    }
+    let channel = |i: usize| {
+        let c = (top[i] as f32 * top_a + bottom[i] as f32 * bottom_a * (1.0 - top_a)) / a;
+        c.round() as u8
+    };
+    [
+        channel(0),
+        channel(1),
+        channel(2),
+        (a * 255.0).round() as u8,
+    ]
+}
+
+/// Maps `items` with `f` on up to `threads` scoped threads, each taking a
+/// contiguous run of them, so the results come out in the same order.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_len = items.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_len)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Loads the `--splash` image, scaled down if either side is longer than
+/// `max_dim`.
+pub(crate) fn load_splash(path: &Path, max_dim: u32) -> Result<image::RgbaImage, Box<dyn Error>> {
+    let images = fit_frames(vec![decode_image(path)?], max_dim);
+    Ok(images.into_iter().next().unwrap())
+}
+
+/// Reads a `--palette`: RRGGBB colors, one per line or separated by
+/// spaces or commas.
+fn load_palette(path: &Path) -> Result<Vec<[u8; 3]>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let palette = (text.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|word| !word.is_empty())
+        .map(|word| match word.parse::<Color>() {
+            Ok(Color([r, g, b, _])) => Ok([r, g, b]),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if palette.is_empty() {
+        return Err(format!("{}: the palette has no colors", path.display()).into());
+    }
+    Ok(palette)
+}
+
+/// Loads the `--background-image`, which is scaled to the window once its
+/// size is known.
+pub(crate) fn load_background(path: &Path) -> Result<image::RgbaImage, Box<dyn Error>> {
+    decode_image(path)
+}
+
+fn decode_image(path: &Path) -> Result<image::RgbaImage, Box<dyn Error>> {
+    let image = ImageReader::open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .decode()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(image.into_rgba8())
+}
+
+/// How the builder's images are turned into frames, which is kept for
+/// [`Animation::set_frames`].
+struct FrameOptions {
+    threads: usize,
+    flip: bool,
+    /// Brightness and gamma.
+    levels: (f64, f64),
+    /// The colors the frames are cut down to, if any.
+    palette: Option<Vec<[u8; 3]>>,
+    outline: Option<Outline>,
+}
+
+impl FrameOptions {
+    /// Returns the frames along with their mirrored versions, if any.
+    fn prepare(&self, mut images: Vec<image::RgbaImage>) -> (Vec<Frame>, Vec<Frame>) {
+        if self.levels != (1.0, 1.0) {
+            let table = levels_table(self.levels.0, self.levels.1);
+            for image in &mut images {
+                for pixel in image.pixels_mut() {
+                    for c in &mut pixel.0[..3] {
+                        *c = table[*c as usize];
+                    }
+                }
+            }
+        }
+        if let Some(palette) = self.palette.as_deref() {
+            for image in &mut images {
+                quantize(image, palette);
+            }
+        }
+        if let Some(outline) = self.outline {
+            images = parallel_map(&images, self.threads, |image| apply_outline(image, outline));
+        }
+
+        let frames: Vec<_> = images.into_iter().map(Frame::new).collect();
+
+        let frames_flipped = if self.flip {
+            parallel_map(&frames, self.threads, Frame::flipped)
+        } else {
+            Vec::new()
+        };
+        (frames, frames_flipped)
+    }
+}
+
+pub(crate) struct Frame {
+    pub(crate) image: image::RgbaImage,
+    /// The point within the image that stays put from frame to frame
+    /// (normally the gopher's feet).
+    anchor: (u32, u32),
+}
+
+impl Frame {
+    /// A frame anchored at the bottom center, where the feet are.
+    fn new(image: image::RgbaImage) -> Self {
+        let anchor = (image.width() / 2, image.height());
+        Frame { image, anchor }
+    }
+
+    fn flipped(&self) -> Self {
+        Frame {
+            image: image::imageops::flip_horizontal(&self.image),
+            anchor: (self.image.width() - self.anchor.0, self.anchor.1),
+        }
+    }
+}
+
+/// Computes the smallest buffer size that fits every frame once their
+/// anchors are aligned, along with where that shared anchor sits in it.
+fn frame_layout<'a>(frames: impl Iterator<Item = &'a Frame> + Clone) -> ((u32, u32), (u32, u32)) {
+    let left = frames.clone().map(|f| f.anchor.0).max().unwrap_or(0);
+    let right = frames
+        .clone()
+        .map(|f| f.image.width() - f.anchor.0)
+        .max()
+        .unwrap_or(0);
+    let top = frames.clone().map(|f| f.anchor.1).max().unwrap_or(0);
+    let bottom = frames
+        .map(|f| f.image.height() - f.anchor.1)
+        .max()
+        .unwrap_or(0);
+
+    ((left + right, top + bottom), (left, top))
+}
+
+/// How far along digging down and popping back up the gopher is, for
+/// `--dig-every`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dig {
+    /// Ticks into the dig frames.
+    Down(usize),
+    /// Out of sight, with this many milliseconds to go.
+    Hidden(u32),
+    /// Ticks into the pop-up frames, having moved somewhere else.
+    Up(usize),
+}
+
+/// The `--dig-frames` and `--popup-frames`, each with their mirrored
+/// versions if any.
+#[derive(Default)]
+struct DigFrames {
+    down: (Vec<Frame>, Vec<Frame>),
+    up: (Vec<Frame>, Vec<Frame>),
+}
+
+impl DigFrames {
+    fn iter(&self) -> impl Iterator<Item = &Frame> + Clone {
+        (self.down.0.iter().chain(&self.down.1)).chain(self.up.0.iter().chain(&self.up.1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AnimationEvent {
+    /// Touched down at the end of a jump.
+    Landed,
+    /// Turned around at the edge of the area.
+    Bounced,
+    /// Went off one edge for `EdgeMode::Wrap`, whether it comes back on the
+    /// other side or turns around out of sight.
+    Crossed,
+}
+
+/// Length of one animation tick. The movement parameters are all per tick,
+/// and `step` runs however many ticks fit into the elapsed time.
+pub(crate) const TICK_MS: u32 = 16;
+
+/// The share of its speed `--ease-edges` slows the gopher to right at an
+/// edge, rather than to a standstill it would never get out of.
+const MIN_EASE: f64 = 0.2;
+
+/// Ticks each of the dig and pop-up frames is shown for. These go by
+/// slower than the walk cycle to be seen at all.
+const DIG_FRAME_TICKS: usize = 6;
+/// How long a gopher that dug down stays out of sight.
+const DIG_HIDDEN_MS: u32 = 2000;
+
+pub(crate) struct Animation {
+    x: f64,
+    y: u64,
+    area: (u64, u64),
+    count: u64,
+    /// Elapsed time not yet consumed by a whole tick.
+    pending_ms: u32,
+    jump: JumpState,
+    /// Which way the gopher walks next.
+    forward: bool,
+    /// Which way the sprite faces. Only `face` sets this, from how the
+    /// gopher actually moved, so it can't disagree with what's on screen.
+    facing_forward: bool,
+
+    walk_step: u64,
+    /// Multiplier applied to `walk_step`.
+    pub(crate) speed: f64,
+    jump_count: u64,
+    jump_step: u64,
+    /// The range every jump picks its height from, rather than always
+    /// `jump_step * jump_count`.
+    jump_heights: Option<(u64, u64)>,
+    jump_ease: JumpEase,
+    /// Ticks between the starts of two jumps.
+    jump_every: u64,
+    edge: EdgeMode,
+    mirror_on_wrap: bool,
+    /// How long to stand at an edge before turning around.
+    edge_pause_ms: u32,
+    /// How to slow down near the edges, and from how far out.
+    easing: Option<(Easing, u32)>,
+    /// What's left of the current pause at an edge.
+    edge_wait_ms: Option<u32>,
+    /// Stand still except for the hops `hop` starts.
+    hop_only: bool,
+    /// The frames shown on the way up and down a jump, if not the first.
+    /// Frames reloaded with fewer of them fall back to the first.
+    jump_frames: (Option<usize>, Option<usize>),
+    /// The corner the gopher stays in, if it doesn't move at all, and
+    /// whether it holds its first frame there.
+    pinned: Option<Corner>,
+    still: bool,
+    pulse: Option<Pulse>,
+    accelerate: Option<Accelerate>,
+    /// What `accelerate` has multiplied the speed by so far.
+    groove: f64,
+    /// How far past the gopher's middle and the other way the target has
+    /// to be for it to turn around.
+    flip_threshold: f64,
+    /// Ticks between digs, if it digs at all.
+    dig_every: Option<u64>,
+    dig_frames: DigFrames,
+    dig: Option<Dig>,
+
+    frames: Vec<Frame>,
+    frames_flipped: Vec<Frame>,
+    frame_index: usize,
+    frame_options: FrameOptions,
+
+    size: (u32, u32),
+    anchor: (u32, u32),
+
+    /// Things that happened since the last `take_events`.
+    events: Vec<AnimationEvent>,
+
+    /// Move to the middle as soon as the area is known.
+    center_pending: bool,
+    /// Whether it started out that way, for `restart`.
+    center_start: bool,
+    /// Move here instead once the area is known.
+    start_x: Option<f64>,
+    rng: StdRng,
+
+    /// Where the middle of the gopher is chasing to, if anywhere.
+    target: Option<f64>,
+}
+
+/// Sets up an [`Animation`], checking that the parameters make sense.
+pub(crate) struct AnimationBuilder {
+    frames: Vec<image::RgbaImage>,
+    area: (u64, u64),
+    walk_step: u64,
+    speed: f64,
+    jump_step: u64,
+    jump_count: u64,
+    jump_heights: Option<(u64, u64)>,
+    jump_ease: JumpEase,
+    edge: EdgeMode,
+    mirror_on_wrap: bool,
+    edge_pause_ms: u32,
+    easing: Option<(Easing, u32)>,
+    center_start: bool,
+    rng: Option<StdRng>,
+    frame_options: FrameOptions,
+    hop_only: bool,
+    jump_frames: (Option<usize>, Option<usize>),
+    pinned: Option<Corner>,
+    still: bool,
+    pulse: Option<Pulse>,
+    accelerate: Option<Accelerate>,
+    flip_threshold: u32,
+    dig_every_secs: Option<u32>,
+    dig_frames: (Vec<image::RgbaImage>, Option<Vec<image::RgbaImage>>),
+}
+
+impl AnimationBuilder {
+    pub(crate) fn frames(mut self, frames: Vec<image::RgbaImage>) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// The area to walk in, for tests that know it up front; otherwise it's
+    /// given later with `set_area`.
+    #[cfg(test)]
+    pub(crate) fn area(mut self, area: (u64, u64)) -> Self {
+        self.area = area;
+        self
+    }
+
+    /// Pixels walked per tick.
+    pub(crate) fn walk_step(mut self, walk_step: u64) -> Self {
+        self.walk_step = walk_step;
+        self
+    }
+
+    pub(crate) fn speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Pixels risen or fallen per tick of a jump.
+    pub(crate) fn jump_step(mut self, jump_step: u64) -> Self {
+        self.jump_step = jump_step;
+        self
+    }
+
+    /// Ticks it takes to reach the top of a jump.
+    pub(crate) fn jump_count(mut self, jump_count: u64) -> Self {
+        self.jump_count = jump_count;
+        self
+    }
+
+    /// The inclusive range of heights, in pixels, each jump picks a random
+    /// one from. Without it every jump is `jump_step * jump_count` high.
+    pub(crate) fn jump_heights(mut self, (min, max): (u64, u64)) -> Self {
+        self.jump_heights = Some((min, max));
+        self
+    }
+
+    pub(crate) fn jump_ease(mut self, ease: JumpEase) -> Self {
+        self.jump_ease = ease;
+        self
+    }
+
+    pub(crate) fn edge(mut self, edge: EdgeMode, mirror_on_wrap: bool) -> Self {
+        self.edge = edge;
+        self.mirror_on_wrap = mirror_on_wrap;
+        self
+    }
+
+    /// Stand at the edge for `ms` milliseconds of animation time before
+    /// turning around. Only edges the gopher bounces off count, as with
+    /// `EdgeMode::Wrap` it turns, if at all, out of sight.
+    pub(crate) fn edge_pause(mut self, ms: u32) -> Self {
+        self.edge_pause_ms = ms;
+        self
+    }
+
+    /// Slows the walk down within `distance` pixels of an edge along
+    /// `easing`, down to `MIN_EASE` of its speed. Again only edges the
+    /// gopher bounces off count.
+    pub(crate) fn ease_edges(mut self, easing: Option<(Easing, u32)>) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Move to the middle of the area, facing a random way, once it's known.
+    pub(crate) fn center_start(mut self, center_start: bool) -> Self {
+        self.center_start = center_start;
+        self
+    }
+
+    pub(crate) fn rng(mut self, rng: StdRng) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Whether to prepare mirrored frames for walking backwards. Symmetric
+    /// art can do without them.
+    pub(crate) fn flip(mut self, flip: bool) -> Self {
+        self.frame_options.flip = flip;
+        self
+    }
+
+    /// Adjusts the frames' colors, after any color key (applied as they're
+    /// loaded) so that it matches the original art, and before the
+    /// outline, which keeps its color. A gopher's tint is applied on top
+    /// of this when drawing.
+    pub(crate) fn levels(mut self, brightness: f64, gamma: f64) -> Self {
+        self.frame_options.levels = (brightness, gamma);
+        self
+    }
+
+    /// Replaces each of the frames' colors with the nearest in `palette`,
+    /// after `levels` and before the outline. Alpha is left alone.
+    pub(crate) fn palette(mut self, palette: Option<Vec<[u8; 3]>>) -> Self {
+        self.frame_options.palette = palette;
+        self
+    }
+
+    /// Draws a border around the frames' silhouette. Flipped frames are
+    /// made from the outlined ones, so they have it too.
+    pub(crate) fn outline(mut self, outline: Option<Outline>) -> Self {
+        self.frame_options.outline = outline;
+        self
+    }
+
+    /// Grows and shrinks the gopher over time, which `pulse_scale` says
+    /// by how much.
+    pub(crate) fn pulse(mut self, pulse: Option<Pulse>) -> Self {
+        self.pulse = pulse;
+        self
+    }
+
+    /// Speeds up the longer the gopher walks without turning or jumping.
+    pub(crate) fn accelerate(mut self, accelerate: Option<Accelerate>) -> Self {
+        self.accelerate = accelerate;
+        self
+    }
+
+    /// Keeps a gopher chasing a target from turning around until it's more
+    /// than `pixels` behind, so it doesn't twitch back and forth over one
+    /// that's about where it is.
+    pub(crate) fn flip_threshold(mut self, pixels: u32) -> Self {
+        self.flip_threshold = pixels;
+        self
+    }
+
+    /// Digs down every `secs` seconds, showing the `down` frames, then pops
+    /// up somewhere else with the `up` frames, by default `down` backwards.
+    pub(crate) fn dig(
+        mut self,
+        secs: Option<u32>,
+        down: Vec<image::RgbaImage>,
+        up: Option<Vec<image::RgbaImage>>,
+    ) -> Self {
+        self.dig_every_secs = secs;
+        self.dig_frames = (down, up);
+        self
+    }
+
+    /// Only move by hopping when `hop` is called, instead of walking and
+    /// jumping on its own.
+    pub(crate) fn hop_only(mut self, hop_only: bool) -> Self {
+        self.hop_only = hop_only;
+        self
+    }
+
+    /// Shows frame `up` on the way up a jump and `down` on the way down,
+    /// rather than the first frame.
+    pub(crate) fn jump_frames(mut self, up: Option<usize>, down: Option<usize>) -> Self {
+        self.jump_frames = (up, down);
+        self
+    }
+
+    /// Stays in `corner` facing into the area rather than moving, going
+    /// through the frames in place unless `still` is set.
+    pub(crate) fn pin(mut self, corner: Option<Corner>, still: bool) -> Self {
+        self.pinned = corner;
+        self.still = still;
+        self
+    }
+
+    /// How many threads may be used to prepare the frames.
+    pub(crate) fn threads(mut self, threads: usize) -> Self {
+        self.frame_options.threads = threads;
+        self
+    }
+
+    pub(crate) fn build(self) -> Result<Animation, Box<dyn Error>> {
+        if self.frames.is_empty() {
+            return Err("the animation needs at least one frame".into());
+        }
+        if self.walk_step == 0 || self.jump_step == 0 || self.jump_count == 0 {
+            return Err("the walk and jump steps must be at least 1".into());
+        }
+        if let Some((min, max)) = self.jump_heights {
+            if min == 0 || min > max {
+                return Err(format!("invalid jump height range {}..={}", min, max).into());
+            }
+        }
+        if !(self.speed > 0.0 && self.speed.is_finite()) {
+            return Err(format!("the speed must be a positive number, not {}", self.speed).into());
+        }
+        for (option, index) in [
+            ("--jump-up-frame", self.jump_frames.0),
+            ("--jump-down-frame", self.jump_frames.1),
+        ] {
+            if index.is_some_and(|index| index >= self.frames.len()) {
+                return Err(format!(
+                    "{} {} is past the last frame, there being {}",
+                    option,
+                    index.unwrap(),
+                    self.frames.len()
+                )
+                .into());
+            }
+        }
+
+        let (frames, frames_flipped) = self.frame_options.prepare(self.frames);
+        let (down, up) = self.dig_frames;
+        let up = up.unwrap_or_else(|| down.iter().rev().cloned().collect());
+        let dig_frames = DigFrames {
+            down: self.frame_options.prepare(down),
+            up: self.frame_options.prepare(up),
+        };
+        let (size, anchor) =
+            frame_layout((frames.iter().chain(frames_flipped.iter())).chain(dig_frames.iter()));
+        let forward = self.pinned.is_none_or(Corner::is_left);
+
+        let mut animation = Animation {
+            x: 0.0,
+            y: 0,
+            area: (0, 0),
+            count: 0,
+            pending_ms: 0,
+            jump: JumpState::NotJumping,
+            forward,
+            facing_forward: forward,
+
+            walk_step: self.walk_step,
+            speed: self.speed,
+            jump_count: self.jump_count,
+            jump_step: self.jump_step,
+            jump_heights: self.jump_heights,
+            jump_ease: self.jump_ease,
+            jump_every: 45,
+            edge: self.edge,
+            mirror_on_wrap: self.mirror_on_wrap,
+            edge_pause_ms: self.edge_pause_ms,
+            easing: self.easing,
+            edge_wait_ms: None,
+            hop_only: self.hop_only,
+            jump_frames: self.jump_frames,
+            pinned: self.pinned,
+            still: self.still,
+            pulse: self.pulse,
+            accelerate: self.accelerate,
+            groove: 1.0,
+            flip_threshold: self.flip_threshold as f64,
+            dig_every: (self.dig_every_secs).map(|secs| secs as u64 * 1000 / TICK_MS as u64),
+            dig_frames,
+            dig: None,
+
+            frames,
+            frames_flipped,
+            frame_index: 0,
+            frame_options: self.frame_options,
+
+            size,
+            anchor,
+
+            events: Vec::new(),
+
+            center_pending: self.center_start,
+            center_start: self.center_start,
+            start_x: None,
+            rng: self.rng.unwrap_or_else(StdRng::from_entropy),
+
+            target: None,
+        };
+        animation.set_area(self.area);
+        Ok(animation)
+    }
+}
+
+impl Animation {
+    pub(crate) fn builder() -> AnimationBuilder {
+        AnimationBuilder {
+            frames: Vec::new(),
+            area: (0, 0),
+            walk_step: 15,
+            speed: 1.0,
+            jump_step: 15,
+            jump_count: 6,
+            jump_heights: None,
+            jump_ease: JumpEase::default(),
+            edge: EdgeMode::Bounce,
+            mirror_on_wrap: false,
+            edge_pause_ms: 0,
+            easing: None,
+            center_start: false,
+            rng: None,
+            frame_options: FrameOptions {
+                threads: 1,
+                flip: true,
+                levels: (1.0, 1.0),
+                palette: None,
+                outline: None,
+            },
+            hop_only: false,
+            jump_frames: (None, None),
+            pinned: None,
+            still: false,
+            pulse: None,
+            accelerate: None,
+            flip_threshold: 0,
+            dig_every_secs: None,
+            dig_frames: (Vec::new(), None),
+        }
+    }
+
+    pub(crate) fn set_area(&mut self, area: (u64, u64)) {
+        self.area = area;
+
+        if area.0 == 0 {
+            return;
+        }
+        let right = area.0.saturating_sub(self.size.0 as u64) as f64;
+        if let Some(x) = self.start_x.take() {
+            self.x = x.clamp(0.0, right);
+            self.center_pending = false;
+        }
+        if self.center_pending {
+            self.x = right / 2.0;
+            self.forward = self.rng.gen_bool(0.5);
+            self.facing_forward = self.forward;
+            self.center_pending = false;
+        }
+    }
+
+    pub(crate) fn area(&self) -> (u64, u64) {
+        self.area
+    }
+
+    /// Starts out at `x` rather than the left edge or the middle, once the
+    /// area is known.
+    pub(crate) fn start_at(&mut self, x: f64) {
+        self.start_x = Some(x);
+    }
+
+    /// Goes back to the start, at `x` if given, and walking the way it
+    /// first did, with whatever it was doing forgotten. Takes effect with
+    /// the next `set_area`.
+    pub(crate) fn restart(&mut self, x: Option<f64>) {
+        let forward = self.pinned.is_none_or(Corner::is_left);
+        self.x = 0.0;
+        self.y = 0;
+        self.jump = JumpState::NotJumping;
+        self.forward = forward;
+        self.facing_forward = forward;
+        self.edge_wait_ms = None;
+        self.target = None;
+        self.dig = None;
+        self.center_pending = self.center_start;
+        self.start_x = x;
+    }
+
+    /// Position of the buffer's top-left corner, which may fall between
+    /// pixels; it's up to the caller how to place that on the screen.
+    pub(crate) fn position(&self) -> (f64, f64) {
+        if let Some(corner) = self.pinned {
+            let right = self.area.0.saturating_sub(self.size.0 as u64);
+            let bottom = self.area.1.saturating_sub(self.size.1 as u64);
+            return (
+                if corner.is_left() { 0.0 } else { right as f64 },
+                if corner.is_top() { 0.0 } else { bottom as f64 },
+            );
+        }
+        // An area shorter than the gopher (or its jump) clips it at the top.
+        let y = self.area.1.saturating_sub(self.size.1 as u64 + self.y);
+        (self.x, y as _)
+    }
+
+    /// How far above the ground the top of the highest jump is.
+    pub(crate) fn jump_height(&self) -> u64 {
+        self.jump_heights
+            .map_or(self.jump_step * self.jump_count, |(_, max)| max)
+    }
+
+    /// Starts a jump of a height picked from the range.
+    fn jump(&mut self) {
+        let limit = match self.jump_heights {
+            Some((min, max)) if min < max => self.rng.gen_range(min..=max),
+            Some((_, max)) => max,
+            None => self.jump_step * self.jump_count,
+        };
+        self.jump = JumpState::Ascending { y: 0, limit };
+    }
+
+    /// Swaps in new frames, prepared like the first ones were, without
+    /// moving the gopher or turning it around.
+    pub(crate) fn set_frames(&mut self, images: Vec<image::RgbaImage>) {
+        let (frames, frames_flipped) = self.frame_options.prepare(images);
+        let (size, anchor) = frame_layout(
+            (frames.iter().chain(frames_flipped.iter())).chain(self.dig_frames.iter()),
+        );
+        self.frame_index %= frames.len();
+        self.frames = frames;
+        self.frames_flipped = frames_flipped;
+        self.size = size;
+        self.anchor = anchor;
+
+        // A wider gopher may no longer fit where it stood.
+        if self.edge == EdgeMode::Bounce && self.area.0 > 0 {
+            let right = self.area.0.saturating_sub(size.0 as u64) as f64;
+            self.x = self.x.min(right);
+        }
+    }
+
+    pub(crate) fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub(crate) fn walk_step(&self) -> u64 {
+        self.walk_step
+    }
+
+    /// Dimensions of the buffer every frame is drawn into.
+    pub(crate) fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Where the current frame's top-left corner goes within the buffer.
+    pub(crate) fn frame_offset(&self) -> (u32, u32) {
+        let frame = self.frame();
+        (
+            self.anchor.0 - frame.anchor.0,
+            self.anchor.1 - frame.anchor.1,
+        )
+    }
+
+    /// The first frame facing right, for a gopher that isn't going anywhere.
+    pub(crate) fn still_frame(&self) -> &Frame {
+        &self.frames[0]
+    }
+
+    /// How much bigger or smaller than `size` the gopher is shown right
+    /// now, going along a sine from 1 with the ticks so far.
+    pub(crate) fn pulse_scale(&self) -> f64 {
+        let Some(Pulse {
+            amplitude,
+            period_ms,
+        }) = self.pulse
+        else {
+            return 1.0;
+        };
+        let elapsed = (self.count * TICK_MS as u64) % period_ms as u64;
+        1.0 + amplitude * (TAU * elapsed as f64 / period_ms as f64).sin()
+    }
+
+    /// Which of the frames is up, counting the same both ways.
+    pub(crate) fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    pub(crate) fn forward(&self) -> bool {
+        self.forward
+    }
+
+    pub(crate) fn frame(&self) -> &Frame {
+        let ((frames, frames_flipped), index) = match self.dig {
+            Some(Dig::Down(ticks)) => {
+                let (frames, flipped) = &self.dig_frames.down;
+                ((frames, flipped), ticks / DIG_FRAME_TICKS)
+            }
+            Some(Dig::Up(ticks)) => {
+                let (frames, flipped) = &self.dig_frames.up;
+                ((frames, flipped), ticks / DIG_FRAME_TICKS)
+            }
+            _ => ((&self.frames, &self.frames_flipped), self.frame_index),
+        };
+        // Without flipped frames (--no-flip) both directions look the same.
+        if self.facing_forward || frames_flipped.is_empty() {
+            &frames[index]
+        } else {
+            &frames_flipped[index]
+        }
+    }
+
+    /// Whether the gopher has dug down out of sight.
+    pub(crate) fn hidden(&self) -> bool {
+        matches!(self.dig, Some(Dig::Hidden(_)))
+    }
+
+    /// Advances the animation by `dt_ms` milliseconds, carrying any time
+    /// left over after the last whole tick into the next call.
+    pub(crate) fn step(&mut self, dt_ms: u32) {
+        self.pending_ms += dt_ms;
+        while self.pending_ms >= TICK_MS {
+            self.pending_ms -= TICK_MS;
+            self.tick();
+        }
+    }
+
+    /// Advances the animation by exactly one tick.
+    pub(crate) fn next(&mut self) {
+        self.step(TICK_MS);
+    }
+
+    /// Goes back to the frame before this one, staying where it is. Ticks
+    /// move it along too many ways for them to be undone.
+    pub(crate) fn previous_frame(&mut self) {
+        let count = self.frames.len();
+        self.frame_index = (self.frame_index + count - 1) % count;
+    }
+
+    /// Moves the point the gopher walks towards by `dx`, starting from
+    /// where it stands if it wasn't chasing anything yet.
+    pub(crate) fn chase(&mut self, dx: f64) {
+        let half = self.size.0 as f64 / 2.0;
+        let target = self.target.unwrap_or(self.x + half) + dx;
+        // Staying a pixel clear of the edges keeps it from bouncing off them.
+        let (min, max) = (half + 1.0, self.area.0 as f64 - half - 1.0);
+        self.target = Some(target.min(max).max(min));
+    }
+
+    /// Goes back to walking on its own.
+    pub(crate) fn stop_chasing(&mut self) {
+        self.target = None;
+    }
+
+    /// Starts a hop, which carries the gopher forward along the arc of a
+    /// jump. Does nothing until the last jump has landed.
+    pub(crate) fn hop(&mut self) {
+        if matches!(self.jump, JumpState::NotJumping) {
+            self.jump();
+        }
+    }
+
+    /// Lands the gopher at a random spot in the area, cutting short any
+    /// jump it was in.
+    pub(crate) fn teleport(&mut self) {
+        let right = self.area.0.saturating_sub(self.size.0 as u64);
+        self.x = self.rng.gen_range(0..=right) as f64;
+        self.y = 0;
+        self.jump = JumpState::NotJumping;
+        self.edge_wait_ms = None;
+    }
+
+    /// Where the gopher is and what it's doing, for `--debug-overlay`: its
+    /// x, its height above the ground, the frame, which way it's walking
+    /// and how far into a jump it is.
+    pub(crate) fn describe(&self) -> String {
+        let jump = match self.jump {
+            JumpState::NotJumping => "not jumping".to_owned(),
+            JumpState::Ascending { y, limit } => format!("ascending {}/{}", y, limit),
+            JumpState::Descending { y, limit } => format!("descending {}/{}", y, limit),
+        };
+        format!(
+            "({:.1}, {}) frame {}/{} {} {}",
+            self.x,
+            self.y,
+            self.frame_index,
+            self.frames.len(),
+            if self.forward { "forward" } else { "backward" },
+            jump
+        )
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<AnimationEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn tick(&mut self) {
+        self.count += 1;
+        let forward = self.forward;
+
+        if self.pinned.is_some() {
+            if !self.still {
+                self.frame_index = (self.frame_index + 1) % self.frames.len();
+            }
+            return;
+        }
+
+        if let Some(dig) = self.dig {
+            self.dig_tick(dig);
+            return;
+        }
+
+        let was_jumping = !matches!(self.jump, JumpState::NotJumping);
+        self.jump.next(self.jump_step, self.jump_ease);
+        if was_jumping && matches!(self.jump, JumpState::NotJumping) {
+            self.events.push(AnimationEvent::Landed);
+            self.groove = 1.0;
+        }
+
+        // Standing at an edge, on an idle frame, while any jump that got it
+        // there comes down.
+        if let Some(left) = self.edge_wait_ms {
+            if let JumpState::Ascending { y, .. } | JumpState::Descending { y, .. } = self.jump {
+                self.y = y;
+            }
+            self.frame_index = 0;
+            self.edge_wait_ms = left.checked_sub(TICK_MS).filter(|&left| left > 0);
+            if self.edge_wait_ms.is_none() {
+                self.forward = !self.forward;
+                self.groove = 1.0;
+            }
+            return;
+        }
+
+        let walk_step = match self.jump {
+            JumpState::Ascending { y, .. } | JumpState::Descending { y, .. } => {
+                self.y = y;
+                let frame = match self.jump {
+                    JumpState::Ascending { .. } => self.jump_frames.0,
+                    _ => self.jump_frames.1,
+                };
+                self.frame_index = frame.filter(|&i| i < self.frames.len()).unwrap_or(0);
+                self.walk_step / 2
+            }
+            JumpState::NotJumping if self.hop_only => {
+                // Off whichever jump frame it landed on.
+                self.frame_index = 0;
+                0
+            }
+            JumpState::NotJumping => {
+                if (self.dig_every).is_some_and(|every| self.count.is_multiple_of(every)) {
+                    self.dig = Some(match self.dig_frames.down.0.is_empty() {
+                        true => Dig::Hidden(DIG_HIDDEN_MS),
+                        false => Dig::Down(0),
+                    });
+                    return;
+                }
+                self.frame_index = (self.frame_index + 1) % self.frames.len();
+
+                if self.count.is_multiple_of(self.jump_every) {
+                    self.jump();
+                }
+                if let Some(Accelerate { rate, max }) = self.accelerate {
+                    // Whole ticks keep this the same at any frame rate.
+                    self.groove = (self.groove + rate * TICK_MS as f64 / 1000.0).min(max);
+                }
+
+                self.walk_step
+            }
+        };
+
+        let mut step = walk_step as f64 * self.speed * self.groove * self.edge_ease();
+        if let Some(target) = self.target {
+            let distance = target - (self.x + self.size.0 as f64 / 2.0);
+            if distance.abs() > self.flip_threshold {
+                self.forward = distance > 0.0;
+            }
+            // Short of turning around, wait for the target to come back.
+            step = match (distance > 0.0) == self.forward {
+                true => step.min(distance.abs()),
+                false => 0.0,
+            };
+        }
+
+        let delta = self.walk(step);
+        self.face(delta);
+        if self.forward != forward {
+            self.groove = 1.0;
+        }
+    }
+
+    /// Moves digging down, waiting out of sight and popping up along by a
+    /// tick, back to walking once it's all the way up.
+    fn dig_tick(&mut self, dig: Dig) {
+        let (down, up) = (self.dig_frames.down.0.len(), self.dig_frames.up.0.len());
+        self.dig = match dig {
+            Dig::Down(ticks) if ticks + 1 < down * DIG_FRAME_TICKS => Some(Dig::Down(ticks + 1)),
+            Dig::Down(_) => Some(Dig::Hidden(DIG_HIDDEN_MS)),
+            Dig::Hidden(left) if left > TICK_MS => Some(Dig::Hidden(left - TICK_MS)),
+            Dig::Hidden(_) => {
+                self.teleport();
+                (up > 0).then_some(Dig::Up(0))
+            }
+            Dig::Up(ticks) if ticks + 1 < up * DIG_FRAME_TICKS => Some(Dig::Up(ticks + 1)),
+            Dig::Up(_) => None,
+        };
+        if self.dig.is_none() {
+            self.groove = 1.0;
+        }
+    }
+
+    /// The share of its speed the gopher walks at this close to an edge.
+    fn edge_ease(&self) -> f64 {
+        let Some((easing, distance)) = self.easing.filter(|_| self.edge == EdgeMode::Bounce) else {
+            return 1.0;
+        };
+        let right = self.area.0 as f64 - self.size.0 as f64;
+        let to_edge = self.x.min(right - self.x).max(0.0);
+        MIN_EASE + (1.0 - MIN_EASE) * easing.apply(to_edge / distance as f64)
+    }
+
+    /// Turns the sprite towards `delta`, the horizontal distance walked in
+    /// the last tick. Standing still keeps the old facing.
+    fn face(&mut self, delta: f64) {
+        if delta > 0.0 {
+            self.facing_forward = true;
+        } else if delta < 0.0 {
+            self.facing_forward = false;
+        }
+    }
+
+    /// Turns around, or with `edge_pause_ms` starts waiting to. Chasing the
+    /// pointer doesn't stop to look around.
+    fn turn_at_edge(&mut self) {
+        if self.edge_pause_ms > 0 && self.target.is_none() {
+            self.edge_wait_ms = Some(self.edge_pause_ms);
+        } else {
+            self.forward = !self.forward;
+        }
+        self.events.push(AnimationEvent::Bounced);
+    }
+
+    /// Moves `step` pixels in the current direction as the edge mode
+    /// allows, returning how far the gopher walked. Reappearing on the
+    /// other side doesn't count as walking.
+    fn walk(&mut self, step: f64) -> f64 {
+        let start = self.x;
+        let width = self.size.0 as f64;
+        let right = self.area.0 as f64 - width;
+
+        if self.forward {
+            self.x += step;
+        } else {
+            self.x -= step;
+        }
+
+        match self.edge {
+            EdgeMode::Bounce if self.forward && self.x >= right => {
+                self.x = right;
+                self.turn_at_edge();
+            }
+            EdgeMode::Bounce if !self.forward && self.x <= 0.0 => {
+                self.x = 0.0;
+                self.turn_at_edge();
+            }
+            // Once the gopher is entirely off-screen it either turns around
+            // where it is or reappears on the opposite side.
+            EdgeMode::Wrap if self.forward && self.x >= right + width => {
+                self.events.push(AnimationEvent::Crossed);
+                if self.mirror_on_wrap {
+                    self.forward = false;
+                } else {
+                    self.x = -width;
+                    return step;
+                }
+            }
+            EdgeMode::Wrap if !self.forward && self.x <= -width => {
+                self.events.push(AnimationEvent::Crossed);
+                if self.mirror_on_wrap {
+                    self.forward = true;
+                } else {
+                    self.x = right + width;
+                    return -step;
+                }
+            }
+            _ => {}
+        }
+
+        self.x - start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(area: (u64, u64), walk_step: u64) -> Animation {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area(area)
+            .walk_step(walk_step)
+            .build()
+            .unwrap();
+        animation.jump_every = u64::MAX;
+        animation
+    }
+
+    #[test]
+    fn fit_size_keeps_aspect_ratio() {
+        assert_eq!(fit_size((800, 600), 8192), (800, 600));
+        assert_eq!(fit_size((16384, 4096), 8192), (8192, 2048));
+        assert_eq!(fit_size((100, 10000), 1000), (10, 1000));
+    }
+
+    #[test]
+    fn builder_rejects_bad_parameters() {
+        let frames = vec![image::RgbaImage::new(10, 10); 3];
+        assert!(Animation::builder().build().is_err());
+        assert!(Animation::builder()
+            .frames(frames.clone())
+            .walk_step(0)
+            .build()
+            .is_err());
+        assert!(Animation::builder()
+            .frames(frames.clone())
+            .jump_count(0)
+            .build()
+            .is_err());
+        assert!(Animation::builder().frames(frames).build().is_ok());
+    }
+
+    #[test]
+    fn color_key_clears_matching_pixels() {
+        let mut image = image::RgbaImage::from_pixel(3, 1, image::Rgba([0xff, 0, 0xff, 0xff]));
+        image.put_pixel(1, 0, image::Rgba([0xf0, 0x08, 0xf8, 0xff]));
+        image.put_pixel(2, 0, image::Rgba([0x10, 0x20, 0x30, 0xff]));
+
+        apply_color_key(&mut image, [0xff, 0, 0xff], 0x10);
+
+        assert_eq!(image.get_pixel(0, 0).0, [0; 4]);
+        assert_eq!(image.get_pixel(1, 0).0, [0; 4]);
+        assert_eq!(image.get_pixel(2, 0).0, [0x10, 0x20, 0x30, 0xff]);
+    }
+
+    #[test]
+    fn color_key_comes_before_scaling() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("walk.png");
+        let image = image::RgbaImage::from_fn(4, 4, |x, _| match x {
+            0 | 1 => image::Rgba([0xff, 0, 0xff, 0xff]),
+            _ => image::Rgba([0x10, 0x20, 0x30, 0xff]),
+        });
+        image.save(&path).unwrap();
+        let config = Config {
+            frames: Some(path),
+            scale: 0.5,
+            color_key: Some(Color([0xff, 0, 0xff, 0xff])),
+            ..Config::default()
+        };
+
+        let images = load_images(&config, 0).unwrap();
+        assert_eq!(images[0].dimensions(), (2, 2));
+        // No magenta bled into what's left.
+        assert!(images[0].pixels().all(|p| p.0[0] <= 0x10 && p.0[2] <= 0x30));
+    }
+
+    #[test]
+    fn adjusts_brightness_then_gamma() {
+        let pixel = image::Rgba([0x40, 0x80, 0xff, 0x80]);
+        let image = image::RgbaImage::from_pixel(1, 1, pixel);
+        let levels = |brightness, gamma| {
+            let animation = Animation::builder()
+                .frames(vec![image.clone()])
+                .levels(brightness, gamma)
+                .build()
+                .unwrap();
+            animation.frames[0].image.get_pixel(0, 0).0
+        };
+
+        assert_eq!(levels(1.0, 1.0), pixel.0);
+        // Alpha stays, and brightness clips at white.
+        assert_eq!(levels(2.0, 1.0), [0x80, 0xff, 0xff, 0x80]);
+        // 0x40 is a quarter, which gamma 2 takes to a half.
+        assert_eq!(levels(1.0, 2.0)[0], 0x80);
+        assert_eq!(levels(0.5, 2.0)[0], 0x5a);
+    }
+
+    #[test]
+    fn outlines_the_silhouette() {
+        let mut image = image::RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, image::Rgba([10, 20, 30, 0xff]));
+        let outline = Outline {
+            color: Color([0xff, 0, 0, 0xff]),
+            width: 1,
+        };
+        let animation = Animation::builder()
+            .frames(vec![image])
+            .outline(Some(outline))
+            .build()
+            .unwrap();
+
+        let red = [0xff, 0, 0, 0xff];
+        let clear = [0; 4];
+        let pixels: Vec<_> = animation.frames[0].image.pixels().map(|p| p.0).collect();
+        #[rustfmt::skip]
+        assert_eq!(pixels, [
+            clear, red, clear, clear,
+            red, [10, 20, 30, 0xff], red, clear,
+            clear, red, clear, clear,
+        ]);
+        // The flipped frame was outlined before flipping.
+        assert_eq!(animation.frames_flipped[0].image.get_pixel(1, 1).0, red);
+    }
+
+    #[test]
+    fn quantizes_a_gradient_to_the_palette() {
+        let gradient = image::RgbaImage::from_fn(5, 1, |x, _| {
+            let v = (x * 60) as u8;
+            image::Rgba([v, v, v, 0x80 + x as u8])
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("palette.txt");
+        std::fs::write(&path, "000000, #808080\nffffff\n").unwrap();
+        let palette = load_palette(&path).unwrap();
+
+        let animation = Animation::builder()
+            .frames(vec![gradient])
+            .palette(Some(palette))
+            .build()
+            .unwrap();
+        let pixels: Vec<_> = animation.frames[0].image.pixels().map(|p| p.0).collect();
+        // 0, 60, 120, 180 and 240 go to the nearest of 0, 128 and 255.
+        assert_eq!(
+            pixels,
+            [
+                [0, 0, 0, 0x80],
+                [0, 0, 0, 0x81],
+                [0x80, 0x80, 0x80, 0x82],
+                [0x80, 0x80, 0x80, 0x83],
+                [0xff, 0xff, 0xff, 0x84],
+            ]
+        );
+        assert_eq!(
+            animation.frames_flipped[0].image.get_pixel(0, 0).0,
+            pixels[4]
+        );
+    }
+
+    #[test]
+    fn frames_of_different_sizes_keep_their_feet_in_place() {
+        let mut animation = Animation::builder()
+            .frames(vec![
+                image::RgbaImage::new(5, 6),
+                image::RgbaImage::new(8, 4),
+            ])
+            .build()
+            .unwrap();
+        // As wide as the widest and as tall as the tallest.
+        assert_eq!(animation.size(), (8, 6));
+        assert_eq!(animation.anchor, (4, 6));
+
+        let mut offsets = Vec::new();
+        for facing_forward in [true, false] {
+            animation.facing_forward = facing_forward;
+            for index in 0..2 {
+                animation.frame_index = index;
+                let (x, y) = animation.frame_offset();
+                let frame = animation.frame();
+                assert_eq!((x + frame.anchor.0, y + frame.anchor.1), (4, 6));
+                assert!(x + frame.image.width() <= 8 && y + frame.image.height() <= 6);
+                offsets.push((x, y));
+            }
+        }
+        // The odd width rounds the other way once flipped.
+        assert_eq!(offsets, [(2, 0), (0, 2), (1, 0), (0, 2)]);
+    }
+
+    #[test]
+    fn step_advances_by_elapsed_time() {
+        let mut animation = animation((10000, 100), 3);
+
+        animation.step(1000);
+        assert_eq!(animation.x, (1000 / TICK_MS * 3) as f64);
+
+        // 1000ms leaves 8ms over, so another 8ms completes one more tick.
+        animation.step(8);
+        assert_eq!(animation.x, (1000 / TICK_MS * 3 + 3) as f64);
+    }
+
+    #[test]
+    fn next_is_one_tick() {
+        let mut stepped = animation((10000, 100), 5);
+        let mut nexted = animation((10000, 100), 5);
+
+        for _ in 0..10 {
+            stepped.step(TICK_MS);
+            nexted.next();
+        }
+
+        assert_eq!(stepped.x, nexted.x);
+        assert_eq!(stepped.frame_index, nexted.frame_index);
+    }
+
+    #[test]
+    fn short_steps_accumulate() {
+        let mut animation = animation((10000, 100), 4);
+
+        for _ in 0..TICK_MS - 1 {
+            animation.step(1);
+        }
+        assert_eq!(animation.x, 0.0);
+
+        animation.step(1);
+        assert_eq!(animation.x, 4.0);
+    }
+
+    #[test]
+    fn reverses_at_boundary() {
+        let mut animation = animation((100, 100), 7);
+
+        animation.step(13 * TICK_MS);
+        assert_eq!(animation.x, 90.0);
+        assert!(!animation.forward);
+
+        animation.next();
+        assert_eq!(animation.x, 83.0);
+    }
+
+    #[test]
+    fn faces_the_way_it_walks_in_every_edge_mode() {
+        for (edge, mirror_on_wrap) in [
+            (EdgeMode::Bounce, false),
+            (EdgeMode::Wrap, false),
+            (EdgeMode::Wrap, true),
+        ] {
+            let mut animation = Animation {
+                edge,
+                mirror_on_wrap,
+                jump_every: 7,
+                ..animation((100, 100), 6)
+            };
+
+            let mut seen = [false; 2];
+            for _ in 0..200 {
+                let before = animation.x;
+                animation.next();
+                let delta = animation.x - before;
+                // Coming back in on the other side isn't walking.
+                if delta == 0.0 || delta.abs() > 6.0 {
+                    continue;
+                }
+
+                assert_eq!(
+                    animation.facing_forward,
+                    delta > 0.0,
+                    "{:?} (mirror {}) moved by {} facing the other way",
+                    edge,
+                    mirror_on_wrap,
+                    delta
+                );
+                seen[(delta > 0.0) as usize] = true;
+            }
+            assert!(seen[1]);
+            assert_eq!(seen[0], edge == EdgeMode::Bounce || mirror_on_wrap);
+        }
+    }
+
+    #[test]
+    fn no_flip_shows_forward_frames_both_ways() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((30, 100))
+            .walk_step(5)
+            .flip(false)
+            .build()
+            .unwrap();
+        assert!(animation.frames_flipped.is_empty());
+
+        animation.step(6 * TICK_MS);
+        assert!(!animation.facing_forward);
+        assert!(std::ptr::eq(
+            animation.frame(),
+            &animation.frames[animation.frame_index]
+        ));
+    }
+
+    #[test]
+    fn chases_the_target_and_stops_there() {
+        let mut animation = animation((100, 100), 4);
+
+        // The middle of the gopher starts at 5.
+        animation.chase(20.0);
+        animation.step(10 * TICK_MS);
+        assert_eq!(animation.x, 20.0);
+        assert!(animation.facing_forward);
+
+        // Targets past the edge stop just short of it.
+        animation.chase(-100.0);
+        animation.step(10 * TICK_MS);
+        assert_eq!(animation.x, 1.0);
+        assert!(!animation.facing_forward);
+        assert_eq!(animation.take_events(), vec![]);
+
+        // Then it walks on as before, into the edge.
+        animation.stop_chasing();
+        animation.next();
+        assert_eq!(animation.x, 0.0);
+        assert_eq!(animation.take_events(), vec![AnimationEvent::Bounced]);
+    }
+
+    #[test]
+    fn small_wobbles_of_the_target_dont_turn_it_around() {
+        let mut gopher = Animation {
+            flip_threshold: 3.0,
+            ..animation((100, 100), 4)
+        };
+        gopher.chase(20.0);
+        gopher.step(10 * TICK_MS);
+        assert_eq!(gopher.x, 20.0);
+
+        // Back and forth within the threshold it holds still, facing on.
+        for dx in [-2.0, 3.0, -3.0, 2.0, -2.0] {
+            gopher.chase(dx);
+            gopher.step(TICK_MS);
+            assert!(gopher.forward && gopher.facing_forward, "{}", dx);
+        }
+        assert_eq!(gopher.x, 21.0);
+
+        gopher.chase(-4.0);
+        gopher.step(TICK_MS);
+        assert!(!gopher.forward && !gopher.facing_forward);
+        assert_eq!(gopher.x, 17.0);
+    }
+
+    #[test]
+    fn reports_landing_and_bounce() {
+        let mut walker = animation((30, 100), 5);
+        walker.step(4 * TICK_MS);
+        assert_eq!(walker.take_events(), vec![AnimationEvent::Bounced]);
+
+        // Takes off on tick 3, peaks on tick 5 and touches down on tick 6.
+        let mut jumper = Animation {
+            jump_every: 3,
+            jump_step: 4,
+            jump_count: 1,
+            ..animation((10000, 100), 5)
+        };
+        jumper.step(5 * TICK_MS);
+        assert_eq!(jumper.take_events(), vec![]);
+        jumper.next();
+        assert_eq!(jumper.take_events(), vec![AnimationEvent::Landed]);
+    }
+
+    #[test]
+    fn center_start_waits_for_the_area() {
+        let mut animation = Animation {
+            center_pending: true,
+            ..animation((0, 0), 3)
+        };
+
+        animation.set_area((0, 0));
+        assert!(animation.center_pending);
+
+        animation.set_area((110, 100));
+        assert_eq!(animation.x, 50.0);
+        assert!(!animation.center_pending);
+
+        // Later resizes leave the position alone.
+        animation.x = 7.0;
+        animation.set_area((200, 100));
+        assert_eq!(animation.x, 7.0);
+    }
+
+    #[test]
+    fn restarts_where_it_started() {
+        let mut gopher = animation((200, 100), 3);
+        gopher.x = 120.0;
+        gopher.forward = false;
+        gopher.facing_forward = false;
+        gopher.restart(None);
+        gopher.set_area((150, 100));
+        assert_eq!(gopher.x, 0.0);
+        assert!(gopher.forward && gopher.facing_forward);
+
+        gopher.restart(Some(500.0));
+        gopher.set_area((100, 100));
+        assert_eq!(gopher.x, 100.0 - gopher.size.0 as f64);
+
+        let mut gopher = Animation {
+            center_start: true,
+            ..animation((110, 100), 3)
+        };
+        gopher.x = 3.0;
+        gopher.restart(None);
+        gopher.set_area((110, 100));
+        assert_eq!(gopher.x, 50.0);
+    }
+
+    #[test]
+    fn jumps_periodically() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((10000, 100))
+            .walk_step(2)
+            .jump_step(4)
+            .jump_count(3)
+            .build()
+            .unwrap();
+        animation.jump_every = 10;
+
+        animation.step(10 * TICK_MS);
+        assert!(matches!(animation.jump, JumpState::Ascending { y: 0, .. }));
+
+        animation.next();
+        assert_eq!(animation.y, 4);
+        assert_eq!(animation.frame_index, 0);
+    }
+
+    #[test]
+    fn jumps_reach_varied_heights_within_the_range() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((10000, 1000))
+            .jump_step(4)
+            .jump_heights((20, 60))
+            .rng(StdRng::seed_from_u64(1))
+            .build()
+            .unwrap();
+        animation.jump_every = 5;
+        assert_eq!(animation.jump_height(), 60);
+
+        let mut peaks = Vec::new();
+        for _ in 0..8 {
+            let mut peak = 0;
+            animation.step(5 * TICK_MS);
+            while !matches!(animation.jump, JumpState::NotJumping) {
+                peak = peak.max(animation.y);
+                animation.next();
+            }
+            peaks.push(peak);
+        }
+        assert!(
+            peaks.iter().all(|&peak| (20..=60).contains(&peak)),
+            "{:?}",
+            peaks
+        );
+        assert!(peaks.iter().any(|&peak| peak != peaks[0]), "{:?}", peaks);
+    }
+
+    #[test]
+    fn hops_only_when_asked() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((10000, 100))
+            .walk_step(2)
+            .jump_step(4)
+            .jump_count(3)
+            .hop_only(true)
+            .build()
+            .unwrap();
+        animation.jump_every = 10;
+
+        animation.step(20 * TICK_MS);
+        assert_eq!((animation.x, animation.y), (0.0, 0));
+
+        animation.hop();
+        animation.next();
+        assert_eq!((animation.x, animation.y), (1.0, 4));
+        // Asking again mid-air doesn't start over.
+        animation.hop();
+        animation.next();
+        assert_eq!(animation.y, 8);
+
+        animation.step(20 * TICK_MS);
+        assert_eq!(animation.take_events(), [AnimationEvent::Landed]);
+        let landed_at = animation.x;
+        animation.step(20 * TICK_MS);
+        assert_eq!((animation.x, animation.y), (landed_at, 0));
+    }
+
+    #[test]
+    fn jump_curves_rise_and_fall() {
+        let heights = |ease: JumpEase| {
+            let mut jump = JumpState::Ascending { y: 0, limit: 90 };
+            let mut heights = Vec::new();
+            while let JumpState::Ascending { y, .. } | JumpState::Descending { y, .. } = jump {
+                heights.push(y);
+                jump.next(15, ease);
+            }
+            heights
+        };
+
+        let linear = heights(JumpEase::Linear);
+        assert_eq!(linear, [0, 15, 30, 45, 60, 75, 90, 75, 60, 45, 30, 15, 0]);
+        // As it always has: a quarter step over the top 40%.
+        let ease_out = heights(JumpEase::EaseOut);
+        assert_eq!(
+            ease_out,
+            [
+                0, 15, 30, 45, 60, 63, 66, 69, 72, 75, 78, 81, 84, 87, 90, 75, 72, 69, 66, 63, 60,
+                57, 54, 51, 36, 21, 6, 0
+            ]
+        );
+
+        let parabolic = heights(JumpEase::Parabolic);
+        assert_eq!(parabolic.iter().max(), Some(&90));
+        let steps: Vec<i64> = (parabolic.windows(2))
+            .map(|pair| pair[1] as i64 - pair[0] as i64)
+            .collect();
+        let apex = parabolic.iter().position(|&y| y == 90).unwrap();
+        assert_eq!(apex, 6);
+        // Slower and slower on the way up, then faster and faster down.
+        assert!(steps[..apex].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(steps[apex..].windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(parabolic.last(), Some(&0));
+    }
+
+    #[test]
+    fn pulses_along_a_sine() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10)])
+            .area((1000, 100))
+            .pulse(Some(Pulse {
+                amplitude: 0.5,
+                period_ms: TICK_MS * 4,
+            }))
+            .build()
+            .unwrap();
+        let mut scales = Vec::new();
+        for _ in 0..5 {
+            scales.push(animation.pulse_scale());
+            animation.next();
+        }
+        let expected = [1.0, 1.5, 1.0, 0.5, 1.0];
+        for (scale, expected) in scales.iter().zip(expected) {
+            assert!((scale - expected).abs() < 1e-9, "{:?}", scales);
+        }
+    }
+
+    #[test]
+    fn accelerates_until_it_turns() {
+        let mut gopher = Animation {
+            accelerate: Some(Accelerate {
+                rate: 1000.0 / TICK_MS as f64 / 4.0,
+                max: 2.0,
+            }),
+            ..animation((1000, 100), 4)
+        };
+        let mut steps = Vec::new();
+        for _ in 0..6 {
+            let x = gopher.x;
+            gopher.next();
+            steps.push(gopher.x - x);
+        }
+        assert_eq!(steps, [5.0, 6.0, 7.0, 8.0, 8.0, 8.0]);
+
+        // Back to walking at its speed the other way.
+        gopher.x = 1000.0 - gopher.size.0 as f64 - 1.0;
+        gopher.next();
+        assert!(!gopher.forward);
+        assert_eq!(gopher.groove, 1.0);
+
+        // Stepping by several ticks at once gets just as far.
+        let mut by_frames = Animation {
+            accelerate: gopher.accelerate,
+            ..animation((1000, 100), 4)
+        };
+        by_frames.step(TICK_MS * 6);
+        assert_eq!(by_frames.x, steps.iter().sum::<f64>());
+    }
+
+    #[test]
+    fn eases_into_and_out_of_the_edges() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((410, 100))
+            .walk_step(10)
+            .ease_edges(Some((Easing::Linear, 100)))
+            .build()
+            .unwrap();
+        animation.jump_every = u64::MAX;
+
+        // Walk up to where it turns at the right edge.
+        let mut steps = Vec::new();
+        while animation.forward {
+            let x = animation.x;
+            animation.next();
+            steps.push(animation.x - x);
+        }
+        // Slowest next to the left edge it starts at, full speed in the
+        // middle and slowing down again for the right edge.
+        assert_eq!(steps[0], 10.0 * MIN_EASE);
+        let full = |step: f64| step > 9.99;
+        let top = steps.iter().position(|&step| full(step)).unwrap();
+        assert!(steps[..=top].windows(2).all(|w| w[0] < w[1]));
+        // Up to the last step, which stops short at the edge.
+        let slowing = top + steps[top..].iter().position(|&step| !full(step)).unwrap();
+        let last = steps.len() - 1;
+        assert!(steps[slowing..last].windows(2).all(|w| w[0] > w[1]));
+        // And speeding up again from it.
+        let x = animation.x;
+        animation.next();
+        assert_eq!(x - animation.x, 10.0 * MIN_EASE);
+        assert_eq!(Easing::Sine.apply(1.5), 1.0);
+    }
+
+    #[test]
+    fn digs_down_and_pops_up_somewhere_else() {
+        let mut gopher = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((1000, 100))
+            .walk_step(2)
+            .rng(StdRng::seed_from_u64(1))
+            .dig(
+                Some(1),
+                vec![image::RgbaImage::new(10, 4), image::RgbaImage::new(10, 2)],
+                None,
+            )
+            .build()
+            .unwrap();
+        gopher.jump_every = u64::MAX;
+
+        // A second in, it stops to dig through both frames, six ticks each.
+        gopher.step(62 * TICK_MS);
+        assert_eq!(gopher.dig, Some(Dig::Down(0)));
+        let x = gopher.x;
+        assert_eq!(gopher.frame().image.height(), 4);
+        gopher.step(6 * TICK_MS);
+        assert_eq!(gopher.frame().image.height(), 2);
+        gopher.step(6 * TICK_MS);
+        assert!(gopher.hidden());
+        assert_eq!(gopher.x, x);
+
+        // Then it comes up again the way it went down, somewhere else.
+        gopher.step(DIG_HIDDEN_MS);
+        assert_eq!(gopher.dig, Some(Dig::Up(0)));
+        assert_ne!(gopher.x, x);
+        assert_eq!(gopher.frame().image.height(), 2);
+        gopher.step(12 * TICK_MS);
+        assert_eq!(gopher.dig, None);
+        let x = gopher.x;
+        gopher.next();
+        assert_eq!(gopher.x, x + 2.0);
+    }
+
+    #[test]
+    fn shows_the_jump_frames_in_the_air() {
+        let builder = || {
+            Animation::builder()
+                .frames(vec![image::RgbaImage::new(10, 10); 3])
+                .area((10000, 100))
+                .walk_step(2)
+                .jump_step(4)
+                .jump_count(3)
+                .hop_only(true)
+        };
+        assert!(builder().jump_frames(Some(3), None).build().is_err());
+
+        let mut animation = builder().jump_frames(Some(1), Some(2)).build().unwrap();
+        animation.hop();
+        let mut shown = Vec::new();
+        while animation.take_events().is_empty() {
+            animation.next();
+            let up = matches!(animation.jump, JumpState::Ascending { .. });
+            shown.push((up, animation.frame_index));
+        }
+        // Landing shows where it stands again.
+        assert_eq!(shown.pop(), Some((false, 0)));
+        assert!(shown.contains(&(true, 1)) && shown.contains(&(false, 2)));
+        assert!(shown
+            .iter()
+            .all(|&(up, frame)| frame == if up { 1 } else { 2 }));
+    }
+
+    #[test]
+    fn teleports_to_the_ground_within_the_area() {
+        let mut animation = animation((40, 100), 2);
+        animation.jump_every = 1;
+        let mut spots = Vec::new();
+        for _ in 0..20 {
+            animation.next();
+            animation.teleport();
+            assert_eq!(animation.y, 0);
+            assert!(animation.x >= 0.0 && animation.x <= 30.0, "{}", animation.x);
+            spots.push(animation.x);
+        }
+        assert!(spots.iter().any(|&x| x != spots[0]), "{:?}", spots);
+    }
+
+    #[test]
+    fn pauses_at_the_edge_before_turning() {
+        let mut animation = Animation::builder()
+            .frames(vec![image::RgbaImage::new(10, 10); 3])
+            .area((40, 100))
+            .walk_step(10)
+            .edge_pause(TICK_MS * 3)
+            .build()
+            .unwrap();
+        animation.jump_every = u64::MAX;
+
+        for _ in 0..3 {
+            animation.next();
+        }
+        assert_eq!(animation.x, 30.0);
+        for _ in 0..3 {
+            animation.next();
+            assert_eq!((animation.x, animation.frame_index), (30.0, 0));
+        }
+        assert!(!animation.forward);
+        animation.next();
+        assert_eq!(animation.x, 20.0);
+    }
+
+    #[test]
+    fn stays_in_its_corner() {
+        let pinned = |corner, still| {
+            let mut animation = Animation::builder()
+                .frames(vec![image::RgbaImage::new(10, 20); 3])
+                .area((200, 100))
+                .pin(Some(corner), still)
+                .build()
+                .unwrap();
+            for _ in 0..100 {
+                animation.next();
+            }
+            (
+                animation.position(),
+                animation.facing_forward,
+                animation.frame_index,
+            )
+        };
+
+        assert_eq!(
+            pinned(Corner::BottomRight, false),
+            ((190.0, 80.0), false, 1)
+        );
+        assert_eq!(pinned(Corner::TopLeft, false), ((0.0, 0.0), true, 1));
+        assert_eq!(pinned(Corner::TopRight, true), ((190.0, 0.0), false, 0));
+    }
+
+    #[test]
+    fn describes_the_state_for_the_overlay() {
+        let mut animation = animation((40, 100), 2);
+        animation.x = 12.5;
+        animation.y = 6;
+        animation.forward = false;
+        animation.jump = JumpState::Ascending { y: 6, limit: 90 };
+        assert_eq!(
+            animation.describe(),
+            "(12.5, 6) frame 0/3 backward ascending 6/90"
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// What the parent surface shows behind the gopher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Background {
+    #[default]
+    None,
+    Solid,
+    Gradient,
+    Stripes,
+    Starfield,
+}
+
+impl Background {
+    /// Whether the background changes over time and so has to be redrawn
+    /// on every frame rather than once per resize.
+    pub fn is_animated(self) -> bool {
+        matches!(self, Background::Stripes | Background::Starfield)
+    }
+
+    /// Whether every pixel of the background drawn with `color` is fully
+    /// opaque, so the compositor needn't draw what's behind it.
+    pub fn is_opaque(self, color: [u8; 4]) -> bool {
+        match self {
+            Background::None => false,
+            Background::Starfield => true,
+            Background::Solid | Background::Gradient | Background::Stripes => color[3] == 0xff,
+        }
+    }
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Background::None),
+            "solid" => Ok(Background::Solid),
+            "gradient" => Ok(Background::Gradient),
+            "stripes" => Ok(Background::Stripes),
+            "starfield" => Ok(Background::Starfield),
+            _ => Err(format!("unknown background '{}'", s)),
+        }
+    }
+}
+
+const STRIPE_WIDTH: u32 = 32;
+/// Milliseconds it takes the stripes to scroll by one pixel.
+const STRIPE_SCROLL_MS: u32 = 20;
+/// Roughly one star per this many pixels.
+const STAR_DENSITY: u32 = 4000;
+
+/// Fills `buffer`, an ARGB8888 image of `size`, with `background` as it
+/// looks `time_ms` into the animation. `color` is straight RGBA.
+pub fn fill(
+    background: Background,
+    color: [u8; 4],
+    buffer: &mut [u8],
+    size: (u32, u32),
+    time_ms: u32,
+) {
+    let (width, height) = size;
+    let stride = (width * 4) as usize;
+
+    match background {
+        Background::None => buffer.fill(0),
+        Background::Solid => {
+            let pixel = to_argb(color, 1.0);
+            for dst in buffer.chunks_exact_mut(4) {
+                dst.copy_from_slice(&pixel);
+            }
+        }
+        Background::Gradient => {
+            for (y, row) in buffer.chunks_exact_mut(stride).enumerate() {
+                // Fade from the color at the top to half of it at the bottom.
+                let pixel = to_argb(color, 1.0 - 0.5 * y as f32 / height as f32);
+                for dst in row.chunks_exact_mut(4) {
+                    dst.copy_from_slice(&pixel);
+                }
+            }
+        }
+        Background::Stripes => {
+            let light = to_argb(color, 1.0);
+            let dark = to_argb(color, 0.7);
+            let offset = time_ms / STRIPE_SCROLL_MS;
+            for (y, row) in buffer.chunks_exact_mut(stride).enumerate() {
+                for (x, dst) in row.chunks_exact_mut(4).enumerate() {
+                    let band = (x as u32 + y as u32 + offset) / STRIPE_WIDTH;
+                    dst.copy_from_slice(if band.is_multiple_of(2) {
+                        &light
+                    } else {
+                        &dark
+                    });
+                }
+            }
+        }
+        Background::Starfield => {
+            for dst in buffer.chunks_exact_mut(4) {
+                dst.copy_from_slice(&[0, 0, 0, 0xff]);
+            }
+
+            // Every star is derived from its index, so the field needs no
+            // state of its own and stays put across resizes.
+            let stars = (width * height / STAR_DENSITY) as u64;
+            for i in 0..stars {
+                let hash = splitmix64(i);
+                let speed = (hash >> 48) % 3 + 1;
+                let x = ((hash % width as u64) + time_ms as u64 * speed / 40) % width as u64;
+                let y = (hash >> 16) % height as u64;
+                let level = 0x60 + ((hash >> 40) % 0xa0) as u8;
+                let at = (y as usize) * stride + (x as usize) * 4;
+                buffer[at..at + 4].copy_from_slice(&[level, level, level, 0xff]);
+            }
+        }
+    }
+}
+
+/// `image` scaled to cover `size`, with what sticks out cropped.
+pub fn cover(image: &image::RgbaImage, size: (u32, u32)) -> image::RgbaImage {
+    image::DynamicImage::ImageRgba8(image.clone())
+        .resize_to_fill(size.0, size.1, image::imageops::FilterType::Triangle)
+        .into_rgba8()
+}
+
+/// Draws `image`, straight RGBA and as big as `buffer`, over it as filled
+/// by `fill`.
+pub fn blit(buffer: &mut [u8], image: &image::RgbaImage) {
+    for (dst, src) in buffer.chunks_exact_mut(4).zip(image.pixels()) {
+        over(dst, to_argb(src.0, 1.0));
+    }
+}
+
+/// Draws a gopher's buffer, premultiplied ARGB8888 `stride` pixels wide
+/// with `size` pixels of frame `padding` pixels in from the top left, over
+/// `buffer`, an image of `buffer_size`, with the frame's corner at `at`.
+/// Whatever falls outside `buffer` is cut off.
+pub fn composite(
+    buffer: &mut [u8],
+    buffer_size: (u32, u32),
+    sprite: &[u8],
+    (stride, padding): (u32, u32),
+    size: (u32, u32),
+    at: (i64, i64),
+) {
+    let (width, height) = (buffer_size.0 as i64, buffer_size.1 as i64);
+    for y in 0..size.1 {
+        let dst_y = at.1 + y as i64;
+        if !(0..height).contains(&dst_y) {
+            continue;
+        }
+        for x in 0..size.0 {
+            let dst_x = at.0 + x as i64;
+            if !(0..width).contains(&dst_x) {
+                continue;
+            }
+            let src = (((y + padding) * stride + x + padding) * 4) as usize;
+            let dst = ((dst_y * width + dst_x) * 4) as usize;
+            let pixel = sprite[src..src + 4].try_into().unwrap();
+            over(&mut buffer[dst..dst + 4], pixel);
+        }
+    }
+}
+
+/// Draws `image`, straight RGBA, over `buffer` as filled by `fill` again
+/// and again, `spacing` surface pixels apart. `scale` is buffer pixels per
+/// surface pixel, for a background that's drawn smaller than the window.
+pub fn tile(
+    buffer: &mut [u8],
+    size: (u32, u32),
+    image: &image::RgbaImage,
+    spacing: u32,
+    scale: f64,
+) {
+    let (width, height) = size;
+    let cell = (image.width() + spacing, image.height() + spacing);
+    // Which pixel of the image, if any, covers each buffer column or row.
+    let source = |i: u32, len: u32, cell: u32| {
+        let at = ((i as f64 + 0.5) / scale) as u32 % cell;
+        (at < len).then_some(at)
+    };
+    let columns: Vec<_> = (0..width)
+        .map(|x| source(x, image.width(), cell.0))
+        .collect();
+
+    for y in 0..height {
+        let Some(src_y) = source(y, image.height(), cell.1) else {
+            continue;
+        };
+        for (x, src_x) in columns.iter().enumerate() {
+            let Some(src_x) = *src_x else {
+                continue;
+            };
+            let src = to_argb(image.get_pixel(src_x, src_y).0, 1.0);
+            let at = ((y * width) as usize + x) * 4;
+            over(&mut buffer[at..at + 4], src);
+        }
+    }
+}
+
+/// Draws a `--footprints` print over `buffer`, an image of `size`: a dark
+/// oval with radii `radius` centered on `at`, `alpha` of the way opaque.
+pub fn footprint(
+    buffer: &mut [u8],
+    size: (u32, u32),
+    at: (f64, f64),
+    radius: (f64, f64),
+    alpha: f32,
+) {
+    let src = to_argb([0x3a, 0x2a, 0x1a, (0x70 as f32 * alpha).round() as u8], 1.0);
+    let rows = (at.1 - radius.1).floor().max(0.0) as u32..(at.1 + radius.1).ceil().max(0.0) as u32;
+    let columns =
+        (at.0 - radius.0).floor().max(0.0) as u32..(at.0 + radius.0).ceil().max(0.0) as u32;
+    for y in rows.take_while(|&y| y < size.1) {
+        for x in columns.clone().take_while(|&x| x < size.0) {
+            // Whether the pixel's center is inside the oval.
+            let dx = (x as f64 + 0.5 - at.0) / radius.0;
+            let dy = (y as f64 + 0.5 - at.1) / radius.1;
+            if dx * dx + dy * dy <= 1.0 {
+                let at = ((y * size.0 + x) * 4) as usize;
+                over(&mut buffer[at..at + 4], src);
+            }
+        }
+    }
+}
+
+/// Blends the premultiplied ARGB8888 pixel `src` over `dst`.
+fn over(dst: &mut [u8], src: [u8; 4]) {
+    let rest = 1.0 - src[3] as f32 / 255.0;
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = s + (*d as f32 * rest).round() as u8;
+    }
+}
+
+/// Converts straight RGBA scaled by `brightness` into premultiplied ARGB8888
+/// byte order.
+fn to_argb([r, g, b, a]: [u8; 4], brightness: f32) -> [u8; 4] {
+    let scale = brightness * a as f32 / 255.0;
+    let channel = |c: u8| (c as f32 * scale).round() as u8;
+    [channel(b), channel(g), channel(r), a]
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composites_the_sprite_cut_off_at_the_edges() {
+        let mut buffer = vec![0x10; 3 * 2 * 4];
+        // A 2x2 frame in a buffer with a pixel of padding, half see-through
+        // on the right.
+        let mut sprite = vec![0; 3 * 3 * 4];
+        for (x, y, pixel) in [
+            (1, 1, [0xff; 4]),
+            (2, 1, [0x80; 4]),
+            (1, 2, [0xff; 4]),
+            (2, 2, [0x80; 4]),
+        ] {
+            let at = (y * 3 + x) * 4;
+            sprite[at..at + 4].copy_from_slice(&pixel);
+        }
+        composite(&mut buffer, (3, 2), &sprite, (3, 1), (2, 2), (2, 1));
+
+        let pixel = |buffer: &[u8], x: usize, y: usize| buffer[(y * 3 + x) * 4..][..4].to_vec();
+        assert_eq!(pixel(&buffer, 2, 1), [0xff; 4]);
+        assert_eq!(pixel(&buffer, 1, 1), [0x10; 4]);
+        assert_eq!(pixel(&buffer, 2, 0), [0x10; 4]);
+
+        composite(&mut buffer, (3, 2), &sprite, (3, 1), (2, 2), (-1, 0));
+        assert_eq!(pixel(&buffer, 0, 0), [0x88; 4]);
+    }
+
+    #[test]
+    fn footprints_are_ovals_cut_off_at_the_edges() {
+        let size = (6, 3);
+        let mut buffer = vec![0; (size.0 * size.1 * 4) as usize];
+        footprint(&mut buffer, size, (1.0, 2.0), (2.0, 1.0), 1.0);
+
+        let drawn: Vec<_> = (buffer.chunks_exact(4))
+            .map(|pixel| pixel[3] != 0)
+            .collect();
+        let (x, o) = (true, false);
+        #[rustfmt::skip]
+        assert_eq!(drawn, [
+            o, o, o, o, o, o,
+            x, x, x, o, o, o,
+            x, x, x, o, o, o,
+        ]);
+        assert_eq!(&buffer[(6 + 1) * 4..][..4], [0x0b, 0x12, 0x19, 0x70]);
+    }
+
+    #[test]
+    fn solid_is_premultiplied_argb() {
+        let mut buffer = vec![0; 2 * 2 * 4];
+        fill(
+            Background::Solid,
+            [0xff, 0x80, 0x00, 0x80],
+            &mut buffer,
+            (2, 2),
+            0,
+        );
+
+        assert_eq!(&buffer[0..4], &[0x00, 0x40, 0x80, 0x80]);
+        assert!(buffer.chunks_exact(4).all(|p| p == &buffer[0..4]));
+    }
+
+    #[test]
+    fn tiles_repeat_with_gaps_between() {
+        let size = (5, 1);
+        let mut buffer = vec![0; (size.0 * 4) as usize];
+        fill(Background::Solid, [0, 0, 0xff, 0xff], &mut buffer, size, 0);
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([0xff, 0, 0, 0xff]));
+
+        tile(&mut buffer, size, &image, 1, 1.0);
+        let red = [0, 0, 0xff, 0xff];
+        let blue = [0xff, 0, 0, 0xff];
+        let pixels: Vec<_> = buffer.chunks_exact(4).collect();
+        assert_eq!(pixels, [red, blue, red, blue, red]);
+    }
+
+    #[test]
+    fn blits_the_image_scaled_to_cover() {
+        let size = (4, 2);
+        let mut buffer = vec![0; (size.0 * size.1 * 4) as usize];
+        fill(Background::Solid, [0, 0, 0xff, 0xff], &mut buffer, size, 0);
+        // Twice as tall as the buffer would have it, so it's cropped top
+        // and bottom rather than squashed.
+        let image = image::RgbaImage::from_pixel(1, 2, image::Rgba([0xff, 0, 0, 0x80]));
+
+        let image = cover(&image, size);
+        assert_eq!(image.dimensions(), size);
+        blit(&mut buffer, &image);
+        assert!(buffer.chunks_exact(4).all(|p| p == [0x7f, 0, 0x80, 0xff]));
+    }
+
+    #[test]
+    fn stripes_scroll_over_time() {
+        let size = (STRIPE_WIDTH * 2, 1);
+        let mut before = vec![0; (size.0 * 4) as usize];
+        let mut after = before.clone();
+
+        fill(Background::Stripes, [0xff; 4], &mut before, size, 0);
+        fill(
+            Background::Stripes,
+            [0xff; 4],
+            &mut after,
+            size,
+            STRIPE_SCROLL_MS,
+        );
+
+        // Everything moves one pixel to the left.
+        assert_eq!(&after[..after.len() - 4], &before[4..]);
+    }
+}
@@ -0,0 +1,2127 @@
+use std::{
+    error::Error,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{animation::pick_walk_step, keys};
+
+pub use crate::{
+    background::Background,
+    quirks::{Compositor, QuirksMode},
+};
+
+pub const USAGE: &str = "\
+Usage: wlgopher [OPTIONS]
+       wlgopher bench [--frames N] [OPTIONS]
+
+The bench subcommand draws N frames (default 1000) into memory without a
+compositor and prints the frame count, the seconds it took and the frames
+per second, space-separated.
+
+Options:
+  --frames PATH       Load the animation frames from a directory of images,
+                      a pattern such as 'walk_*.png' matching them in order,
+                      an animated GIF or a single image
+  --skin PATH         Another set of frames, loaded like --frames, for
+                      --random-skin-per-crossing or the N key to switch to;
+                      may be repeated
+  --random-skin-per-crossing
+                      Switch to a random other --skin (or back to the first
+                      frames) each time the gopher turns at or wraps around
+                      an edge
+  --scene FILE        Set up the frames, background, any number of gophers
+                      and the keys from a TOML or JSON file; the other
+                      options override it, wherever they are
+  --config FILE       Start from the options a --dump-config wrote to FILE;
+                      like --scene, the other options override it
+  --watch             Load the frames again whenever they change on disk, for
+                      seeing edits to them without restarting
+  --max-frames N      Load at most N frames from --frames (0 means no limit)
+  --load-threads N    Decode the frames on up to N threads (default 0, one per
+                      CPU)
+  --color-key COLOR   Make pixels of this RRGGBB color transparent, for frames
+                      without an alpha channel
+  --color-key-tolerance N
+                      Also key out colors up to N off on each channel
+                      (default 0)
+  --brightness F      Multiply the frames' colors by F (default 1.0)
+  --gamma F           Apply gamma F to the frames' colors after --brightness;
+                      above 1.0 lightens them (default 1.0)
+  --palette FILE      Cut the frames' colors down to the nearest of the RRGGBB
+                      colors listed in FILE, after --brightness and --gamma
+  --outline COLOR[:W] Draw a W pixel wide (default 1) RRGGBB or RRGGBBAA border
+                      around the gopher, to stand out on busy backgrounds
+  --pulse AMPLITUDE,PERIOD
+                      Grow and shrink by up to AMPLITUDE (0 to 1) of the size,
+                      and back every PERIOD milliseconds, keeping the feet
+                      where they are; takes a compositor with wp_viewporter
+  --no-flip           Don't mirror the frames when walking left, for symmetric
+                      art
+  --walk-step N       Walk N pixels per tick instead of a random amount
+  --walk-step-min N   Smallest randomly chosen walk step (default 2)
+  --walk-step-max N   Largest randomly chosen walk step (default 29)
+  --speed F           Walk F times the walk step per tick (default 1.0)
+  --jump-height-min N Lowest a jump goes, in pixels; each jump picks a random
+                      height between this and --jump-height-max (default 90)
+  --jump-height-max N Highest a jump goes, in pixels (default 90)
+  --jump-up-frame N   Show frame N, counting from 0, on the way up a jump
+                      instead of the first one
+  --jump-down-frame N Show frame N on the way down a jump
+  --jump-ease CURVE   How a jump rises and falls: linear, ease-out (the
+                      default, slowing down near the top) or parabolic
+  --dig-every SECONDS Every SECONDS, stop and dig down out of sight, then pop
+                      up somewhere else a couple of seconds later
+  --dig-frames PATH   Frames to show digging down, loaded like --frames;
+                      without them the gopher just vanishes
+  --popup-frames PATH Frames to show popping back up (default the
+                      --dig-frames backwards)
+  --center-start      Start in the middle of the screen facing a random way
+  --reset-position-on-resize
+                      Go back to the start, walking the way the gopher first
+                      did, whenever the window changes size, instead of
+                      staying put as far as the new edges allow
+  --seed N            Seed for everything random, for reproducible runs
+  --vary              Give every gopher a random tint and size, unless the
+                      --scene sets them
+  --edge MODE         What the gopher does at the screen edge: bounce (default)
+                      or wrap around to the other side
+  --edge-pause-ms MS  Stand at the screen edge for MS milliseconds before
+                      turning around (default 0; ignored with --edge wrap,
+                      where the edge is off-screen)
+  --mirror-on-wrap    Turn around off-screen on wrap instead of re-entering
+                      from the other side (requires --edge wrap)
+  --ease-edges        Slow down on the way into an edge and speed up on the
+                      way out rather than walking at the same pace throughout
+                      (ignored with --edge wrap)
+  --ease-distance PX  How far from an edge --ease-edges starts slowing down
+                      (default 120)
+  --ease-curve CURVE  How --ease-edges slows down: linear, quad or sine
+                      (default)
+  --accelerate RATE,MAX
+                      Walk faster the longer the gopher keeps going one way,
+                      by RATE times its speed more every second up to MAX
+                      times it, back to its speed after turning or a jump
+  --bg MODE           Background behind the gopher: none (default), solid,
+                      gradient, stripes or starfield
+  --bg-color COLOR    Base color for --bg as RRGGBB or RRGGBBAA
+                      (default 4080c0)
+  --background-image FILE
+                      Show an image behind the gopher, over any --bg, scaled
+                      to cover the window and cropped to it
+  --tile-gophers SPACING
+                      Cover the background with still gophers SPACING pixels
+                      apart, behind the walking ones
+  --footprints        Leave prints on the ground where the gophers walk,
+                      fading away after a few seconds
+  --no-opaque-region  Don't tell the compositor an opaque --bg hides what's
+                      behind the window
+  --windowed          Open a normal window instead of going fullscreen; the F
+                      key switches between the two either way
+  --fullscreen-on-focused
+                      Go fullscreen on the output the window first shows up
+                      on, which is usually the focused one, rather than
+                      leaving the pick to the compositor; the window is
+                      mapped as a window until then
+  --follow-active-window
+                      While fullscreen, move to whichever output the focused
+                      window is on whenever the focus changes (needs
+                      zwlr_foreign_toplevel_manager_v1)
+  --all-outputs       Go fullscreen on every output at once, with gophers of
+                      their own walking on each, including outputs plugged
+                      in later
+  --wayland-display NAME
+                      Connect to the compositor on socket NAME, in
+                      $XDG_RUNTIME_DIR unless it's a full path, instead of
+                      $WAYLAND_DISPLAY's
+  --parent HANDLE     Open as a transient child of the window another client
+                      exported as HANDLE through xdg-foreign; other windows
+                      can't be referred to
+  --remember          Start where the gopher was last time and, with
+                      --windowed, ask for the same window size
+  --min-size WxH      Smallest size the window may be resized to (default: the
+                      gopher plus --size-margin, with room to jump)
+  --max-size WxH      Largest size the window may be resized to (default:
+                      --max-buffer-dim in both directions)
+  --size-margin N     Pixels added around the gopher for the default
+                      --min-size (default 16)
+  --max-buffer-dim N  Largest width or height of any buffer handed to the
+                      compositor; bigger ones are scaled down (default 8192)
+  --anchor CORNER     Don't walk, just stand in the top-left, top-right,
+                      bottom-left or bottom-right corner, facing inward
+  --no-walk           With --anchor, hold the first frame instead of going
+                      through the walk cycle in place
+  --region X,Y,WxH    Keep the gopher walking within this rectangle of the
+                      window rather than across all of it, cut down to fit
+  --keep-aspect       When the window is too small for the gopher, shrink it
+                      evenly and center it instead of squashing it to fit
+  --transform ROT     Treat the display as rotated by normal, 90, 180 or 270
+                      degrees (default normal), for compositors that don't
+                      turn the surface upright on a rotated output
+  --mirror-output     Mirror everything left to right, for projecting through
+                      a mirror; the compositor flips the buffers, so it costs
+                      nothing to draw
+  --render-scale F    Draw at F (0 < F <= 1) times the window's resolution and
+                      let wp_viewporter scale it up, on top of any upscaling
+                      the compositor already does for HiDPI outputs
+  --subpixel          Position the gopher at fractional pixel offsets where
+                      the compositor supports wp_viewporter
+  --focus-react       Walk faster while the window is focused and slower
+                      while it isn't
+  --pause-unfocused   Stop animating while the window isn't focused
+  --pause-when-idle SECS
+                      Stop animating once there's been no input for SECS
+                      seconds, by when the display is likely to be off,
+                      until there is again (needs ext_idle_notifier_v1)
+  --bounce-sound FILE Play a WAV file whenever the gopher lands or bounces
+                      off an edge (needs the 'audio' build feature)
+  --quit-key NAME     Quit on the key with this keysym name in the keyboard
+                      layout (default Escape)
+  --pause-key NAME    Pause and resume on this key (default space); while
+                      paused, '.' steps forward a tick and ',' back a frame
+  --start-paused      Start out paused, standing still until the pause key
+  --cheat-code KEYS   Turn the gopher into a fast rainbow one and back when
+                      these comma-separated keys are pressed in a row (default
+                      up,up,down,down,left,right,left,right,b,a)
+  --no-cheat-code     Don't listen for the --cheat-code
+  --hop-on-key        Only move by hopping forward, one hop per key press (any
+                      key that doesn't do something else)
+  --lock-pointer      Lock the pointer to the window and have the gopher chase
+                      its motion; ESC frees it and a click locks it again
+  --flip-threshold PX While chasing, only turn around once the pointer is
+                      more than PX pixels the other way (default 3)
+  --control PATH      Listen for commands on a Unix datagram socket at PATH;
+                      'activate' raises and focuses the window, 'teleport'
+                      moves the gopher somewhere random like the T key does
+  --metrics-port [HOST:]PORT
+                      Serve the FPS, drawn and dropped frame counts, crossings
+                      and uptime to Prometheus at http://HOST:PORT/metrics;
+                      HOST is an IP address, 127.0.0.1 unless given (needs
+                      the 'metrics' build feature)
+  --pipe-raw PATH     Also write every frame of the gopher as raw RGBA to the
+                      FIFO at PATH, creating it if needed, for recording with
+                      ffmpeg -f rawvideo; frames are dropped while nothing is
+                      reading. The size and frame rate are printed at startup
+  --emit-position     Print 'X Y FRAME forward|backward' to stdout for the
+                      first gopher on each frame drawn, instead of the FPS
+                      line, for mirroring its moves in another program
+  --emit-every N      Only print every Nth position (default 1)
+  --format FORMAT     Pixel format of the gopher and background buffers: 'argb'
+                      (default) keeps them see-through where the images are,
+                      'xrgb' draws them opaque over black
+  --damage MODE       How much of each gopher frame to report as changed: 'full'
+                      (default) or 'minimal', just the pixels that differ from
+                      the frame before, where the compositor supports it
+  --record-path FILE  Record the gopher into FILE, an animated GIF or (ending
+                      in .png or .apng) APNG, written when wlgopher exits or
+                      --record-seconds have been recorded. Frames over 320
+                      pixels across are scaled down
+  --record-seconds N  The longest --record-path records, at most 60
+                      (default 10)
+  --terminal          Animate the gopher in the terminal instead of a Wayland
+                      window
+  --splash FILE       Show this image in the middle of the window at startup,
+                      before the gopher starts walking
+  --splash-ms MS      How long to show --splash for (default 2000)
+  --fade-ms MS        Fade the window in at startup and out when quitting over
+                      MS milliseconds (default 0, no fade)
+  --max-fps N         Draw at most N frames per second, skipping frame callbacks
+                      in between and advancing the animation by elapsed time
+  --throttle-timeout MS
+                      Consider the window throttled or occluded after MS
+                      milliseconds without a frame callback (default 1000,
+                      0 disables the check)
+  --pause-when-throttled
+                      Don't advance the animation while throttled
+  --prerender         Keep a third buffer per gopher and render upcoming frames
+                      into spare buffers ahead of time, so a frame is ready
+                      when the compositor asks for one
+  --desync            Let each gopher's surface update as soon as it's drawn
+                      rather than together with the window's, which can be
+                      smoother but lets a new frame show for a moment before
+                      the gopher moves to where it belongs; the default keeps
+                      them in step
+  --single-surface    Copy the gophers into the window's own buffer every frame
+                      instead of giving each a subsurface, for compositors
+                      that handle subsurfaces badly; it costs more copying
+  --compositor-quirks MODE
+                      Work around how some compositors behave: auto (the
+                      default) goes by what the compositor looks like, none
+                      turns the workarounds off, and sway, gnome or kde uses
+                      that compositor's
+  --check-frames      Print the size of every frame as INDEX WIDTHxHEIGHT and
+                      exit, warning if they aren't all the same
+  --dry-run           Load the frames and check the options, print a summary and
+                      exit without opening a window
+  --dump-config PATH  Write every option as it ends up, defaults included, to
+                      PATH as JSON if it ends in .json or else TOML, and exit;
+                      with --seed, what would be picked at random is filled in
+  --debug-overlay     Show each gopher's position, frame, direction and jump
+                      in the top-left corner, updated every frame
+  --debug-alpha       Draw the gophers over a gray checkerboard instead of
+                      see-through, to show what their alpha channel holds
+  --verbose           Print diagnostics, such as throttling, to stderr
+  --trace-protocol    Print every event received and the main requests sent,
+                      naming the gopher, surface or buffer each is about
+  --quiet             Don't print the periodic FPS line
+  --profile           Print how long copying pixels, moving the gophers and
+                      committing took per frame along with the FPS line
+  --inhibit-idle      Keep the screen awake while the gopher is running
+  --content-type TYPE Tell the compositor the window shows a photo, a video or
+                      a game (default), where it supports the hint
+  --help-modes        List the ways the gopher can move and exit
+  -h, --help          Print this help and exit
+
+Environment:
+  These set the option of the same name, overriding any --scene or
+  --config; options given on the command line override them. An option
+  also overrides the ones it would clash with, as --walk-step-min does
+  WLGOPHER_WALK_STEP. Empty ones count as unset.
+
+  WLGOPHER_FRAMES, WLGOPHER_MAX_FPS, WLGOPHER_SPEED, WLGOPHER_WALK_STEP,
+  WLGOPHER_JUMP_HEIGHT_MIN, WLGOPHER_JUMP_HEIGHT_MAX, WLGOPHER_JUMP_EASE,
+  WLGOPHER_EDGE, WLGOPHER_BG, WLGOPHER_BG_COLOR, WLGOPHER_SEED";
+
+/// The environment variables read for options, by `from_env_and_args`.
+const ENV_OPTIONS: &[(&str, &str)] = &[
+    ("WLGOPHER_FRAMES", "--frames"),
+    ("WLGOPHER_MAX_FPS", "--max-fps"),
+    ("WLGOPHER_SPEED", "--speed"),
+    ("WLGOPHER_WALK_STEP", "--walk-step"),
+    ("WLGOPHER_JUMP_HEIGHT_MIN", "--jump-height-min"),
+    ("WLGOPHER_JUMP_HEIGHT_MAX", "--jump-height-max"),
+    ("WLGOPHER_JUMP_EASE", "--jump-ease"),
+    ("WLGOPHER_EDGE", "--edge"),
+    ("WLGOPHER_BG", "--bg"),
+    ("WLGOPHER_BG_COLOR", "--bg-color"),
+    ("WLGOPHER_SEED", "--seed"),
+];
+
+/// Options that set the same thing between them. Setting one of them in
+/// the environment or on the command line drops what came before for all
+/// of them, rather than clashing with it.
+const OPTION_GROUPS: &[&[&str]] = &[
+    &["--walk-step", "--walk-step-min", "--walk-step-max"],
+    &["--jump-height-min", "--jump-height-max"],
+];
+
+/// One of the ways the gopher can move, as listed by `--help-modes`.
+pub struct Mode {
+    pub name: &'static str,
+    /// The options that turn it on.
+    pub options: &'static str,
+    pub description: &'static str,
+    /// The other modes it doesn't make sense together with.
+    pub conflicts: &'static [&'static str],
+    is_on: fn(&Config) -> bool,
+}
+
+impl Mode {
+    /// The option that turns it on, without its value.
+    fn option(&self) -> &'static str {
+        self.options.split(' ').next().unwrap()
+    }
+}
+
+/// Every mode, with the default first.
+pub const MODES: &[Mode] = &[
+    Mode {
+        name: "walk",
+        options: "(default)",
+        description: "Walk back and forth along the bottom, jumping now and then and \
+                      turning around at the edges",
+        conflicts: &[],
+        is_on: |config| config.edge == EdgeMode::Bounce,
+    },
+    Mode {
+        name: "wrap",
+        options: "--edge wrap",
+        description: "Walk off one edge and come back on at the other, or with \
+                      --mirror-on-wrap turn around out of sight",
+        conflicts: &["anchor"],
+        is_on: |config| config.edge == EdgeMode::Wrap,
+    },
+    Mode {
+        name: "hop",
+        options: "--hop-on-key",
+        description: "Stand still but for a hop forward on every key press",
+        conflicts: &["anchor", "dig"],
+        is_on: |config| config.hop_on_key,
+    },
+    Mode {
+        name: "chase",
+        options: "--lock-pointer",
+        description: "Walk after the pointer, which is locked to the window",
+        conflicts: &["anchor"],
+        is_on: |config| config.lock_pointer,
+    },
+    Mode {
+        name: "anchor",
+        options: "--anchor CORNER [--no-walk]",
+        description: "Stay in a corner, going through the walk cycle in place or \
+                      holding the first frame",
+        conflicts: &["wrap", "hop", "chase", "dig"],
+        is_on: |config| config.anchor.is_some(),
+    },
+    Mode {
+        name: "dig",
+        options: "--dig-every SECONDS [--dig-frames PATH] [--popup-frames PATH]",
+        description: "Walk as usual, but now and then dig down out of sight and pop \
+                      up somewhere else",
+        conflicts: &["hop", "anchor"],
+        is_on: |config| config.dig_every.is_some(),
+    },
+];
+
+/// What `--help-modes` prints.
+pub fn modes_help() -> String {
+    let mut help = String::new();
+    for mode in MODES {
+        help += &format!(
+            "{:<8}{}\n        {}\n",
+            mode.name, mode.options, mode.description
+        );
+        if !mode.conflicts.is_empty() {
+            help += &format!("        Not with: {}\n", mode.conflicts.join(", "));
+        }
+    }
+    help
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeMode {
+    #[default]
+    Bounce,
+    Wrap,
+}
+
+impl FromStr for EdgeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounce" => Ok(EdgeMode::Bounce),
+            "wrap" => Ok(EdgeMode::Wrap),
+            _ => Err(format!("unknown edge mode '{}'", s)),
+        }
+    }
+}
+
+/// How `--ease-edges` goes from full speed to its slowest at an edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    Quad,
+    #[default]
+    Sine,
+}
+
+impl Easing {
+    /// The share of full speed at `t`, 0 at the edge and 1 from the ease
+    /// distance on.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::Quad => t * (2.0 - t),
+            Easing::Sine => (t * std::f64::consts::FRAC_PI_2).sin(),
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Easing::Linear),
+            "quad" => Ok(Easing::Quad),
+            "sine" => Ok(Easing::Sine),
+            _ => Err(format!("unknown easing curve '{}'", s)),
+        }
+    }
+}
+
+/// How a jump's height goes over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JumpEase {
+    /// The same step all the way up and down.
+    Linear,
+    /// Full steps, slowing to a quarter of them over the top 40%.
+    #[default]
+    EaseOut,
+    /// Along a parabola, as if thrown up against gravity.
+    Parabolic,
+}
+
+impl FromStr for JumpEase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(JumpEase::Linear),
+            "ease-out" => Ok(JumpEase::EaseOut),
+            "parabolic" => Ok(JumpEase::Parabolic),
+            _ => Err(format!("unknown jump curve '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DamageMode {
+    #[default]
+    Full,
+    Minimal,
+}
+
+impl FromStr for DamageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(DamageMode::Full),
+            "minimal" => Ok(DamageMode::Minimal),
+            _ => Err(format!("unknown damage mode '{}'", s)),
+        }
+    }
+}
+
+/// Something a key does, as bound by name in a `--scene`'s `[keys]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    Pause,
+    Jump,
+    Teleport,
+    NextSkin,
+    ToggleFullscreen,
+    StepForward,
+    StepBack,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Quit,
+        Action::Pause,
+        Action::Jump,
+        Action::Teleport,
+        Action::NextSkin,
+        Action::ToggleFullscreen,
+        Action::StepForward,
+        Action::StepBack,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Pause => "pause",
+            Action::Jump => "jump",
+            Action::Teleport => "teleport",
+            Action::NextSkin => "next-skin",
+            Action::ToggleFullscreen => "toggle-fullscreen",
+            Action::StepForward => "step-forward",
+            Action::StepBack => "step-back",
+        }
+    }
+
+    /// How to refer to the action's binding in messages.
+    pub fn option(self) -> String {
+        match self {
+            Action::Quit => "--quit-key".to_owned(),
+            Action::Pause => "--pause-key".to_owned(),
+            _ => format!("the {} key", self.name()),
+        }
+    }
+
+    /// The keysym name the action is bound to in `config`, if any.
+    pub fn key(self, config: &Config) -> Option<&str> {
+        if let Some((_, name)) = (config.key_bindings.iter().rev()).find(|(a, _)| *a == self) {
+            return Some(name);
+        }
+        match self {
+            Action::Quit => Some(&config.quit_key),
+            Action::Pause => Some(&config.pause_key),
+            Action::Jump => None,
+            Action::Teleport => Some("t"),
+            Action::NextSkin => Some("n"),
+            Action::ToggleFullscreen => Some("f"),
+            Action::StepForward => Some("period"),
+            Action::StepBack => Some("comma"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (Action::ALL.into_iter())
+            .find(|action| action.name() == s)
+            .ok_or_else(|| format!("unknown action '{}'", s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn is_left(self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::BottomLeft)
+    }
+
+    pub fn is_top(self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::TopRight)
+    }
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("unknown corner '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentType {
+    Photo,
+    Video,
+    #[default]
+    Game,
+}
+
+impl FromStr for ContentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "photo" => Ok(ContentType::Photo),
+            "video" => Ok(ContentType::Video),
+            "game" => Ok(ContentType::Game),
+            _ => Err(format!("unknown content type '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PixelFormat {
+    #[default]
+    Argb,
+    Xrgb,
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argb" => Ok(PixelFormat::Argb),
+            "xrgb" => Ok(PixelFormat::Xrgb),
+            _ => Err(format!("unknown pixel format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "90")]
+    Rotate90,
+    #[serde(rename = "180")]
+    Rotate180,
+    #[serde(rename = "270")]
+    Rotate270,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" | "0" => Ok(Rotation::Normal),
+            "90" => Ok(Rotation::Rotate90),
+            "180" => Ok(Rotation::Rotate180),
+            "270" => Ok(Rotation::Rotate270),
+            _ => Err(format!("unknown transform '{}'", s)),
+        }
+    }
+}
+
+/// A straight (not premultiplied) RGBA color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color(pub [u8; 4]);
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(|| format!("'{}' is not an RRGGBB or RRGGBBAA color", s))
+        };
+
+        match hex.len() {
+            6 => Ok(Color([channel(0)?, channel(2)?, channel(4)?, 0xff])),
+            8 => Ok(Color([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+            _ => Err(format!("'{}' is not an RRGGBB or RRGGBBAA color", s)),
+        }
+    }
+}
+
+/// Written back the way it's read, as RRGGBBAA.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Color([r, g, b, a]) = *self;
+        serializer.serialize_str(&format!("{:02x}{:02x}{:02x}{:02x}", r, g, b, a))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A border around the gopher's silhouette, written COLOR[:WIDTH].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outline {
+    pub color: Color,
+    pub width: u32,
+}
+
+/// Growing and shrinking over time, written AMPLITUDE,PERIOD: by up to
+/// `amplitude` of its size either way, and back every `period_ms`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pulse {
+    pub amplitude: f64,
+    pub period_ms: u32,
+}
+
+impl FromStr for Pulse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (amplitude, period) = s
+            .split_once(',')
+            .ok_or_else(|| format!("'{}' is not AMPLITUDE,PERIOD", s))?;
+        let amplitude: f64 = amplitude
+            .parse()
+            .map_err(|_| format!("'{}' is not a pulse amplitude", amplitude))?;
+        let period_ms: u32 = period
+            .parse()
+            .map_err(|_| format!("'{}' is not a pulse period", period))?;
+        // Shrinking by all of it would leave nothing to draw.
+        if !(amplitude > 0.0 && amplitude < 1.0) {
+            return Err("the pulse amplitude must be between 0 and 1".into());
+        }
+        if period_ms == 0 {
+            return Err("the pulse period must be at least 1 millisecond".into());
+        }
+        Ok(Pulse {
+            amplitude,
+            period_ms,
+        })
+    }
+}
+
+/// Speeding up while walking, written RATE,MAX: `rate` times the speed
+/// more every second, up to `max` times it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Accelerate {
+    pub rate: f64,
+    pub max: f64,
+}
+
+impl FromStr for Accelerate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rate, max) = s
+            .split_once(',')
+            .ok_or_else(|| format!("'{}' is not RATE,MAX", s))?;
+        let rate: f64 = rate
+            .parse()
+            .map_err(|_| format!("'{}' is not an acceleration rate", rate))?;
+        let max: f64 = max
+            .parse()
+            .map_err(|_| format!("'{}' is not a top speed multiplier", max))?;
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err("the acceleration rate must be a positive number".into());
+        }
+        if !(max > 1.0 && max.is_finite()) {
+            return Err("the top speed multiplier must be more than 1".into());
+        }
+        Ok(Accelerate { rate, max })
+    }
+}
+
+/// Wider outlines take long to draw and stop looking like outlines.
+const MAX_OUTLINE_WIDTH: u32 = 16;
+
+impl FromStr for Outline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (color, width) = match s.split_once(':') {
+            Some((color, width)) => (
+                color,
+                width
+                    .parse()
+                    .map_err(|_| format!("'{}' is not an outline width", width))?,
+            ),
+            None => (s, 1),
+        };
+        if !(1..=MAX_OUTLINE_WIDTH).contains(&width) {
+            return Err(format!(
+                "the outline width must be between 1 and {}",
+                MAX_OUTLINE_WIDTH
+            ));
+        }
+        Ok(Outline {
+            color: color.parse()?,
+            width,
+        })
+    }
+}
+
+/// A width and height in pixels, written WIDTHxHEIGHT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Size(pub u32, pub u32);
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('x')
+            .and_then(|(w, h)| Some(Size(w.parse().ok()?, h.parse().ok()?)))
+            .ok_or_else(|| format!("'{}' is not a WIDTHxHEIGHT size", s))
+    }
+}
+
+/// A rectangle within the window, written X,Y,WIDTHxHEIGHT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub size: Size,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ',');
+        let mut next = || parts.next().and_then(|part| part.parse().ok());
+        let (x, y) = (next(), next());
+        let size = parts.next().and_then(|size| size.parse().ok());
+        match (x, y, size) {
+            (Some(x), Some(y), Some(size)) => Ok(Region { x, y, size }),
+            _ => Err(format!("'{}' is not an X,Y,WIDTHxHEIGHT region", s)),
+        }
+    }
+}
+
+/// One of the gophers a `--scene` puts in the window. Whatever isn't set
+/// here comes from the rest of the [`Config`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GopherConfig {
+    pub frames: Option<PathBuf>,
+    pub speed: Option<f64>,
+    /// Where the gopher starts along its walk.
+    pub start_x: Option<f64>,
+    pub tint: Option<Color>,
+    pub scale: Option<f64>,
+}
+
+/// Written out by `--dump-config` and read back by `--config`, where
+/// whatever's left out keeps its default.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub frames: Option<PathBuf>,
+    pub skins: Vec<PathBuf>,
+    pub random_skin_per_crossing: bool,
+    pub watch: bool,
+    pub max_frames: usize,
+    pub load_threads: usize,
+    pub color_key: Option<Color>,
+    pub color_key_tolerance: u8,
+    pub brightness: f64,
+    pub gamma: f64,
+    pub palette: Option<PathBuf>,
+    pub outline: Option<Outline>,
+    pub pulse: Option<Pulse>,
+    pub no_flip: bool,
+    pub walk_step: Option<u64>,
+    pub walk_step_min: Option<u64>,
+    pub walk_step_max: Option<u64>,
+    pub jump_height_min: Option<u64>,
+    pub jump_height_max: Option<u64>,
+    pub jump_up_frame: Option<usize>,
+    pub jump_down_frame: Option<usize>,
+    pub jump_ease: JumpEase,
+    pub dig_every: Option<u32>,
+    pub dig_frames: Option<PathBuf>,
+    pub popup_frames: Option<PathBuf>,
+    pub center_start: bool,
+    pub reset_position_on_resize: bool,
+    /// Multiplier for the walk step, on top of `--focus-react`.
+    pub speed: f64,
+    pub seed: Option<u64>,
+    /// Color the gopher's frames are multiplied with, which `--scene` and
+    /// `--vary` set per gopher.
+    pub tint: Option<Color>,
+    /// Size of the gopher relative to its frames, which `--scene` and
+    /// `--vary` set per gopher.
+    pub scale: f64,
+    pub vary: bool,
+    pub edge: EdgeMode,
+    pub mirror_on_wrap: bool,
+    pub edge_pause_ms: u32,
+    pub ease_edges: bool,
+    pub ease_distance: Option<u32>,
+    pub ease_curve: Option<Easing>,
+    pub accelerate: Option<Accelerate>,
+    pub bg: Background,
+    pub bg_color: Color,
+    pub bg_image: Option<PathBuf>,
+    pub tile_gophers: Option<u32>,
+    pub footprints: bool,
+    pub no_opaque_region: bool,
+    /// Pixels kept clear on the left and right of the window, from a
+    /// `--scene`.
+    pub margin: u32,
+    pub region: Option<Region>,
+    pub anchor: Option<Corner>,
+    pub no_walk: bool,
+    /// Height of the ground line above the bottom of the window, from a
+    /// `--scene`.
+    pub ground: u32,
+    /// The gophers in the window; without any there's a single one set up
+    /// by the rest of the options.
+    pub gophers: Vec<GopherConfig>,
+    pub transform: Option<Rotation>,
+    pub mirror_output: bool,
+    pub windowed: bool,
+    /// Go fullscreen on the output the surface enters first.
+    pub fullscreen_on_focused: bool,
+    pub follow_active_window: bool,
+    pub all_outputs: bool,
+    /// The socket to connect to instead of `$WAYLAND_DISPLAY`.
+    pub wayland_display: Option<String>,
+    /// An xdg-foreign handle exported by another client.
+    pub parent: Option<String>,
+    pub remember: bool,
+    pub min_size: Option<Size>,
+    pub max_size: Option<Size>,
+    pub size_margin: u32,
+    pub max_buffer_dim: u32,
+    pub keep_aspect: bool,
+    /// Buffer pixels per surface pixel, at most 1. This is relative to the
+    /// surface, i.e. to logical pixels, and wlgopher always attaches buffers
+    /// at scale 1, so it multiplies with any HiDPI upscaling the compositor
+    /// does rather than replacing it.
+    pub render_scale: f64,
+    pub subpixel: bool,
+    pub focus_react: bool,
+    pub pause_unfocused: bool,
+    pub pause_when_idle: Option<u32>,
+    pub bounce_sound: Option<PathBuf>,
+    pub quit_key: String,
+    pub pause_key: String,
+    pub start_paused: bool,
+    /// Key names, or None with `--no-cheat-code`.
+    pub cheat_code: Option<Vec<String>>,
+    /// Keys for actions other than quitting and pausing, which have
+    /// options of their own, from a `--scene`'s `[keys]`.
+    pub key_bindings: Vec<(Action, String)>,
+    pub hop_on_key: bool,
+    pub lock_pointer: bool,
+    pub flip_threshold: u32,
+    pub control: Option<PathBuf>,
+    pub metrics_address: Option<SocketAddr>,
+    pub pipe_raw: Option<PathBuf>,
+    pub emit_position: bool,
+    pub emit_every: u32,
+    pub format: PixelFormat,
+    pub damage: DamageMode,
+    pub record_path: Option<PathBuf>,
+    pub record_seconds: u32,
+    pub terminal: bool,
+    pub splash: Option<PathBuf>,
+    pub splash_ms: u32,
+    pub fade_ms: u32,
+    pub max_fps: Option<u32>,
+    pub throttle_timeout: u64,
+    pub pause_when_throttled: bool,
+    pub prerender: bool,
+    pub desync: bool,
+    pub single_surface: bool,
+    pub compositor_quirks: QuirksMode,
+    // What to do instead of running isn't part of the setup --dump-config
+    // writes out.
+    #[serde(skip)]
+    pub check_frames: bool,
+    #[serde(skip)]
+    pub dry_run: bool,
+    #[serde(skip)]
+    pub dump_config: Option<PathBuf>,
+    pub debug_overlay: bool,
+    pub debug_alpha: bool,
+    pub verbose: bool,
+    pub trace_protocol: bool,
+    pub quiet: bool,
+    pub profile: bool,
+    pub inhibit_idle: bool,
+    pub content_type: ContentType,
+    #[serde(skip)]
+    pub help: bool,
+    #[serde(skip)]
+    pub help_modes: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frames: None,
+            skins: Vec::new(),
+            random_skin_per_crossing: false,
+            watch: false,
+            max_frames: 0,
+            load_threads: 0,
+            color_key: None,
+            color_key_tolerance: 0,
+            brightness: 1.0,
+            gamma: 1.0,
+            palette: None,
+            outline: None,
+            pulse: None,
+            no_flip: false,
+            walk_step: None,
+            walk_step_min: None,
+            walk_step_max: None,
+            jump_height_min: None,
+            jump_height_max: None,
+            jump_up_frame: None,
+            jump_down_frame: None,
+            jump_ease: JumpEase::default(),
+            dig_every: None,
+            dig_frames: None,
+            popup_frames: None,
+            center_start: false,
+            reset_position_on_resize: false,
+            speed: 1.0,
+            seed: None,
+            tint: None,
+            scale: 1.0,
+            vary: false,
+            edge: EdgeMode::Bounce,
+            mirror_on_wrap: false,
+            edge_pause_ms: 0,
+            ease_edges: false,
+            ease_distance: None,
+            ease_curve: None,
+            accelerate: None,
+            bg: Background::None,
+            bg_color: Color([0x40, 0x80, 0xc0, 0xff]),
+            bg_image: None,
+            tile_gophers: None,
+            footprints: false,
+            no_opaque_region: false,
+            margin: 0,
+            region: None,
+            anchor: None,
+            no_walk: false,
+            ground: 0,
+            gophers: Vec::new(),
+            transform: None,
+            mirror_output: false,
+            windowed: false,
+            fullscreen_on_focused: false,
+            follow_active_window: false,
+            wayland_display: None,
+            all_outputs: false,
+            parent: None,
+            remember: false,
+            min_size: None,
+            max_size: None,
+            size_margin: 16,
+            max_buffer_dim: 8192,
+            keep_aspect: false,
+            render_scale: 1.0,
+            subpixel: false,
+            focus_react: false,
+            pause_unfocused: false,
+            pause_when_idle: None,
+            bounce_sound: None,
+            quit_key: "Escape".to_owned(),
+            pause_key: "space".to_owned(),
+            start_paused: false,
+            key_bindings: Vec::new(),
+            cheat_code: Some(
+                [
+                    "up", "up", "down", "down", "left", "right", "left", "right", "b", "a",
+                ]
+                .map(str::to_owned)
+                .to_vec(),
+            ),
+            hop_on_key: false,
+            lock_pointer: false,
+            flip_threshold: 3,
+            control: None,
+            metrics_address: None,
+            pipe_raw: None,
+            emit_position: false,
+            emit_every: 1,
+            format: PixelFormat::Argb,
+            damage: DamageMode::Full,
+            record_path: None,
+            record_seconds: 10,
+            terminal: false,
+            splash: None,
+            splash_ms: 2000,
+            fade_ms: 0,
+            max_fps: None,
+            throttle_timeout: 1000,
+            pause_when_throttled: false,
+            prerender: false,
+            desync: false,
+            single_surface: false,
+            compositor_quirks: QuirksMode::Auto,
+            check_frames: false,
+            dry_run: false,
+            dump_config: None,
+            debug_overlay: false,
+            debug_alpha: false,
+            verbose: false,
+            trace_protocol: false,
+            quiet: false,
+            profile: false,
+            inhibit_idle: false,
+            content_type: ContentType::Game,
+            help: false,
+            help_modes: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, Box<dyn Error>> {
+        Config::from_env_and_args(|_| None, args)
+    }
+
+    /// Like `from_args`, with the `WLGOPHER_*` variables `var` looks up in
+    /// between: any `--scene` goes first, then the variables and then the
+    /// rest of the options, each overriding what came before.
+    pub fn from_env_and_args<I>(
+        var: impl Fn(&str) -> Option<String>,
+        args: I,
+    ) -> Result<Config, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        // Parsed once on their own first, to tell the options from values
+        // that happen to look like one.
+        let options = Config::default().apply_args(args)?;
+        let (scenes, rest): (Vec<_>, Vec<_>) = (options.into_iter())
+            .partition(|option| ["--scene", "--config"].contains(&&*option[0]));
+        let env: Vec<_> = (ENV_OPTIONS.iter())
+            .filter_map(|&(name, option)| {
+                let value = var(name).filter(|value| !value.is_empty())?;
+                Some((name, option, value))
+            })
+            .collect();
+
+        let mut config = Config::default();
+        config.apply_args(scenes.concat())?;
+        config.forget_groups(env.iter().map(|&(_, option, _)| option));
+        for (name, option, value) in env {
+            (config.apply_args([option.to_string(), value]))
+                .map_err(|e| format!("{}: {}", name, e))?;
+        }
+        config.forget_groups(rest.iter().map(|option| option[0].as_str()));
+        config.apply_args(rest.concat())?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Unsets the options in the same `OPTION_GROUPS` as any of `options`,
+    /// which are about to be set again.
+    fn forget_groups<'a>(&mut self, options: impl IntoIterator<Item = &'a str>) {
+        let options: Vec<_> = options.into_iter().collect();
+        let groups =
+            (OPTION_GROUPS.iter()).filter(|group| group.iter().any(|o| options.contains(o)));
+        for &option in groups.flat_map(|group| group.iter()) {
+            match option {
+                "--walk-step" => self.walk_step = None,
+                "--walk-step-min" => self.walk_step_min = None,
+                "--walk-step-max" => self.walk_step_max = None,
+                "--jump-height-min" => self.jump_height_min = None,
+                "--jump-height-max" => self.jump_height_max = None,
+                _ => unreachable!("{} can't be forgotten", option),
+            }
+        }
+    }
+
+    /// Sets the options in `args`, in order, and returns them one by one,
+    /// each with its value if it has one.
+    fn apply_args<I: IntoIterator<Item = String>>(
+        &mut self,
+        args: I,
+    ) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+        let config = self;
+        let all: Vec<String> = args.into_iter().collect();
+        let mut args = all.clone().into_iter();
+        let mut options = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let start = all.len() - args.len() - 1;
+            match arg.as_str() {
+                "--scene" => {
+                    let path: PathBuf = parse_value(&arg, args.next())?;
+                    crate::scene::load(&path, config)?;
+                }
+                "--config" => *config = Config::load(&parse_value::<PathBuf>(&arg, args.next())?)?,
+                "--frames" => config.frames = Some(parse_value(&arg, args.next())?),
+                "--skin" => config.skins.push(parse_value(&arg, args.next())?),
+                "--random-skin-per-crossing" => config.random_skin_per_crossing = true,
+                "--watch" => config.watch = true,
+                "--max-frames" => config.max_frames = parse_value(&arg, args.next())?,
+                "--load-threads" => config.load_threads = parse_value(&arg, args.next())?,
+                "--color-key" => config.color_key = Some(parse_value(&arg, args.next())?),
+                "--color-key-tolerance" => {
+                    config.color_key_tolerance = parse_value(&arg, args.next())?
+                }
+                "--brightness" => config.brightness = parse_value(&arg, args.next())?,
+                "--gamma" => config.gamma = parse_value(&arg, args.next())?,
+                "--palette" => config.palette = Some(parse_value(&arg, args.next())?),
+                "--outline" => config.outline = Some(parse_value(&arg, args.next())?),
+                "--pulse" => config.pulse = Some(parse_value(&arg, args.next())?),
+                "--no-flip" => config.no_flip = true,
+                "--walk-step" => config.walk_step = Some(parse_value(&arg, args.next())?),
+                "--walk-step-min" => config.walk_step_min = Some(parse_value(&arg, args.next())?),
+                "--walk-step-max" => config.walk_step_max = Some(parse_value(&arg, args.next())?),
+                "--speed" => config.speed = parse_value(&arg, args.next())?,
+                "--jump-height-min" => {
+                    config.jump_height_min = Some(parse_value(&arg, args.next())?)
+                }
+                "--jump-height-max" => {
+                    config.jump_height_max = Some(parse_value(&arg, args.next())?)
+                }
+                "--jump-up-frame" => config.jump_up_frame = Some(parse_value(&arg, args.next())?),
+                "--jump-down-frame" => {
+                    config.jump_down_frame = Some(parse_value(&arg, args.next())?)
+                }
+                "--jump-ease" => config.jump_ease = parse_value(&arg, args.next())?,
+                "--dig-every" => config.dig_every = Some(parse_value(&arg, args.next())?),
+                "--dig-frames" => config.dig_frames = Some(parse_value(&arg, args.next())?),
+                "--popup-frames" => config.popup_frames = Some(parse_value(&arg, args.next())?),
+                "--center-start" => config.center_start = true,
+                "--reset-position-on-resize" => config.reset_position_on_resize = true,
+                "--seed" => config.seed = Some(parse_value(&arg, args.next())?),
+                "--vary" => config.vary = true,
+                "--edge" => config.edge = parse_value(&arg, args.next())?,
+                "--edge-pause-ms" => config.edge_pause_ms = parse_value(&arg, args.next())?,
+                "--mirror-on-wrap" => config.mirror_on_wrap = true,
+                "--ease-edges" => config.ease_edges = true,
+                "--ease-distance" => config.ease_distance = Some(parse_value(&arg, args.next())?),
+                "--ease-curve" => config.ease_curve = Some(parse_value(&arg, args.next())?),
+                "--accelerate" => config.accelerate = Some(parse_value(&arg, args.next())?),
+                "--bg" => config.bg = parse_value(&arg, args.next())?,
+                "--bg-color" => config.bg_color = parse_value(&arg, args.next())?,
+                "--background-image" => config.bg_image = Some(parse_value(&arg, args.next())?),
+                "--tile-gophers" => config.tile_gophers = Some(parse_value(&arg, args.next())?),
+                "--footprints" => config.footprints = true,
+                "--no-opaque-region" => config.no_opaque_region = true,
+                "--windowed" => config.windowed = true,
+                "--fullscreen-on-focused" => config.fullscreen_on_focused = true,
+                "--follow-active-window" => config.follow_active_window = true,
+                "--wayland-display" => {
+                    config.wayland_display = Some(parse_value(&arg, args.next())?)
+                }
+                "--all-outputs" => config.all_outputs = true,
+                "--parent" => config.parent = Some(parse_value(&arg, args.next())?),
+                "--remember" => config.remember = true,
+                "--anchor" => config.anchor = Some(parse_value(&arg, args.next())?),
+                "--no-walk" => config.no_walk = true,
+                "--region" => config.region = Some(parse_value(&arg, args.next())?),
+                "--min-size" => config.min_size = Some(parse_value(&arg, args.next())?),
+                "--max-size" => config.max_size = Some(parse_value(&arg, args.next())?),
+                "--size-margin" => config.size_margin = parse_value(&arg, args.next())?,
+                "--max-buffer-dim" => config.max_buffer_dim = parse_value(&arg, args.next())?,
+                "--render-scale" => config.render_scale = parse_value(&arg, args.next())?,
+                "--keep-aspect" => config.keep_aspect = true,
+                "--transform" => config.transform = Some(parse_value(&arg, args.next())?),
+                "--mirror-output" => config.mirror_output = true,
+                "--subpixel" => config.subpixel = true,
+                "--focus-react" => config.focus_react = true,
+                "--pause-unfocused" => config.pause_unfocused = true,
+                "--pause-when-idle" => {
+                    config.pause_when_idle = Some(parse_value(&arg, args.next())?)
+                }
+                "--bounce-sound" => config.bounce_sound = Some(parse_value(&arg, args.next())?),
+                "--quit-key" => config.quit_key = parse_value(&arg, args.next())?,
+                "--pause-key" => config.pause_key = parse_value(&arg, args.next())?,
+                "--start-paused" => config.start_paused = true,
+                "--cheat-code" => {
+                    let keys: String = parse_value(&arg, args.next())?;
+                    config.cheat_code = Some(keys.split(',').map(str::to_owned).collect());
+                }
+                "--no-cheat-code" => config.cheat_code = None,
+                "--hop-on-key" => config.hop_on_key = true,
+                "--lock-pointer" => config.lock_pointer = true,
+                "--flip-threshold" => config.flip_threshold = parse_value(&arg, args.next())?,
+                "--control" => config.control = Some(parse_value(&arg, args.next())?),
+                "--metrics-port" => {
+                    config.metrics_address = Some(parse_metrics_address(&arg, args.next())?)
+                }
+                "--pipe-raw" => config.pipe_raw = Some(parse_value(&arg, args.next())?),
+                "--emit-position" => config.emit_position = true,
+                "--emit-every" => config.emit_every = parse_value(&arg, args.next())?,
+                "--format" => config.format = parse_value(&arg, args.next())?,
+                "--damage" => config.damage = parse_value(&arg, args.next())?,
+                "--record-path" => config.record_path = Some(parse_value(&arg, args.next())?),
+                "--record-seconds" => config.record_seconds = parse_value(&arg, args.next())?,
+                "--terminal" => config.terminal = true,
+                "--splash" => config.splash = Some(parse_value(&arg, args.next())?),
+                "--splash-ms" => config.splash_ms = parse_value(&arg, args.next())?,
+                "--fade-ms" => config.fade_ms = parse_value(&arg, args.next())?,
+                "--max-fps" => config.max_fps = Some(parse_value(&arg, args.next())?),
+                "--throttle-timeout" => config.throttle_timeout = parse_value(&arg, args.next())?,
+                "--pause-when-throttled" => config.pause_when_throttled = true,
+                "--prerender" => config.prerender = true,
+                "--desync" => config.desync = true,
+                "--single-surface" => config.single_surface = true,
+                "--compositor-quirks" => config.compositor_quirks = parse_value(&arg, args.next())?,
+                "--check-frames" => config.check_frames = true,
+                "--dry-run" => config.dry_run = true,
+                "--dump-config" => config.dump_config = Some(parse_value(&arg, args.next())?),
+                "--debug-overlay" => config.debug_overlay = true,
+                "--debug-alpha" => config.debug_alpha = true,
+                "--verbose" => config.verbose = true,
+                "--trace-protocol" => config.trace_protocol = true,
+                "--quiet" => config.quiet = true,
+                "--profile" => config.profile = true,
+                "--inhibit-idle" => config.inhibit_idle = true,
+                "--content-type" => config.content_type = parse_value(&arg, args.next())?,
+                "--help-modes" => config.help_modes = true,
+                "-h" | "--help" => config.help = true,
+                _ => return Err(format!("unknown option '{}'", arg).into()),
+            }
+            options.push(all[start..all.len() - args.len()].to_vec());
+        }
+        Ok(options)
+    }
+
+    /// The inclusive range the walk step is picked from.
+    pub fn walk_step_range(&self) -> (u64, u64) {
+        match self.walk_step {
+            Some(step) => (step, step),
+            None => (
+                self.walk_step_min.unwrap_or(2),
+                self.walk_step_max.unwrap_or(29),
+            ),
+        }
+    }
+
+    /// How `--ease-edges` slows the gopher down and from how far out, if
+    /// it's set.
+    pub fn edge_easing(&self) -> Option<(Easing, u32)> {
+        self.ease_edges.then(|| {
+            (
+                self.ease_curve.unwrap_or_default(),
+                self.ease_distance.unwrap_or(EASE_DISTANCE),
+            )
+        })
+    }
+
+    /// The inclusive range jump heights are picked from. Giving only one
+    /// end moves the other one out of its way if needed.
+    pub fn jump_height_range(&self) -> (u64, u64) {
+        match (self.jump_height_min, self.jump_height_max) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, min.max(JUMP_HEIGHT)),
+            (None, Some(max)) => (max.min(JUMP_HEIGHT), max),
+            (None, None) => (JUMP_HEIGHT, JUMP_HEIGHT),
+        }
+    }
+
+    /// How many threads to load the frames on, with 0 resolved to the
+    /// number of CPUs.
+    pub fn load_threads(&self) -> usize {
+        match self.load_threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    /// The config the `index`th of `gophers` is built from, which is this
+    /// one with the gopher's own settings on top.
+    pub(crate) fn for_gopher(&self, index: usize, gopher: &GopherConfig) -> Config {
+        // Gophers from the same seed would walk in lockstep.
+        let seed = self.seed.map(|seed| seed.wrapping_add(index as u64));
+        let mut config = Config {
+            frames: gopher.frames.clone().or_else(|| self.frames.clone()),
+            speed: gopher.speed.unwrap_or(self.speed),
+            seed,
+            tint: gopher.tint.or(self.tint),
+            scale: gopher.scale.unwrap_or(self.scale),
+            gophers: Vec::new(),
+            ..self.clone()
+        };
+
+        if self.vary {
+            // A stream of its own, so varying doesn't change how they walk.
+            let mut rng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed ^ VARY_SEED),
+                None => StdRng::from_entropy(),
+            };
+            let tint = Color([
+                rng.gen_range(128..=255),
+                rng.gen_range(128..=255),
+                rng.gen_range(128..=255),
+                0xff,
+            ]);
+            let scale = rng.gen_range(0.6..=1.2);
+            config.tint = gopher.tint.or(Some(tint));
+            config.scale = gopher.scale.unwrap_or(scale);
+        }
+        config
+    }
+
+    /// The options as they end up: with `--seed`, the walk step and the
+    /// `--vary` looks are the ones that would be picked at random.
+    pub(crate) fn resolved(&self) -> Config {
+        let mut config = self.clone();
+        let Some(seed) = self.seed else {
+            return config;
+        };
+        // Each gopher picks its walk step from a seed of its own, so it
+        // only comes down to one number when there's one gopher.
+        if self.walk_step.is_none() && self.gophers.len() <= 1 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            config.walk_step = Some(pick_walk_step(self.walk_step_range(), &mut rng));
+            // Which the range it came from would clash with.
+            (config.walk_step_min, config.walk_step_max) = (None, None);
+        }
+        if self.vary {
+            let gophers = match self.gophers.is_empty() {
+                true => vec![GopherConfig::default()],
+                false => self.gophers.clone(),
+            };
+            config.gophers = (gophers.into_iter().enumerate())
+                .map(|(index, gopher)| {
+                    let looks = self.for_gopher(index, &gopher);
+                    GopherConfig {
+                        tint: looks.tint,
+                        scale: Some(looks.scale),
+                        ..gopher
+                    }
+                })
+                .collect();
+            config.vary = false;
+        }
+        config
+    }
+
+    /// Reads what `dump` wrote, for `--config`.
+    fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let config = match path.extension().is_some_and(|ext| ext == "json") {
+            true => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            false => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        config.map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Writes the `resolved` options to `path` for `--dump-config`.
+    pub(crate) fn dump(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let config = self.resolved();
+        let text = match path.extension().is_some_and(|ext| ext == "json") {
+            true => serde_json::to_string_pretty(&config)? + "\n",
+            false => toml::to_string(&config)?,
+        };
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.walk_step.is_some()
+            && (self.walk_step_min.is_some() || self.walk_step_max.is_some())
+        {
+            return Err(
+                "--walk-step can't be combined with --walk-step-min/--walk-step-max".into(),
+            );
+        }
+        let (min, max) = self.walk_step_range();
+        if min == 0 {
+            return Err("the walk step must be at least 1".into());
+        }
+        if min > max {
+            return Err(format!(
+                "--walk-step-min {} is greater than --walk-step-max {}",
+                min, max
+            )
+            .into());
+        }
+
+        if !(self.brightness >= 0.0 && self.brightness.is_finite()) {
+            return Err(format!("--brightness must be at least 0, not {}", self.brightness).into());
+        }
+        if !(self.gamma > 0.0 && self.gamma.is_finite()) {
+            return Err(format!("--gamma must be a positive number, not {}", self.gamma).into());
+        }
+
+        let (min, max) = self.jump_height_range();
+        if min == 0 {
+            return Err("the jump height must be at least 1".into());
+        }
+        if min > max {
+            return Err(format!(
+                "--jump-height-min {} is greater than --jump-height-max {}",
+                min, max
+            )
+            .into());
+        }
+
+        if !(self.speed > 0.0 && self.speed.is_finite()) {
+            return Err(format!("the speed must be a positive number, not {}", self.speed).into());
+        }
+
+        if !(self.render_scale > 0.0 && self.render_scale <= 1.0) {
+            return Err(format!(
+                "--render-scale must be greater than 0 and at most 1, not {}",
+                self.render_scale
+            )
+            .into());
+        }
+
+        if self.max_buffer_dim == 0 {
+            return Err("--max-buffer-dim must be at least 1".into());
+        }
+
+        if (self.min_size.is_some() || self.max_size.is_some()) && !self.windowed {
+            return Err("--min-size and --max-size require --windowed".into());
+        }
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            if min.0 > max.0 || min.1 > max.1 {
+                return Err(format!(
+                    "--min-size {}x{} is bigger than --max-size {}x{}",
+                    min.0, min.1, max.0, max.1
+                )
+                .into());
+            }
+        }
+
+        if self.max_fps.is_some_and(|fps| fps == 0 || fps > 1000) {
+            return Err("--max-fps must be between 1 and 1000".into());
+        }
+
+        if self.color_key_tolerance > 0 && self.color_key.is_none() {
+            return Err("--color-key-tolerance requires --color-key".into());
+        }
+
+        if self.random_skin_per_crossing && self.skins.is_empty() {
+            return Err("--random-skin-per-crossing requires --skin".into());
+        }
+
+        let on: Vec<_> = MODES.iter().filter(|mode| (mode.is_on)(self)).collect();
+        for mode in &on {
+            if let Some(other) = on.iter().find(|other| mode.conflicts.contains(&other.name)) {
+                return Err(format!(
+                    "{} can't be combined with {}",
+                    mode.option(),
+                    other.option()
+                )
+                .into());
+            }
+        }
+
+        if self.dig_every == Some(0) {
+            return Err("--dig-every must be at least 1 second".into());
+        }
+        for (set, option) in [
+            (self.dig_frames.is_some(), "--dig-frames"),
+            (self.popup_frames.is_some(), "--popup-frames"),
+        ] {
+            if set && self.dig_every.is_none() {
+                return Err(format!("{} requires --dig-every", option).into());
+            }
+        }
+
+        if self.no_walk && self.anchor.is_none() {
+            return Err("--no-walk requires --anchor".into());
+        }
+
+        if (self.ease_distance.is_some() || self.ease_curve.is_some()) && !self.ease_edges {
+            return Err("--ease-distance and --ease-curve require --ease-edges".into());
+        }
+        if self.ease_distance == Some(0) {
+            return Err("--ease-distance must be at least 1".into());
+        }
+
+        if self.mirror_on_wrap && self.edge != EdgeMode::Wrap {
+            return Err("--mirror-on-wrap requires --edge wrap".into());
+        }
+
+        // Only a --config sets it directly; otherwise it's 1.
+        if !(self.scale > 0.0 && self.scale.is_finite()) {
+            return Err(format!("the scale must be a positive number, not {}", self.scale).into());
+        }
+        if let Some(scale) = (self.gophers.iter().filter_map(|gopher| gopher.scale))
+            .find(|&scale| !(scale > 0.0 && scale.is_finite()))
+        {
+            return Err(
+                format!("a gopher's scale must be a positive number, not {}", scale).into(),
+            );
+        }
+
+        if self.gophers.len() > 1 && self.terminal {
+            return Err("--terminal only shows one gopher, not a whole --scene".into());
+        }
+
+        if self.watch && self.frames.is_none() && self.gophers.iter().all(|g| g.frames.is_none()) {
+            return Err("--watch requires --frames".into());
+        }
+
+        let bound: Vec<_> = (Action::ALL.into_iter())
+            .filter_map(|action| Some((action, action.key(self)?)))
+            .collect();
+        for (i, (action, key)) in bound.iter().enumerate() {
+            if let Some((other, _)) = (bound[i + 1..].iter())
+                .find(|(_, other)| keys::normalize(other) == keys::normalize(key))
+            {
+                return Err(format!(
+                    "{} and {} are both '{}'",
+                    action.option(),
+                    other.option(),
+                    key
+                )
+                .into());
+            }
+        }
+
+        if self.record_seconds == 0 || self.record_seconds > 60 {
+            return Err("--record-seconds must be between 1 and 60".into());
+        }
+
+        if self.terminal {
+            // The terminal only runs the animation: there's no window, no
+            // keyboard or pointer and no frames to reload, record or send.
+            for (set, option) in [
+                (self.windowed, "--windowed"),
+                (self.fullscreen_on_focused, "--fullscreen-on-focused"),
+                (self.follow_active_window, "--follow-active-window"),
+                (self.trace_protocol, "--trace-protocol"),
+                (self.pulse.is_some(), "--pulse"),
+                (self.single_surface, "--single-surface"),
+                (self.reset_position_on_resize, "--reset-position-on-resize"),
+                (self.mirror_output, "--mirror-output"),
+                (self.wayland_display.is_some(), "--wayland-display"),
+                (self.parent.is_some(), "--parent"),
+                (self.tile_gophers.is_some(), "--tile-gophers"),
+                (self.footprints, "--footprints"),
+                (self.bg_image.is_some(), "--background-image"),
+                (self.splash.is_some(), "--splash"),
+                (self.debug_overlay, "--debug-overlay"),
+                (self.debug_alpha, "--debug-alpha"),
+                (self.profile, "--profile"),
+                (self.watch, "--watch"),
+                (self.random_skin_per_crossing, "--random-skin-per-crossing"),
+                (self.focus_react, "--focus-react"),
+                (self.pause_unfocused, "--pause-unfocused"),
+                (self.pause_when_idle.is_some(), "--pause-when-idle"),
+                (self.start_paused, "--start-paused"),
+                (self.hop_on_key, "--hop-on-key"),
+                (self.lock_pointer, "--lock-pointer"),
+                (self.control.is_some(), "--control"),
+                (self.metrics_address.is_some(), "--metrics-port"),
+                (self.pipe_raw.is_some(), "--pipe-raw"),
+                (self.emit_position, "--emit-position"),
+                (self.record_path.is_some(), "--record-path"),
+                (self.inhibit_idle, "--inhibit-idle"),
+            ] {
+                if set {
+                    return Err(format!("{} can't be combined with --terminal", option).into());
+                }
+            }
+        }
+
+        if self.single_surface {
+            // These move, scale or turn a gopher's own surface, and copied
+            // into the window's there's none.
+            for (set, option) in [
+                (self.desync, "--desync"),
+                (self.subpixel, "--subpixel"),
+                (self.render_scale != 1.0, "--render-scale"),
+                (self.transform.is_some(), "--transform"),
+                (self.pulse.is_some(), "--pulse"),
+            ] {
+                if set {
+                    return Err(
+                        format!("--single-surface can't be combined with {}", option).into(),
+                    );
+                }
+            }
+        }
+
+        if self.all_outputs {
+            // Each window would want the same socket, FIFO or file.
+            for (set, option) in [
+                (self.windowed, "--windowed"),
+                (self.fullscreen_on_focused, "--fullscreen-on-focused"),
+                (self.follow_active_window, "--follow-active-window"),
+                (self.terminal, "--terminal"),
+                (self.parent.is_some(), "--parent"),
+                (self.control.is_some(), "--control"),
+                (self.metrics_address.is_some(), "--metrics-port"),
+                (self.pipe_raw.is_some(), "--pipe-raw"),
+                (self.emit_position, "--emit-position"),
+                (self.record_path.is_some(), "--record-path"),
+                (self.remember, "--remember"),
+            ] {
+                if set {
+                    return Err(format!("--all-outputs can't be combined with {}", option).into());
+                }
+            }
+        }
+
+        if self.emit_every == 0 {
+            return Err("--emit-every must be at least 1".into());
+        }
+        if self.emit_every > 1 && !self.emit_position {
+            return Err("--emit-every requires --emit-position".into());
+        }
+
+        if self.pause_when_idle == Some(0) {
+            return Err("--pause-when-idle must be at least 1 second".into());
+        }
+        // Our own inhibitor would keep the idle timeout from ever running out.
+        if self.pause_when_idle.is_some() && self.inhibit_idle {
+            return Err("--pause-when-idle can't be combined with --inhibit-idle".into());
+        }
+
+        if self.center_start && self.anchor.is_some() {
+            return Err("--center-start can't be combined with --anchor".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Height of every jump without `--jump-height-min`/`--jump-height-max`.
+const JUMP_HEIGHT: u64 = 90;
+
+/// How far from an edge `--ease-edges` starts slowing down without
+/// `--ease-distance`.
+const EASE_DISTANCE: u32 = 120;
+
+/// Mixed into `--seed` for the `--vary` choices.
+const VARY_SEED: u64 = 0x7661_7279;
+
+fn parse_value<T>(option: &str, value: Option<String>) -> Result<T, Box<dyn Error>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = value.ok_or_else(|| format!("option '{}' requires a value", option))?;
+    value
+        .parse()
+        .map_err(|e| format!("invalid value '{}' for '{}': {}", value, option, e).into())
+}
+
+/// `--metrics-port`'s `[HOST:]PORT`, which only listens on the loopback
+/// interface unless told otherwise: the endpoint has no authentication.
+fn parse_metrics_address(
+    option: &str,
+    value: Option<String>,
+) -> Result<SocketAddr, Box<dyn Error>> {
+    let value = value.ok_or_else(|| format!("option '{}' requires a value", option))?;
+    match value.parse::<u16>() {
+        Ok(port) => Ok((Ipv4Addr::LOCALHOST, port).into()),
+        Err(_) => value.parse().map_err(|_| {
+            format!(
+                "invalid value '{}' for '{}': not a PORT or IP:PORT",
+                value, option
+            )
+            .into()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, Box<dyn Error>> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn walk_step_range() {
+        assert_eq!(parse(&[]).unwrap().walk_step_range(), (2, 29));
+        assert_eq!(
+            parse(&["--walk-step", "7"]).unwrap().walk_step_range(),
+            (7, 7)
+        );
+        assert_eq!(
+            parse(&["--walk-step-min", "5"]).unwrap().walk_step_range(),
+            (5, 29)
+        );
+        assert_eq!(
+            parse(&["--walk-step-min", "3", "--walk-step-max", "4"])
+                .unwrap()
+                .walk_step_range(),
+            (3, 4)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_walk_steps() {
+        assert!(parse(&["--walk-step", "0"]).is_err());
+        assert!(parse(&["--walk-step-min", "0"]).is_err());
+        assert!(parse(&["--walk-step-min", "10", "--walk-step-max", "9"]).is_err());
+        assert!(parse(&["--walk-step-max", "1"]).is_err());
+        assert!(parse(&["--walk-step", "5", "--walk-step-max", "9"]).is_err());
+        assert!(parse(&["--walk-step", "-1"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_missing_values() {
+        assert!(parse(&["--nope"]).is_err());
+        assert!(parse(&["--edge"]).is_err());
+        assert!(parse(&["--mirror-on-wrap"]).is_err());
+        assert!(parse(&["--edge", "wrap", "--mirror-on-wrap"]).is_ok());
+        assert!(parse(&["--min-size", "10x10"]).is_err());
+    }
+
+    #[test]
+    fn parses_pulse() {
+        let config = parse(&["--pulse", "0.2,1500"]).unwrap();
+        assert_eq!(
+            config.pulse,
+            Some(Pulse {
+                amplitude: 0.2,
+                period_ms: 1500
+            })
+        );
+        for value in ["0.2", "1,1500", "0,1500", "0.2,0", "big,1500"] {
+            assert!(parse(&["--pulse", value]).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_accelerate() {
+        let config = parse(&["--accelerate", "0.25,3"]).unwrap();
+        assert_eq!(
+            config.accelerate,
+            Some(Accelerate {
+                rate: 0.25,
+                max: 3.0
+            })
+        );
+        for value in ["0.25", "0,3", "-1,3", "0.25,1", "0.25,inf", "fast,3"] {
+            assert!(parse(&["--accelerate", value]).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn environment_goes_between_the_scene_and_the_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let scene = dir.path().join("scene.toml");
+        std::fs::write(&scene, "bg = \"stripes\"\nbg_color = \"102030\"\n").unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                (vars.iter())
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        let config = Config::from_env_and_args(
+            env(&[
+                ("WLGOPHER_BG_COLOR", "405060"),
+                ("WLGOPHER_MAX_FPS", "30"),
+                ("WLGOPHER_SPEED", "2.5"),
+                ("WLGOPHER_BG", ""),
+            ]),
+            args(&["--max-fps", "20", "--scene", scene.to_str().unwrap()]),
+        )
+        .unwrap();
+        assert_eq!(config.bg, Background::Stripes);
+        assert_eq!(config.bg_color, Color([0x40, 0x50, 0x60, 0xff]));
+        assert_eq!(config.max_fps, Some(20));
+        assert_eq!(config.speed, 2.5);
+
+        // Checked like the options are, and said which variable it was.
+        let e = Config::from_env_and_args(env(&[("WLGOPHER_SEED", "many")]), args(&[]));
+        assert!(e.err().unwrap().to_string().starts_with("WLGOPHER_SEED: "));
+        let e = Config::from_env_and_args(env(&[("WLGOPHER_SPEED", "-1")]), args(&[]));
+        assert!(e.is_err());
+
+        // Options replace the variables they'd clash with.
+        let config = Config::from_env_and_args(
+            env(&[
+                ("WLGOPHER_WALK_STEP", "5"),
+                ("WLGOPHER_JUMP_HEIGHT_MIN", "50"),
+            ]),
+            args(&["--walk-step-min", "3", "--jump-height-max", "40"]),
+        )
+        .unwrap();
+        assert_eq!(config.walk_step_range(), (3, 29));
+        assert_eq!(config.jump_height_range(), (40, 40));
+        // Still not on the command line alone.
+        let e = Config::from_env_and_args(
+            env(&[]),
+            args(&["--walk-step", "5", "--walk-step-max", "9"]),
+        );
+        assert!(e.is_err());
+
+        // A value that looks like --scene isn't one.
+        let config = Config::from_env_and_args(
+            env(&[("WLGOPHER_BG", "stripes")]),
+            args(&["--frames", "--scene"]),
+        )
+        .unwrap();
+        assert_eq!(config.frames, Some(PathBuf::from("--scene")));
+        assert_eq!(config.bg, Background::Stripes);
+    }
+
+    #[test]
+    fn dumps_the_resolved_options() {
+        let config = parse(&[
+            "--seed",
+            "3",
+            "--vary",
+            "--bg-color",
+            "102030",
+            "--jump-ease",
+            "parabolic",
+            "--transform",
+            "90",
+        ])
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wlgopher.toml");
+        config.dump(&path).unwrap();
+        let dumped: toml::Table = std::fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(dumped["bg_color"].as_str(), Some("102030ff"));
+        assert_eq!(dumped["jump_ease"].as_str(), Some("parabolic"));
+        assert_eq!(dumped["transform"].as_str(), Some("90"));
+        assert_eq!(dumped["vary"].as_bool(), Some(false));
+        assert!(!dumped.contains_key("help"));
+
+        // What the seed picks, rather than the ranges it picks from.
+        let (min, max) = config.walk_step_range();
+        let walk_step = dumped["walk_step"].as_integer().unwrap() as u64;
+        assert!((min..=max).contains(&walk_step));
+        let looks = config.for_gopher(0, &GopherConfig::default());
+        let gopher = &dumped["gophers"].as_array().unwrap()[0];
+        assert_eq!(gopher["scale"].as_float(), Some(looks.scale));
+
+        let path = dir.path().join("wlgopher.json");
+        config.dump(&path).unwrap();
+        let dumped: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(dumped["edge"], "bounce");
+        assert_eq!(dumped["walk_step"], walk_step);
+    }
+
+    #[test]
+    fn reads_back_what_it_dumps() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["dumped.toml", "dumped.json"] {
+            let path = dir.path().join(name);
+            let config = parse(&[
+                "--seed",
+                "3",
+                "--walk-step-min",
+                "4",
+                "--vary",
+                "--outline",
+                "ff0000:2",
+                "--region",
+                "10,20,300x200",
+                "--metrics-port",
+                "9100",
+            ])
+            .unwrap();
+            config.dump(&path).unwrap();
+            let dumped = std::fs::read_to_string(&path).unwrap();
+
+            let loaded = parse(&["--config", path.to_str().unwrap()]).unwrap();
+            let again = dir.path().join(format!("again-{}", name));
+            loaded.dump(&again).unwrap();
+            assert_eq!(std::fs::read_to_string(&again).unwrap(), dumped);
+            // The options still override it.
+            let loaded = parse(&["--max-fps", "20", "--config", path.to_str().unwrap()]).unwrap();
+            assert_eq!(loaded.max_fps, Some(20));
+        }
+
+        // Checked like the options are, edited by hand or not.
+        for (name, text) in [
+            ("c0.toml", "scale = 0.0\n"),
+            ("c1.json", "{\"scale\": -1.0}"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            assert!(parse(&["--config", path.to_str().unwrap()]).is_err());
+        }
+    }
+
+    #[test]
+    fn single_surface_rejects_what_moves_subsurfaces() {
+        assert!(parse(&["--single-surface"]).is_ok());
+        assert!(parse(&["--single-surface", "--desync"]).is_err());
+        assert!(parse(&["--single-surface", "--render-scale", "0.5"]).is_err());
+        assert!(parse(&["--single-surface", "--transform", "90"]).is_err());
+    }
+
+    #[test]
+    fn emit_every_needs_emit_position() {
+        assert!(parse(&["--emit-every", "5"]).is_err());
+        assert!(parse(&["--emit-position", "--emit-every", "0"]).is_err());
+        let config = parse(&["--emit-position", "--emit-every", "5"]).unwrap();
+        assert_eq!((config.emit_position, config.emit_every), (true, 5));
+    }
+
+    #[test]
+    fn metrics_listen_on_loopback_unless_told_otherwise() {
+        let address = |value| parse(&["--metrics-port", value]).map(|c| c.metrics_address);
+        assert_eq!(
+            address("9100").unwrap(),
+            Some("127.0.0.1:9100".parse().unwrap())
+        );
+        assert_eq!(
+            address("0.0.0.0:9100").unwrap(),
+            Some("0.0.0.0:9100".parse().unwrap())
+        );
+        assert!(address("localhost").is_err());
+    }
+
+    #[test]
+    fn terminal_rejects_window_options() {
+        for args in [
+            &["--windowed"][..],
+            &["--lock-pointer"],
+            &["--control", "/tmp/wlgopher.sock"],
+            &["--start-paused"],
+        ] {
+            let err = parse(&[args, &["--terminal"]].concat()).err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                format!("{} can't be combined with --terminal", args[0])
+            );
+        }
+        assert!(parse(&["--terminal", "--edge", "wrap"]).is_ok());
+    }
+
+    #[test]
+    fn modes_that_conflict_are_rejected() {
+        let err = parse(&["--anchor", "top-left", "--edge", "wrap"])
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "--edge can't be combined with --anchor");
+        assert!(parse(&["--hop-on-key", "--lock-pointer"]).is_ok());
+        for mode in MODES {
+            for name in mode.conflicts {
+                let other = MODES.iter().find(|other| other.name == *name).unwrap();
+                assert!(
+                    other.conflicts.contains(&mode.name),
+                    "{} {}",
+                    mode.name,
+                    name
+                );
+            }
+        }
+        assert!(modes_help().contains("wrap    --edge wrap\n"));
+    }
+
+    #[test]
+    fn all_outputs_rejects_what_the_windows_would_share() {
+        assert!(parse(&["--all-outputs"]).is_ok());
+        assert!(parse(&["--all-outputs", "--windowed"]).is_err());
+        assert!(parse(&["--all-outputs", "--control", "/tmp/gopher"]).is_err());
+    }
+
+    #[test]
+    fn rejects_one_key_for_quit_and_pause() {
+        assert!(parse(&["--pause-key", "Escape"]).is_err());
+        assert!(parse(&["--quit-key", "Q", "--pause-key", "q"]).is_err());
+        assert!(parse(&["--pause-key", "esc"]).is_err());
+        assert!(parse(&["--quit-key", "q", "--pause-key", "p"]).is_ok());
+    }
+
+    #[test]
+    fn window_size_limits() {
+        let config = parse(&["--windowed", "--min-size", "300x200"]).unwrap();
+        assert_eq!(config.min_size, Some(Size(300, 200)));
+        assert!(parse(&["--windowed", "--max-size", "300"]).is_err());
+        assert!(parse(&["--windowed", "--min-size", "30x20", "--max-size", "20x20"]).is_err());
+    }
+
+    #[test]
+    fn jump_height_range() {
+        assert_eq!(parse(&[]).unwrap().jump_height_range(), (90, 90));
+        let range = |args: &[&str]| parse(args).unwrap().jump_height_range();
+        assert_eq!(range(&["--jump-height-min", "40"]), (40, 90));
+        assert_eq!(range(&["--jump-height-min", "120"]), (120, 120));
+        assert_eq!(range(&["--jump-height-max", "50"]), (50, 50));
+        assert!(parse(&["--jump-height-min", "60", "--jump-height-max", "50"]).is_err());
+        assert!(parse(&["--jump-height-min", "0"]).is_err());
+    }
+
+    #[test]
+    fn seeded_variety_is_reproducible() {
+        let mut config = parse(&["--vary", "--seed", "7"]).unwrap();
+        let scaled = GopherConfig {
+            scale: Some(2.0),
+            ..GopherConfig::default()
+        };
+        config.gophers = vec![GopherConfig::default(), GopherConfig::default(), scaled];
+
+        let looks = |config: &Config| -> Vec<_> {
+            (config.gophers.iter().enumerate())
+                .map(|(i, gopher)| {
+                    let gopher = config.for_gopher(i, gopher);
+                    (gopher.tint, gopher.scale)
+                })
+                .collect()
+        };
+        let first = looks(&config);
+        assert_eq!(first, looks(&config));
+        assert_ne!(first[0], first[1]);
+        assert_eq!(first[2].1, 2.0);
+    }
+}
@@ -0,0 +1,119 @@
+//! The `--control` socket: a Unix datagram socket other programs send
+//! commands to, one per datagram, such as
+//! `echo activate | socat - UNIX-SENDTO:$path`.
+
+use std::{
+    fs, io,
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::{fs::FileTypeExt, net::UnixDatagram},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Raise and focus the window.
+    Activate,
+    /// Move the gophers somewhere random.
+    Teleport,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "activate" => Ok(Command::Activate),
+            "teleport" => Ok(Command::Teleport),
+            s => Err(format!("unknown control command '{}'", s)),
+        }
+    }
+}
+
+/// The bound socket, which is removed again when dropped.
+pub struct ControlSocket {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Binds to `path`, replacing a socket a previous run left behind but
+    /// nothing else.
+    pub fn bind(path: &Path) -> Result<ControlSocket, String> {
+        let bind = || {
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+                Ok(_) => return Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            let socket = UnixDatagram::bind(path)?;
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        };
+        let socket = bind().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(ControlSocket {
+            socket,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Takes every command waiting on the socket.
+    pub fn commands(&self) -> Vec<Result<Command, String>> {
+        let mut commands = Vec::new();
+        let mut datagram = [0; 256];
+        while let Ok(len) = self.socket.recv(&mut datagram) {
+            commands.push(match std::str::from_utf8(&datagram[..len]) {
+                Ok(text) => text.parse(),
+                Err(_) => Err("control command isn't UTF-8".to_owned()),
+            });
+        }
+        commands
+    }
+}
+
+impl AsFd for ControlSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receives_commands_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let control = ControlSocket::bind(&path).unwrap();
+        assert!(control.commands().is_empty());
+
+        let client = UnixDatagram::unbound().unwrap();
+        client.send_to(b"activate\n", &path).unwrap();
+        client.send_to(b"teleport", &path).unwrap();
+        client.send_to(b"dance", &path).unwrap();
+        assert_eq!(
+            control.commands(),
+            [
+                Ok(Command::Activate),
+                Ok(Command::Teleport),
+                Err("unknown control command 'dance'".to_owned())
+            ]
+        );
+
+        // A second gopher can take over the socket but not any other file.
+        drop(ControlSocket::bind(&path).unwrap());
+        assert!(!path.exists());
+        fs::write(&path, "").unwrap();
+        assert!(ControlSocket::bind(&path).is_err());
+    }
+}
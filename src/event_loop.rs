@@ -0,0 +1,120 @@
+//! Waiting on the Wayland socket together with our own file descriptors.
+
+use std::{
+    error::Error,
+    io::{self, ErrorKind},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    time::Duration,
+};
+
+use wayland_client::{backend::WaylandError, EventQueue};
+
+/// Blocks until either the compositor sends something or one of `fds`
+/// becomes readable, dispatches whatever Wayland events arrived and reports
+/// which of `fds` are ready, in the same order.
+pub fn dispatch<S>(
+    queue: &mut EventQueue<S>,
+    state: &mut S,
+    fds: &[BorrowedFd],
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    if queue.dispatch_pending(state)? > 0 {
+        return Ok(vec![false; fds.len()]);
+    }
+
+    queue.flush()?;
+
+    let guard = match queue.prepare_read() {
+        Some(guard) => guard,
+        None => {
+            queue.dispatch_pending(state)?;
+            return Ok(vec![false; fds.len()]);
+        }
+    };
+
+    let mut pollfds: Vec<_> = std::iter::once(guard.connection_fd())
+        .chain(fds.iter().copied())
+        .map(|fd| libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    loop {
+        let ret = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as _, -1) };
+        if ret >= 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+
+    if pollfds[0].revents != 0 {
+        match guard.read() {
+            Ok(_) => {}
+            Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        drop(guard);
+    }
+    queue.dispatch_pending(state)?;
+
+    Ok(pollfds[1..]
+        .iter()
+        .map(|pollfd| pollfd.revents & libc::POLLIN != 0)
+        .collect())
+}
+
+/// A periodic timer that can be waited on with `dispatch`.
+pub struct Timer(OwnedFd);
+
+impl Timer {
+    pub fn periodic(interval: Duration) -> io::Result<Timer> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let timer = Timer(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let interval = libc::timespec {
+            tv_sec: interval.as_secs() as _,
+            tv_nsec: interval.subsec_nanos() as _,
+        };
+        let spec = libc::itimerspec {
+            it_interval: interval,
+            it_value: interval,
+        };
+        if unsafe { libc::timerfd_settime(fd, 0, &spec, std::ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(timer)
+    }
+
+    /// Consumes the pending expirations so the timer stops being readable
+    /// until it fires again.
+    pub fn acknowledge(&self) {
+        let mut expirations = 0u64;
+        unsafe {
+            libc::read(
+                self.0.as_raw_fd(),
+                &mut expirations as *mut u64 as *mut _,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl AsFd for Timer {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
@@ -0,0 +1,99 @@
+//! `--footprints`: marks a gopher leaves on the ground as it walks, which
+//! fade away over a few seconds.
+
+use std::collections::VecDeque;
+
+use crate::window::ms_since;
+
+/// How far apart the prints are, in surface pixels.
+const SPACING: f64 = 24.0;
+/// How long a print takes to fade away.
+const FADE_MS: u32 = 3000;
+
+/// One print, centered on `at`, a little higher for the left foot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Footprint {
+    pub(crate) at: (f64, f64),
+    pub(crate) left: bool,
+    /// When it was made, on the compositor's clock.
+    made: u32,
+}
+
+/// The prints one gopher left, oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct Trail {
+    prints: VecDeque<Footprint>,
+    /// Where the last print was made, not to make another until the gopher
+    /// is `SPACING` away from it.
+    last_x: Option<f64>,
+}
+
+impl Trail {
+    /// Notes that the gopher's feet are at `feet` at `time`, making a print
+    /// if they're on the ground far enough from the last one.
+    pub(crate) fn step(&mut self, feet: (f64, f64), on_ground: bool, time: u32) {
+        if !on_ground || self.last_x.is_some_and(|x| (feet.0 - x).abs() < SPACING) {
+            return;
+        }
+        let left = !self.prints.back().is_some_and(|print| print.left);
+        self.prints.push_back(Footprint {
+            at: feet,
+            left,
+            made: time,
+        });
+        self.last_x = Some(feet.0);
+    }
+
+    /// Forgets the prints that are gone by `time`.
+    pub(crate) fn fade(&mut self, time: u32) {
+        while (self.prints.front()).is_some_and(|print| ms_since(print.made, time) >= FADE_MS) {
+            self.prints.pop_front();
+        }
+    }
+
+    /// The prints and how much of them is left at `time`, from 1 down.
+    pub(crate) fn prints(&self, time: u32) -> impl Iterator<Item = (Footprint, f32)> + '_ {
+        (self.prints.iter()).map(move |print| {
+            let faded = ms_since(print.made, time).min(FADE_MS) as f32 / FADE_MS as f32;
+            (*print, 1.0 - faded)
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.prints.is_empty()
+    }
+
+    /// Forgets every print, for when the ground they were on has moved.
+    pub(crate) fn clear(&mut self) {
+        self.prints.clear();
+        self.last_x = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_prints_that_fade_away() {
+        let mut trail = Trail::default();
+        trail.step((100.0, 50.0), true, 0);
+        trail.step((110.0, 50.0), true, 100);
+        // Mid-jump, well past the spacing.
+        trail.step((130.0, 20.0), false, 200);
+        trail.step((130.0, 50.0), true, 300);
+        trail.step((120.0, 50.0), true, 400);
+
+        let prints: Vec<_> = trail.prints(1500).collect();
+        assert_eq!(prints.len(), 2);
+        assert_eq!(prints[0].0.at, (100.0, 50.0));
+        assert_eq!(prints[0].1, 0.5);
+        assert_eq!((prints[0].0.left, prints[1].0.left), (true, false));
+        assert_eq!(prints[1].0.at, (130.0, 50.0));
+
+        trail.fade(3000);
+        assert_eq!(trail.prints(3000).count(), 1);
+        trail.fade(3300);
+        assert!(trail.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+//! `--frames` patterns such as `sprites/walk_*.png`, expanded by the C
+//! library's glob(3) and matched with fnmatch(3).
+
+use std::{
+    ffi::{CStr, CString, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Whether `path` has any of the characters glob(3) treats specially.
+pub(crate) fn is_pattern(path: &Path) -> bool {
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .any(|b| matches!(b, b'*' | b'?' | b'['))
+}
+
+/// Every path matching `pattern`, sorted.
+pub(crate) fn expand(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    let c_pattern = CString::new(pattern.as_os_str().as_bytes())
+        .map_err(|_| format!("{}: pattern contains a NUL byte", pattern.display()))?;
+
+    let mut glob: libc::glob_t = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::glob(c_pattern.as_ptr(), 0, None, &mut glob) };
+    let mut paths: Vec<PathBuf> = match result {
+        0 => (0..glob.gl_pathc)
+            .map(|i| {
+                let path = unsafe { CStr::from_ptr(*glob.gl_pathv.add(i)) };
+                PathBuf::from(OsStr::from_bytes(path.to_bytes()))
+            })
+            .collect(),
+        libc::GLOB_NOMATCH => Vec::new(),
+        _ => {
+            unsafe { libc::globfree(&mut glob) };
+            return Err(format!("{}: can't expand the pattern", pattern.display()));
+        }
+    };
+    unsafe { libc::globfree(&mut glob) };
+
+    // glob(3) sorts by the locale, which could put them in another order
+    // than a directory of frames is loaded in.
+    paths.sort();
+    Ok(paths)
+}
+
+/// Whether the file name `name` matches `pattern`.
+pub(crate) fn matches(pattern: &OsStr, name: &OsStr) -> bool {
+    let (Ok(pattern), Ok(name)) = (
+        CString::new(pattern.as_bytes()),
+        CString::new(name.as_bytes()),
+    ) else {
+        return false;
+    };
+    unsafe { libc::fnmatch(pattern.as_ptr(), name.as_ptr(), libc::FNM_PERIOD) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn expands_patterns_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["walk_2.png", "walk_10.png", "walk_1.png", "idle_1.png"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let pattern = dir.path().join("walk_*.png");
+        assert!(is_pattern(&pattern));
+        let names: Vec<_> = (expand(&pattern).unwrap().iter())
+            .map(|path| path.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["walk_1.png", "walk_10.png", "walk_2.png"]);
+        assert!(matches(
+            pattern.file_name().unwrap(),
+            OsStr::new("walk_3.png")
+        ));
+        assert!(!matches(
+            pattern.file_name().unwrap(),
+            OsStr::new("idle_1.png")
+        ));
+
+        assert!(expand(&dir.path().join("run_*.png")).unwrap().is_empty());
+        assert!(!is_pattern(dir.path()));
+    }
+}
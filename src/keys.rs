@@ -0,0 +1,314 @@
+//! Finding the keys `--quit-key`, `--pause-key` and a `--scene`'s
+//! `[keys]` name in the keymap the compositor sends, which is an XKB keymap
+//! in its compiled text form.
+
+use std::collections::VecDeque;
+
+use crate::config::{Action, Config};
+
+/// Keys by keysym name, for when there's no keymap: evdev codes as laid
+/// out on a US keyboard.
+const FALLBACK: &[(&str, u32)] = &[
+    ("escape", 1),
+    ("1", 2),
+    ("2", 3),
+    ("3", 4),
+    ("4", 5),
+    ("5", 6),
+    ("6", 7),
+    ("7", 8),
+    ("8", 9),
+    ("9", 10),
+    ("0", 11),
+    ("backspace", 14),
+    ("tab", 15),
+    ("q", 16),
+    ("w", 17),
+    ("e", 18),
+    ("r", 19),
+    ("t", 20),
+    ("y", 21),
+    ("u", 22),
+    ("i", 23),
+    ("o", 24),
+    ("p", 25),
+    ("return", 28),
+    ("a", 30),
+    ("s", 31),
+    ("d", 32),
+    ("f", 33),
+    ("g", 34),
+    ("h", 35),
+    ("j", 36),
+    ("k", 37),
+    ("l", 38),
+    ("z", 44),
+    ("x", 45),
+    ("c", 46),
+    ("v", 47),
+    ("b", 48),
+    ("n", 49),
+    ("m", 50),
+    ("comma", 51),
+    ("period", 52),
+    ("space", 57),
+    ("up", 103),
+    ("left", 105),
+    ("right", 106),
+    ("down", 108),
+];
+
+/// Evdev codes are XKB keycodes minus this.
+const EVDEV_OFFSET: u32 = 8;
+
+/// Puts a key name the way it's looked up: the unshifted keysym, in lower
+/// case, with a few common names for keys whose keysym is called otherwise.
+pub(crate) fn normalize(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "esc" => "escape".to_owned(),
+        "enter" => "return".to_owned(),
+        _ => name,
+    }
+}
+
+/// Which evdev key code produces which keysym, without modifiers.
+pub(crate) struct Keymap {
+    keys: Vec<(String, u32)>,
+}
+
+impl Keymap {
+    /// A US layout, until the compositor sends something better.
+    pub(crate) fn fallback() -> Keymap {
+        Keymap {
+            keys: (FALLBACK.iter())
+                .map(|&(name, code)| (name.to_owned(), code))
+                .collect(),
+        }
+    }
+
+    /// Reads the `xkb_keycodes` and `xkb_symbols` sections of a keymap,
+    /// taking the first keysym of each key's first group.
+    pub(crate) fn parse(text: &str) -> Keymap {
+        let section = |name: &str| {
+            let rest = text.find(name).map_or("", |at| &text[at + name.len()..]);
+            &rest[..rest.find("xkb_").unwrap_or(rest.len())]
+        };
+
+        // Lines like `<ESC> = 9;` and `alias <KPSP> = <SPCE>;`.
+        let mut codes = Vec::new();
+        let mut aliases = Vec::new();
+        for statement in section("xkb_keycodes").split(';') {
+            // The first one comes after the section's opening brace.
+            let statement = statement.rsplit('{').next().unwrap_or(statement);
+            let Some((left, right)) = statement.split_once('=') else {
+                continue;
+            };
+            let (left, right) = (left.trim(), right.trim());
+            if let Some(alias) = left.strip_prefix("alias") {
+                aliases.push((alias.trim(), right));
+            } else if let (true, Ok(code)) = (left.starts_with('<'), right.parse::<u32>()) {
+                codes.push((left, code));
+            }
+        }
+
+        // Either `key <ESC> { [ Escape ] };` or, with a type or actions,
+        // `key <CAPS> { type= "ONE_LEVEL", symbols[Group1]= [ Escape ] };`.
+        let mut keys = Vec::new();
+        for key in section("xkb_symbols").split("key ").skip(1) {
+            let Some((name, body)) = key.split_once('{') else {
+                continue;
+            };
+            let body = match body.split_once("symbols[Group1]=") {
+                Some((_, symbols)) => symbols.trim_start(),
+                None => body.trim_start(),
+            };
+            let Some(keysym) = body
+                .strip_prefix('[')
+                .and_then(|levels| levels.split([',', ']']).next())
+                .map(str::trim)
+                .filter(|keysym| !keysym.is_empty())
+            else {
+                continue;
+            };
+
+            let name = name.trim();
+            let name = (aliases.iter())
+                .find(|(alias, _)| *alias == name)
+                .map_or(name, |&(_, real)| real);
+            if let Some(&(_, code)) = codes.iter().find(|(key, _)| *key == name) {
+                if code >= EVDEV_OFFSET {
+                    keys.push((normalize(keysym), code - EVDEV_OFFSET));
+                }
+            }
+        }
+        // The lowest code wins when several keys make the same keysym.
+        keys.sort_by_key(|&(_, code)| code);
+
+        Keymap { keys }
+    }
+
+    /// The evdev code of the key named `name`, as normalized.
+    pub(crate) fn code(&self, name: &str) -> Option<u32> {
+        (self.keys.iter())
+            .find(|(key, _)| key == name)
+            .map(|&(_, code)| code)
+    }
+}
+
+/// Which key does what, as evdev codes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Bindings(Vec<(u32, Action)>);
+
+impl Bindings {
+    /// Finds the keys in `keymap`, warning about the ones it lacks if
+    /// `warn` is set.
+    pub(crate) fn new(keymap: &Keymap, config: &Config, warn: bool) -> Bindings {
+        let mut bindings = Vec::new();
+        for action in Action::ALL {
+            let Some(name) = action.key(config) else {
+                continue;
+            };
+            match keymap.code(&normalize(name)) {
+                Some(code) => bindings.push((code, action)),
+                None if warn => eprintln!(
+                    "wlgopher: warning: the keyboard layout has no key for {} '{}'",
+                    action.option(),
+                    name
+                ),
+                None => {}
+            }
+        }
+        Bindings(bindings)
+    }
+
+    /// What pressing `key` does.
+    pub(crate) fn action(&self, key: u32) -> Option<Action> {
+        (self.0.iter())
+            .find(|&&(code, _)| code == key)
+            .map(|&(_, action)| action)
+    }
+}
+
+/// Watches the keys pressed for the `--cheat-code` sequence.
+pub(crate) struct CheatCode {
+    sequence: Vec<u32>,
+    /// The last presses, as many as there are keys in the sequence.
+    recent: VecDeque<u32>,
+}
+
+impl CheatCode {
+    /// Finds the sequence's keys in `keymap`. If any of them are missing
+    /// nothing matches, with a warning if `warn` is set.
+    pub(crate) fn new(keymap: &Keymap, config: &Config, warn: bool) -> CheatCode {
+        let names = config.cheat_code.as_deref().unwrap_or_default();
+        let sequence: Option<Vec<_>> = (names.iter())
+            .map(|name| keymap.code(&normalize(name)))
+            .collect();
+        if sequence.is_none() && warn {
+            eprintln!(
+                "wlgopher: warning: the keyboard layout doesn't have every key of --cheat-code '{}'",
+                names.join(",")
+            );
+        }
+        CheatCode {
+            sequence: sequence.unwrap_or_default(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Adds a press of `key`, returning true if it completes the sequence.
+    pub(crate) fn press(&mut self, key: u32) -> bool {
+        if self.sequence.is_empty() {
+            return false;
+        }
+        if self.recent.len() == self.sequence.len() {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(key);
+        if self.recent.iter().eq(&self.sequence) {
+            self.recent.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Forgets the presses so far, for a key the sequence can't go on from.
+    pub(crate) fn reset(&mut self) {
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keys_by_keysym() {
+        let keymap = Keymap::parse(
+            "xkb_keymap {
+xkb_keycodes \"evdev+aliases(qwerty)\" {
+	minimum = 8;
+	maximum = 255;
+	<ESC>                = 9;
+	<AC01>               = 38;
+	<AE01>               = 10;
+	<SPCE>               = 65;
+	<CAPS>               = 66;
+	alias <KPSP>         = <SPCE>;
+};
+xkb_types \"complete\" {
+	type \"ONE_LEVEL\" {
+		modifiers= none;
+	};
+};
+xkb_symbols \"pc+fr\" {
+	name[Group1]=\"French\";
+	key <ESC>                {	[          Escape ] };
+	key <AE01>               {	[       ampersand,               1 ] };
+	key <AC01>               {	[               q,               Q ] };
+	key <CAPS>               {
+		type= \"ONE_LEVEL\",
+		symbols[Group1]= [          Escape ]
+	};
+	key <KPSP>               {	[           space ] };
+};
+};",
+        );
+        assert_eq!(keymap.code(&normalize("Escape")), Some(1));
+        assert_eq!(keymap.code(&normalize("Q")), Some(30));
+        assert_eq!(keymap.code(&normalize("ampersand")), Some(2));
+        assert_eq!(keymap.code(&normalize("Space")), Some(57));
+        assert_eq!(keymap.code(&normalize("1")), None);
+
+        assert_eq!(Keymap::fallback().code(&normalize("esc")), Some(1));
+    }
+
+    #[test]
+    fn cheat_code_matches_the_last_presses() {
+        let config = Config {
+            cheat_code: Some(vec!["up".to_owned(), "up".to_owned(), "b".to_owned()]),
+            ..Config::default()
+        };
+        let mut cheat = CheatCode::new(&Keymap::fallback(), &config, false);
+        let presses: Vec<_> = [103, 103, 103, 48, 48, 103, 103, 48]
+            .into_iter()
+            .map(|key| cheat.press(key))
+            .collect();
+        assert_eq!(
+            presses,
+            [false, false, false, true, false, false, false, true]
+        );
+
+        cheat.press(103);
+        cheat.press(103);
+        cheat.reset();
+        assert!(!cheat.press(48));
+
+        let config = Config {
+            cheat_code: None,
+            ..Config::default()
+        };
+        assert!(!CheatCode::new(&Keymap::fallback(), &config, false).press(1));
+    }
+}
@@ -0,0 +1,175 @@
+//! A gopher walking along the bottom of a Wayland window.
+//!
+//! [`Gopher`] sets one up and runs it until its window is closed, either on
+//! a connection of its own or on one the embedding application already has:
+//!
+//! ```no_run
+//! wlgopher::Gopher::new()
+//!     .with_frames("frames/")
+//!     .with_speed(1.5)
+//!     .run()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Prints a request sent or an event received to stderr if
+/// `--trace-protocol` was given.
+macro_rules! trace {
+    ($config:expr, $($arg:tt)*) => {
+        if $config.trace_protocol {
+            eprintln!("wlgopher: trace: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Prints a diagnostic to stderr if `--verbose` was given.
+macro_rules! verbose {
+    ($config:expr, $($arg:tt)*) => {
+        if $config.verbose {
+            eprintln!("wlgopher: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+mod animation;
+mod background;
+pub mod config;
+mod control;
+mod event_loop;
+mod footprints;
+mod glob;
+mod keys;
+mod metrics;
+mod overlay;
+mod pool;
+mod profile;
+mod quirks;
+mod raw_pipe;
+mod record;
+mod remember;
+mod scene;
+mod sound;
+mod stacking;
+mod terminal;
+mod watch;
+mod window;
+
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use wayland_client::Connection;
+use wayland_protocols::xdg::shell::client::xdg_toplevel::XdgToplevel;
+
+use animation::{build_animation, check_frames};
+use config::Config;
+
+/// A walking gopher, configured with the `with_*` methods and started with
+/// [`run`](Gopher::run).
+#[derive(Default)]
+pub struct Gopher {
+    config: Config,
+    connection: Option<Connection>,
+    parent: Option<XdgToplevel>,
+}
+
+impl Gopher {
+    /// A gopher with the same defaults as the `wlgopher` binary.
+    pub fn new() -> Gopher {
+        Gopher::default()
+    }
+
+    /// A gopher set up from a full [`Config`], such as one parsed from the
+    /// command line.
+    pub fn from_config(config: Config) -> Gopher {
+        Gopher {
+            config,
+            connection: None,
+            parent: None,
+        }
+    }
+
+    /// Loads the frames from a directory of images, an animated GIF or a
+    /// single image instead of the built-in gopher.
+    pub fn with_frames(mut self, path: impl Into<PathBuf>) -> Gopher {
+        self.config.frames = Some(path.into());
+        self
+    }
+
+    /// Scales how fast the gopher walks; 1.0 is the normal speed.
+    pub fn with_speed(mut self, speed: f64) -> Gopher {
+        self.config.speed = speed;
+        self
+    }
+
+    /// Opens the window on `connection` rather than connecting to
+    /// `$WAYLAND_DISPLAY`. The gopher gets an event queue of its own, so
+    /// this is fine to call from a thread other than the application's.
+    pub fn with_connection(mut self, connection: Connection) -> Gopher {
+        self.connection = Some(connection);
+        self
+    }
+
+    /// Opens the window as a transient child of `parent`, one of the
+    /// application's own toplevels, so the compositor keeps it above that
+    /// window and may place it relative to it. `parent` has to live on the
+    /// connection given to [`with_connection`](Gopher::with_connection);
+    /// windows of other clients can't be referred to this way, only through
+    /// a handle they exported, as the binary's `--parent` takes.
+    pub fn with_parent(mut self, parent: XdgToplevel) -> Gopher {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Renders `frames` frames into memory, stepping the animation between
+    /// them as [`run`](Gopher::run) would, without connecting to a
+    /// compositor. Returns how long that took, which is how long the
+    /// options given make drawing on the CPU.
+    pub fn bench(self, frames: u64) -> Result<Duration, Box<dyn Error>> {
+        self.config.validate()?;
+        window::bench(self.config, frames)
+    }
+
+    /// Shows the gopher and blocks until its window is closed.
+    pub fn run(self) -> Result<(), Box<dyn Error>> {
+        self.config.validate()?;
+
+        if let Some(path) = self.config.dump_config.as_deref() {
+            return self.config.dump(path);
+        }
+
+        if self.config.check_frames {
+            return check_frames(&self.config);
+        }
+
+        if self.config.dry_run {
+            if !self.config.terminal {
+                return window::dry_run(self.config);
+            }
+            // The terminal has nothing to set up beyond the animation, so
+            // there are no buffers to tell about.
+            let animation = build_animation(&self.config, 0)?;
+            println!("frames: {}", animation.frame_count());
+            println!("walk step: {} pixels per tick", animation.walk_step());
+            return Ok(());
+        }
+
+        if self.config.terminal {
+            return terminal::run(build_animation(&self.config, 0)?);
+        }
+
+        let connection = match self.connection {
+            Some(connection) => connection,
+            None if self.parent.is_some() => {
+                return Err(
+                    "with_parent requires the parent's connection in with_connection".into(),
+                )
+            }
+            None => window::connect(self.config.wayland_display.as_deref())?,
+        };
+        if self.config.all_outputs {
+            if self.parent.is_some() {
+                return Err("with_parent can't be combined with --all-outputs".into());
+            }
+            return window::run_all_outputs(&connection, self.config);
+        }
+        window::run(&connection, self.config, self.parent)
+    }
+}
@@ -0,0 +1,199 @@
+//! The `--metrics-port` endpoint, serving how the animation is doing in
+//! Prometheus' text format, only available with the `metrics` cargo feature.
+
+use std::error::Error;
+
+#[cfg(feature = "metrics")]
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// The figures the window updates as it draws and the server thread reads.
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+struct Counters {
+    /// The FPS over the last 5 seconds, as the bits of an `f64`.
+    fps: AtomicU64,
+    frames: AtomicU64,
+    dropped_frames: AtomicU64,
+    crossings: AtomicU64,
+    started: Instant,
+}
+
+#[cfg(feature = "metrics")]
+impl Counters {
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for (name, kind, help, value) in [
+            (
+                "wlgopher_fps",
+                "gauge",
+                "Frames drawn per second over the last 5 seconds.",
+                f64::from_bits(self.fps.load(Ordering::Relaxed)),
+            ),
+            (
+                "wlgopher_frames_total",
+                "counter",
+                "Frames drawn.",
+                self.frames.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "wlgopher_dropped_frames_total",
+                "counter",
+                "Frames drawn that never made it to the screen.",
+                self.dropped_frames.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "wlgopher_crossings_total",
+                "counter",
+                "Times a gopher made it across the window.",
+                self.crossings.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "wlgopher_uptime_seconds",
+                "gauge",
+                "Seconds since wlgopher started.",
+                self.started.elapsed().as_secs_f64(),
+            ),
+        ] {
+            text += &format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n");
+        }
+        text
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub struct Metrics(Arc<Counters>);
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Listens on `address`, answering scrapes on a thread of its own for
+    /// as long as wlgopher runs.
+    pub fn serve(address: SocketAddr) -> Result<Metrics, Box<dyn Error>> {
+        let listener =
+            TcpListener::bind(address).map_err(|e| format!("--metrics-port {}: {}", address, e))?;
+        Ok(Metrics::start(listener))
+    }
+
+    fn start(listener: TcpListener) -> Metrics {
+        let counters = Arc::new(Counters {
+            fps: AtomicU64::new(0f64.to_bits()),
+            frames: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            crossings: AtomicU64::new(0),
+            started: Instant::now(),
+        });
+        let shared = counters.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream, &shared) {
+                    eprintln!("wlgopher: warning: --metrics-port: {}", e);
+                }
+            }
+        });
+        Metrics(counters)
+    }
+
+    pub fn set_fps(&self, fps: f64) {
+        self.0.fps.store(fps.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn frame_drawn(&self) {
+        self.0.frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_dropped(&self) {
+        self.0.dropped_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn crossed(&self) {
+        self.0.crossings.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Answers one HTTP request: the metrics for `GET /metrics`, 404 for
+/// anything else.
+#[cfg(feature = "metrics")]
+fn respond(mut stream: TcpStream, counters: &Counters) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+    // Only the request line matters, but read the headers so the client
+    // isn't cut off mid-request.
+    let mut request = Vec::new();
+    let mut chunk = [0; 1024];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < 8192 {
+        match stream.read(&mut chunk)? {
+            0 => break,
+            len => request.extend_from_slice(&chunk[..len]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", counters.render()),
+        _ => ("404 Not Found", "Try /metrics\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(not(feature = "metrics"))]
+pub struct Metrics;
+
+#[cfg(not(feature = "metrics"))]
+impl Metrics {
+    pub fn serve(_: std::net::SocketAddr) -> Result<Metrics, Box<dyn Error>> {
+        Err("--metrics-port needs wlgopher to be built with the 'metrics' feature".into())
+    }
+
+    pub fn set_fps(&self, _: f64) {}
+
+    pub fn frame_drawn(&self) {}
+
+    pub fn frame_dropped(&self) {}
+
+    pub fn crossed(&self) {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_the_counters_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let metrics = Metrics::start(listener);
+        metrics.set_fps(59.5);
+        metrics.frame_drawn();
+        metrics.frame_drawn();
+        metrics.frame_dropped();
+        metrics.crossed();
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        assert!(get("/").starts_with("HTTP/1.1 404 "));
+        let text = get("/metrics");
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(text.contains("# TYPE wlgopher_fps gauge\nwlgopher_fps 59.5\n"));
+        assert!(text.contains("\nwlgopher_frames_total 2\n"));
+        assert!(text.contains("\nwlgopher_dropped_frames_total 1\n"));
+        assert!(text.contains("\nwlgopher_crossings_total 1\n"));
+        assert!(text.contains("\nwlgopher_uptime_seconds "));
+    }
+}
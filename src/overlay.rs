@@ -0,0 +1,99 @@
+//! `--debug-overlay`: lines of text drawn with a tiny built-in font.
+
+/// Each font pixel is this many buffer pixels across.
+const SCALE: u32 = 2;
+/// Glyphs are 3x5 with a pixel of space to the right and below.
+const ADVANCE: (u32, u32) = (4 * SCALE, 6 * SCALE);
+/// Space between the text and the edge of its backdrop.
+const BORDER: u32 = 2 * SCALE;
+
+/// The rows of `c`, top to bottom, with the leftmost pixel in the 0b100 bit.
+/// Letters all look upper case, and anything unknown is blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_lowercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        _ => [0; 5],
+    }
+}
+
+/// The buffer size that fits `lines` lines of up to `columns` characters.
+pub(crate) fn text_size(columns: usize, lines: usize) -> (u32, u32) {
+    (
+        columns as u32 * ADVANCE.0 + BORDER * 2,
+        lines as u32 * ADVANCE.1 + BORDER * 2,
+    )
+}
+
+/// Draws `lines` in white on a translucent black backdrop into `buffer`, an
+/// ARGB8888 image `width` pixels wide. Whatever doesn't fit is cut off.
+pub(crate) fn draw_text(buffer: &mut [u8], width: u32, lines: &[String]) {
+    for pixel in buffer.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[0, 0, 0, 0xa0]);
+    }
+    let height = buffer.len() as u32 / 4 / width;
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = BORDER + row as u32 * ADVANCE.1;
+        for (column, c) in line.chars().enumerate() {
+            let left = BORDER + column as u32 * ADVANCE.0;
+            for (dy, bits) in glyph(c).into_iter().enumerate() {
+                for dx in 0..3 {
+                    if bits & (0b100 >> dx) == 0 {
+                        continue;
+                    }
+                    for (sx, sy) in (0..SCALE).flat_map(|sx| (0..SCALE).map(move |sy| (sx, sy))) {
+                        let x = left + dx * SCALE + sx;
+                        let y = top + dy as u32 * SCALE + sy;
+                        if x < width && y < height {
+                            let at = ((y * width + x) * 4) as usize;
+                            buffer[at..at + 4].copy_from_slice(&[0xff; 4]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Where each buffer goes in the shm pool.
+
+use std::ops::Range;
+
+/// Buffers start on a pixel boundary.
+const ALIGN: usize = 4;
+
+/// Lays buffers out one after the other in the pool, keeping track of how
+/// much of it they take up. The pool itself is grown to fit by its owner.
+#[derive(Debug, Default)]
+pub(crate) struct PoolAllocator {
+    end: usize,
+}
+
+impl PoolAllocator {
+    pub(crate) fn new() -> PoolAllocator {
+        PoolAllocator::default()
+    }
+
+    /// `len` bytes after every range handed out so far.
+    pub(crate) fn alloc(&mut self, len: usize) -> Range<usize> {
+        let start = self.end.next_multiple_of(ALIGN);
+        self.end = start + len;
+        start..self.end
+    }
+
+    /// How far into the pool the ranges go, which is as big as it has to be.
+    pub(crate) fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Hands out the pool from `end` on again, for buffers that replace the
+    /// ones there. Ranges from before stay where they are.
+    pub(crate) fn truncate(&mut self, end: usize) {
+        assert!(end <= self.end, "truncating the pool past its end");
+        self.end = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_follow_each_other_without_overlapping() {
+        let mut pool = PoolAllocator::new();
+        assert_eq!(pool.alloc(4), 0..4);
+        assert_eq!(pool.alloc(10), 4..14);
+        // Rounded up to the next pixel.
+        assert_eq!(pool.alloc(8), 16..24);
+        assert_eq!(pool.end(), 24);
+
+        pool.truncate(14);
+        assert_eq!(pool.alloc(2), 16..18);
+        pool.truncate(4);
+        assert_eq!(pool.alloc(20), 4..24);
+        assert_eq!(pool.end(), 24);
+    }
+}
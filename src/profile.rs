@@ -0,0 +1,78 @@
+//! `--profile`: where the time drawing a frame goes, added up between two
+//! FPS reports and printed as averages per frame.
+
+use std::time::Duration;
+
+/// The parts of a draw that are timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    /// Copying pixels: the frames into the gophers' buffers, and the
+    /// background or the gophers into the window's.
+    Blit,
+    /// Moving the animations on and working out where the gophers go.
+    Position,
+    /// Attaching buffers, placing subsurfaces and committing.
+    Commit,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Profile {
+    blit: Duration,
+    position: Duration,
+    commit: Duration,
+    frames: u32,
+}
+
+impl Profile {
+    pub(crate) fn add(&mut self, phase: Phase, elapsed: Duration) {
+        *match phase {
+            Phase::Blit => &mut self.blit,
+            Phase::Position => &mut self.position,
+            Phase::Commit => &mut self.commit,
+        } += elapsed;
+    }
+
+    pub(crate) fn frame_drawn(&mut self) {
+        self.frames += 1;
+    }
+
+    /// The average time per frame drawn in each phase so far, starting
+    /// over for the next report.
+    pub(crate) fn report(&mut self) -> String {
+        let profile = std::mem::take(self);
+        let average = |total: Duration| match profile.frames {
+            0 => 0.0,
+            frames => total.as_secs_f64() * 1000.0 / frames as f64,
+        };
+        format!(
+            "per frame: blit {:.3} ms, position {:.3} ms, commit {:.3} ms",
+            average(profile.blit),
+            average(profile.position),
+            average(profile.commit)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_averages_and_starts_over() {
+        let mut profile = Profile::default();
+        for _ in 0..4 {
+            profile.add(Phase::Blit, Duration::from_micros(1500));
+            profile.add(Phase::Commit, Duration::from_micros(250));
+            profile.frame_drawn();
+        }
+        profile.add(Phase::Position, Duration::from_micros(100));
+        assert_eq!(
+            profile.report(),
+            "per frame: blit 1.500 ms, position 0.025 ms, commit 0.250 ms"
+        );
+        assert_eq!(
+            profile.report(),
+            "per frame: blit 0.000 ms, position 0.000 ms, commit 0.000 ms"
+        );
+    }
+}
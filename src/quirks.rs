@@ -0,0 +1,145 @@
+//! Workarounds for how particular compositors behave, which
+//! `--compositor-quirks` picks by name or from what the compositor looks
+//! like.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compositor {
+    Sway,
+    Gnome,
+    Kde,
+}
+
+impl Compositor {
+    /// Guesses the compositor from the globals it announced, some of which
+    /// only one compositor has, and failing that from `desktop`, the value
+    /// of `XDG_CURRENT_DESKTOP`.
+    pub(crate) fn detect<'a>(
+        globals: impl IntoIterator<Item = &'a str>,
+        desktop: Option<&str>,
+    ) -> Option<Compositor> {
+        for global in globals {
+            if global == "gtk_shell1" {
+                return Some(Compositor::Gnome);
+            }
+            if global.starts_with("org_kde_") {
+                return Some(Compositor::Kde);
+            }
+        }
+        // A colon-separated list, such as "ubuntu:GNOME".
+        desktop?
+            .split(':')
+            .find_map(|name| name.to_lowercase().parse().ok())
+    }
+
+    /// The workarounds this compositor needs.
+    pub(crate) fn quirks(self) -> Quirks {
+        match self {
+            Compositor::Sway => Quirks::default(),
+            Compositor::Gnome | Compositor::Kde => Quirks {
+                commit_after_fullscreen: true,
+            },
+        }
+    }
+}
+
+impl FromStr for Compositor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sway" => Ok(Compositor::Sway),
+            "gnome" => Ok(Compositor::Gnome),
+            "kde" | "kwin" => Ok(Compositor::Kde),
+            _ => Err(format!("unknown compositor '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Compositor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compositor::Sway => "sway",
+            Compositor::Gnome => "gnome",
+            Compositor::Kde => "kde",
+        })
+    }
+}
+
+/// Which compositor's workarounds to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuirksMode {
+    /// Whichever compositor it looks like we're running on.
+    #[default]
+    Auto,
+    None,
+    Compositor(Compositor),
+}
+
+impl FromStr for QuirksMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(QuirksMode::Auto),
+            "none" => Ok(QuirksMode::None),
+            s => Ok(QuirksMode::Compositor(s.parse()?)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Quirks {
+    /// Commit the window again right after acking the configure that makes
+    /// it fullscreen. Mutter and KWin only settle on the fullscreen size
+    /// once a commit follows the ack, and otherwise that waits for the
+    /// next frame to be drawn.
+    pub(crate) commit_after_fullscreen: bool,
+}
+
+impl Quirks {
+    /// The workarounds in use, for `--verbose`.
+    pub(crate) fn names(self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.commit_after_fullscreen {
+            names.push("commit after fullscreen");
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_from_globals_before_the_desktop() {
+        let globals = ["wl_compositor", "gtk_shell1", "xdg_wm_base"];
+        assert_eq!(
+            Compositor::detect(globals, Some("KDE")),
+            Some(Compositor::Gnome)
+        );
+        assert_eq!(
+            Compositor::detect(["org_kde_plasma_shell"], None),
+            Some(Compositor::Kde)
+        );
+        assert_eq!(
+            Compositor::detect(["wl_compositor"], Some("ubuntu:GNOME")),
+            Some(Compositor::Gnome)
+        );
+        assert_eq!(
+            Compositor::detect(["wl_compositor"], Some("sway")),
+            Some(Compositor::Sway)
+        );
+        assert_eq!(
+            Compositor::detect(["wl_compositor"], Some("Hyprland")),
+            None
+        );
+        assert_eq!(Compositor::detect([], None), None);
+    }
+}
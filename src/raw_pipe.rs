@@ -0,0 +1,138 @@
+//! `--pipe-raw`: the gopher's frames as raw RGBA bytes on a FIFO, for
+//! something like `ffmpeg -f rawvideo` to record.
+//!
+//! Writes never block the animation. Until a reader opens the FIFO frames
+//! are dropped, and while the reader is still busy with one frame the next
+//! ones are dropped rather than interleaved, so it only ever sees whole
+//! frames.
+
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+pub struct RawPipe {
+    path: PathBuf,
+    /// Open while a reader is there.
+    file: Option<File>,
+    /// The frame being written and how much of it the reader has taken.
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl RawPipe {
+    /// Creates the FIFO at `path` unless something is there already.
+    pub fn new(path: &Path) -> Result<RawPipe, String> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| format!("{}: path contains a NUL byte", path.display()))?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        Ok(RawPipe {
+            path: path.to_owned(),
+            file: None,
+            pending: Vec::new(),
+            written: 0,
+        })
+    }
+
+    /// Hands a frame to the reader, if there is one and it's done with the
+    /// previous frame. `fill` appends the frame's bytes to the buffer it's
+    /// given and is only called when the frame is going to be written.
+    pub fn write_frame(&mut self, fill: impl FnOnce(&mut Vec<u8>)) {
+        self.flush();
+        if self.file.is_none() || !self.pending.is_empty() {
+            return;
+        }
+        fill(&mut self.pending);
+        self.flush();
+    }
+
+    /// Writes as much of the pending frame as the reader takes right now.
+    fn flush(&mut self) {
+        if self.file.is_none() {
+            // Opening a FIFO without blocking fails with ENXIO until it
+            // has a reader, in which case just try again next frame.
+            match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+            {
+                Ok(file) => self.file = Some(file),
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => return,
+                Err(e) => {
+                    eprintln!("wlgopher: warning: {}: {}", self.path.display(), e);
+                    return;
+                }
+            }
+        }
+
+        let file = self.file.as_mut().unwrap();
+        while self.written < self.pending.len() {
+            match file.write(&self.pending[self.written..]) {
+                Ok(len) => self.written += len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    // The reader went away, most likely. Whoever opens the
+                    // FIFO next starts with a fresh frame.
+                    if e.kind() != io::ErrorKind::BrokenPipe {
+                        eprintln!("wlgopher: warning: {}: {}", self.path.display(), e);
+                    }
+                    self.file = None;
+                    break;
+                }
+            }
+        }
+        self.pending.clear();
+        self.written = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn drops_frames_instead_of_blocking() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames");
+        let mut pipe = RawPipe::new(&path).unwrap();
+
+        // Nobody is reading yet.
+        pipe.write_frame(|_| panic!("frame built without a reader"));
+
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        // Far more than the FIFO holds, so the first frame stays pending
+        // and the second is dropped.
+        let frame_len = 1 << 20;
+        pipe.write_frame(|buf| buf.resize(frame_len, 1));
+        pipe.write_frame(|_| panic!("frame built while the last one is pending"));
+
+        let mut received = Vec::new();
+        while received.len() < frame_len {
+            let mut chunk = vec![0; 65536];
+            match reader.read(&mut chunk) {
+                Ok(len) => received.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+            pipe.write_frame(|buf| buf.resize(frame_len, 2));
+        }
+        // The loop may already have queued the next frame behind it.
+        assert_eq!(received[..frame_len], vec![1; frame_len]);
+        assert!(received[frame_len..].iter().all(|&b| b == 2));
+    }
+}
@@ -0,0 +1,163 @@
+//! `--record-path`: the gopher's frames saved as an animated GIF or APNG
+//! when the run ends or `--record-seconds` have been recorded.
+//!
+//! The frames are kept in memory until then, so frames bigger than
+//! `MAX_SIDE` are scaled down to keep a long recording from piling up.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+    Delay, Frame, RgbaImage,
+};
+
+/// The longest side a recorded frame is kept at.
+const MAX_SIDE: u32 = 320;
+
+/// How long the last frame stays up, having nothing after it to go by.
+const LAST_FRAME_MS: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Gif,
+    Apng,
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    format: Format,
+    limit_ms: u32,
+    /// Each frame with the frame time it was shown at.
+    frames: Vec<(RgbaImage, u32)>,
+}
+
+impl Recorder {
+    /// Records up to `seconds` into `path`, a GIF or an APNG going by its
+    /// extension.
+    pub fn new(path: &Path, seconds: u32) -> Result<Recorder, String> {
+        let extension = path.extension().and_then(|e| e.to_str());
+        let format = match extension.map(str::to_lowercase).as_deref() {
+            Some("gif") => Format::Gif,
+            Some("png" | "apng") => Format::Apng,
+            _ => {
+                return Err(format!(
+                    "{}: can only record to a .gif, .png or .apng file",
+                    path.display()
+                ))
+            }
+        };
+        Ok(Recorder {
+            path: path.to_owned(),
+            format,
+            limit_ms: seconds * 1000,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Adds the RGBA frame `pixels`, `size` in pixels, shown at `time`.
+    /// Returns false once the recording is as long as it gets, or if the
+    /// frame's size isn't that of the ones before it, which neither format
+    /// takes; the frame isn't added then.
+    pub fn add_frame(&mut self, time: u32, size: (u32, u32), pixels: Vec<u8>) -> bool {
+        let Some(image) = RgbaImage::from_raw(size.0, size.1, pixels) else {
+            return false;
+        };
+        let image = match size.0.max(size.1) {
+            side if side > MAX_SIDE => {
+                let (width, height) = (
+                    (size.0 * MAX_SIDE / side).max(1),
+                    (size.1 * MAX_SIDE / side).max(1),
+                );
+                imageops::resize(&image, width, height, FilterType::Nearest)
+            }
+            _ => image,
+        };
+
+        if let Some((first, start)) = self.frames.first() {
+            if first.dimensions() != image.dimensions()
+                || time.wrapping_sub(*start) >= self.limit_ms
+            {
+                return false;
+            }
+        }
+        self.frames.push((image, time));
+        true
+    }
+
+    /// Writes the file, returning where it went and how many frames it has.
+    pub fn save(self) -> Result<(PathBuf, usize), Box<dyn Error>> {
+        let count = self.frames.len();
+        if count == 0 {
+            return Err(format!("{}: no frames to record", self.path.display()).into());
+        }
+        let delays: Vec<u32> = (self.frames.windows(2))
+            .map(|pair| pair[1].1.wrapping_sub(pair[0].1))
+            .chain([LAST_FRAME_MS])
+            .collect();
+        let file = BufWriter::new(
+            File::create(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))?,
+        );
+
+        match self.format {
+            Format::Gif => {
+                let mut encoder = GifEncoder::new(file);
+                encoder.set_repeat(Repeat::Infinite)?;
+                encoder.encode_frames(self.frames.into_iter().zip(delays).map(
+                    |((image, _), ms)| {
+                        Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(ms, 1))
+                    },
+                ))?;
+            }
+            Format::Apng => {
+                let (width, height) = self.frames[0].0.dimensions();
+                let mut encoder = png::Encoder::new(file, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(count as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for ((image, _), ms) in self.frames.iter().zip(delays) {
+                    writer.set_frame_delay(ms.min(u16::MAX as u32) as u16, 1000)?;
+                    writer.write_image_data(image.as_raw())?;
+                }
+                writer.finish()?;
+            }
+        }
+        Ok((self.path, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_until_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Recorder::new(&dir.path().join("run.mp4"), 1).is_err());
+
+        for name in ["run.gif", "run.png"] {
+            let path = dir.path().join(name);
+            let mut recorder = Recorder::new(&path, 1).unwrap();
+            for (i, time) in [0, 400, 800].into_iter().enumerate() {
+                let pixel = [i as u8 * 100, 0, 0, 0xff];
+                assert!(recorder.add_frame(time, (2, 3), pixel.repeat(6)));
+            }
+            assert!(!recorder.add_frame(900, (3, 2), vec![0; 24]));
+            assert!(!recorder.add_frame(1000, (2, 3), vec![0; 24]));
+            assert_eq!(recorder.save().unwrap(), (path.clone(), 3));
+
+            let decoded = image::open(&path).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (2, 3));
+        }
+
+        let mut recorder = Recorder::new(&dir.path().join("big.gif"), 1).unwrap();
+        assert!(recorder.add_frame(0, (640, 160), vec![0; 640 * 160 * 4]));
+        assert_eq!(recorder.frames[0].0.dimensions(), (MAX_SIDE, 80));
+    }
+}
@@ -0,0 +1,99 @@
+//! What `--remember` keeps between runs, in `$XDG_STATE_HOME/wlgopher/state`.
+//!
+//! The file is a few `key=value` lines; unknown keys are ignored so older
+//! versions can read what newer ones write.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SavedState {
+    /// Size of the `--windowed` window.
+    pub size: Option<(u32, u32)>,
+    /// Where the gopher was along its walk.
+    pub x: Option<f64>,
+}
+
+impl SavedState {
+    /// Reads the saved state, which is empty if nothing was saved yet or
+    /// the file can't be read.
+    pub fn load() -> SavedState {
+        let Some(path) = path() else {
+            return SavedState::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => SavedState::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SavedState::default(),
+            Err(e) => {
+                eprintln!("wlgopher: warning: {}: {}", path.display(), e);
+                SavedState::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = path().ok_or("neither $XDG_STATE_HOME nor $HOME is set")?;
+        let write = || {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, self.format())
+        };
+        write().map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> SavedState {
+        let mut state = SavedState::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            match key.trim() {
+                "size" => {
+                    state.size = value
+                        .trim()
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                }
+                "x" => state.x = value.trim().parse().ok().filter(|x: &f64| x.is_finite()),
+                _ => {}
+            }
+        }
+        state
+    }
+
+    fn format(&self) -> String {
+        let mut text = String::new();
+        if let Some((width, height)) = self.size {
+            text += &format!("size={}x{}\n", width, height);
+        }
+        if let Some(x) = self.x {
+            text += &format!("x={}\n", x);
+        }
+        text
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(dir.join("wlgopher").join("state"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_skips_what_it_doesnt_know() {
+        let state = SavedState {
+            size: Some((640, 480)),
+            x: Some(123.5),
+        };
+        assert_eq!(SavedState::parse(&state.format()), state);
+
+        assert_eq!(
+            SavedState::parse("size=12\nx=NaN\ncolor=red\n"),
+            SavedState::default()
+        );
+    }
+}
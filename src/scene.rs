@@ -0,0 +1,232 @@
+//! `--scene`: a whole setup in one TOML or JSON file, for when it takes
+//! more than a few flags.
+//!
+//! ```toml
+//! frames = "sprites/"
+//! bg = "gradient"
+//! bg_color = "203040"
+//! margin = 32
+//! ground = 40
+//!
+//! [[gophers]]
+//! speed = 1.5
+//! start_x = 100
+//!
+//! [[gophers]]
+//! frames = "other.gif"
+//! speed = 0.7
+//! tint = "ffc080"
+//! scale = 0.5
+//!
+//! [keys]
+//! quit = "q"
+//! jump = "j"
+//! toggle-fullscreen = "F11"
+//! ```
+//!
+//! Files ending in `.json` are read as JSON with the same fields, anything
+//! else as TOML. Paths are relative to the scene file. `[keys]` binds any
+//! of quit, pause, jump, teleport, next-skin, toggle-fullscreen,
+//! step-forward and step-back to a keysym name; the others keep their keys.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    config::{Action, Config, GopherConfig},
+    glob,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scene {
+    frames: Option<PathBuf>,
+    bg: Option<String>,
+    bg_color: Option<String>,
+    margin: Option<u32>,
+    ground: Option<u32>,
+    #[serde(default)]
+    gophers: Vec<SceneGopher>,
+    #[serde(default)]
+    keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneGopher {
+    frames: Option<PathBuf>,
+    speed: Option<f64>,
+    start_x: Option<f64>,
+    tint: Option<String>,
+    scale: Option<f64>,
+}
+
+/// Reads the scene at `path` into `config`, replacing whatever options it
+/// sets. Referenced frames must exist.
+pub(crate) fn load(path: &Path, config: &mut Config) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let scene = parse(path, &text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    apply(scene, path.parent().unwrap_or(Path::new("")), config)
+        .map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn parse(path: &Path, text: &str) -> Result<Scene, String> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+}
+
+fn apply(scene: Scene, dir: &Path, config: &mut Config) -> Result<(), String> {
+    let frames = |frames: Option<PathBuf>| -> Result<_, String> {
+        let Some(frames) = frames else {
+            return Ok(None);
+        };
+        let frames = dir.join(frames);
+        if !frames.exists() && !glob::is_pattern(&frames) {
+            return Err(format!("{} doesn't exist", frames.display()));
+        }
+        Ok(Some(frames))
+    };
+
+    if let Some(path) = frames(scene.frames)? {
+        config.frames = Some(path);
+    }
+    if let Some(bg) = scene.bg {
+        config.bg = bg.parse()?;
+    }
+    if let Some(color) = scene.bg_color {
+        config.bg_color = color.parse()?;
+    }
+    if let Some(margin) = scene.margin {
+        config.margin = margin;
+    }
+    if let Some(ground) = scene.ground {
+        config.ground = ground;
+    }
+
+    for (action, key) in scene.keys {
+        let action: Action = action.parse().map_err(|e| format!("keys: {}", e))?;
+        // Keysyms are made of letters, digits and underscores, as in
+        // 'Page_Up' or 'F11'.
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!(
+                "keys: '{}' for {} isn't a keysym name",
+                key,
+                action.name()
+            ));
+        }
+        match action {
+            Action::Quit => config.quit_key = key,
+            Action::Pause => config.pause_key = key,
+            _ => config.key_bindings.push((action, key)),
+        }
+    }
+
+    config.gophers = scene
+        .gophers
+        .into_iter()
+        .enumerate()
+        .map(|(i, gopher)| {
+            Ok(GopherConfig {
+                frames: frames(gopher.frames).map_err(|e| format!("gopher {}: {}", i + 1, e))?,
+                speed: gopher.speed,
+                start_x: gopher.start_x,
+                tint: gopher.tint.map(|tint| tint.parse()).transpose()?,
+                scale: gopher.scale,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Background;
+
+    #[test]
+    fn reads_toml_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = dir.path().join("walk.png");
+        fs::write(&frames, "").unwrap();
+
+        let toml = dir.path().join("scene.toml");
+        fs::write(
+            &toml,
+            "bg = \"solid\"\nground = 40\n\
+             [[gophers]]\nframes = \"walk.png\"\nspeed = 2.0\n\
+             [[gophers]]\nstart_x = 10.0\n",
+        )
+        .unwrap();
+        let mut config = Config::default();
+        load(&toml, &mut config).unwrap();
+        assert_eq!(config.bg, Background::Solid);
+        assert_eq!(config.ground, 40);
+        assert_eq!(
+            config.gophers,
+            [
+                GopherConfig {
+                    frames: Some(frames.clone()),
+                    speed: Some(2.0),
+                    ..GopherConfig::default()
+                },
+                GopherConfig {
+                    start_x: Some(10.0),
+                    ..GopherConfig::default()
+                },
+            ]
+        );
+
+        let json = dir.path().join("scene.json");
+        fs::write(
+            &json,
+            r#"{"margin": 8, "gophers": [{"frames": "walk.png"}]}"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        load(&json, &mut config).unwrap();
+        assert_eq!(config.margin, 8);
+        assert_eq!(config.gophers[0].frames, Some(frames));
+
+        fs::write(&json, r#"{"gophers": [{}, {"frames": "run.png"}]}"#).unwrap();
+        let err = load(&json, &mut Config::default()).unwrap_err();
+        assert!(err.to_string().contains("gopher 2: "), "{}", err);
+        fs::write(&json, r#"{"speed": 2}"#).unwrap();
+        assert!(load(&json, &mut Config::default()).is_err());
+    }
+
+    #[test]
+    fn binds_keys_to_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = dir.path().join("scene.toml");
+        fs::write(&toml, "[keys]\nquit = \"q\"\nnext-skin = \"Tab\"\n").unwrap();
+        let mut config = Config::default();
+        load(&toml, &mut config).unwrap();
+        assert_eq!(Action::Quit.key(&config), Some("q"));
+        assert_eq!(Action::NextSkin.key(&config), Some("Tab"));
+        assert_eq!(Action::Teleport.key(&config), Some("t"));
+        assert_eq!(Action::Jump.key(&config), None);
+
+        for keys in ["dance = \"d\"", "jump = \"shift+j\""] {
+            fs::write(&toml, format!("[keys]\n{}\n", keys)).unwrap();
+            let err = load(&toml, &mut Config::default()).unwrap_err();
+            assert!(err.to_string().contains("keys: "), "{}", err);
+        }
+        fs::write(&toml, "[keys]\nteleport = \"f\"\n").unwrap();
+        let mut config = Config::default();
+        load(&toml, &mut config).unwrap();
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the teleport key and the toggle-fullscreen key are both 'f'"
+        );
+    }
+}
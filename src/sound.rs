@@ -0,0 +1,58 @@
+//! Optional sound effects, only available with the `audio` cargo feature.
+
+use std::{error::Error, path::Path};
+
+#[cfg(feature = "audio")]
+pub struct Player {
+    // Dropping the stream stops all playback, so it has to live as long as
+    // the handle does.
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+    sound: std::sync::Arc<[u8]>,
+}
+
+#[cfg(feature = "audio")]
+impl Player {
+    pub fn new(path: &Path) -> Result<Player, Box<dyn Error>> {
+        let sound: std::sync::Arc<[u8]> = std::fs::read(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .into();
+        // Decode once up front so a bad file is reported at startup rather
+        // than on the first bounce.
+        rodio::Decoder::new(std::io::Cursor::new(sound.clone()))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        Ok(Player {
+            _stream: stream,
+            handle,
+            sound,
+        })
+    }
+
+    /// Starts playing the sound and returns immediately; mixing happens on
+    /// the audio device's own thread.
+    pub fn play(&self) {
+        use rodio::Source;
+
+        let decoder = match rodio::Decoder::new(std::io::Cursor::new(self.sound.clone())) {
+            Ok(decoder) => decoder,
+            Err(_) => return,
+        };
+        if let Err(e) = self.handle.play_raw(decoder.convert_samples()) {
+            eprintln!("wlgopher: warning: failed to play sound: {}", e);
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub struct Player;
+
+#[cfg(not(feature = "audio"))]
+impl Player {
+    pub fn new(_: &Path) -> Result<Player, Box<dyn Error>> {
+        Err("--bounce-sound needs wlgopher to be built with the 'audio' feature".into())
+    }
+
+    pub fn play(&self) {}
+}
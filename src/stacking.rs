@@ -0,0 +1,68 @@
+//! Which way up the window's subsurfaces go: each one is added with a z
+//! index, and placed against its neighbours so the order holds however
+//! they were created.
+
+/// Where a newly added layer goes, relative to one already there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Place<T> {
+    Above(T),
+    Below(T),
+}
+
+/// The layers so far, bottom to top.
+#[derive(Debug)]
+pub(crate) struct Stacking<T> {
+    layers: Vec<(i32, T)>,
+}
+
+impl<T: Clone> Stacking<T> {
+    pub(crate) fn new() -> Stacking<T> {
+        Stacking { layers: Vec::new() }
+    }
+
+    /// Adds `layer` at `z`, above any others at the same z, returning where
+    /// it has to be placed. None for the first layer, which has nothing to
+    /// go against.
+    pub(crate) fn add(&mut self, z: i32, layer: T) -> Option<Place<T>> {
+        let at = (self.layers.iter()).rposition(|&(below, _)| below <= z);
+        let place = match at {
+            Some(below) => Some(Place::Above(self.layers[below].1.clone())),
+            None => (self.layers.first()).map(|(_, above)| Place::Below(above.clone())),
+        };
+        self.layers
+            .insert(at.map_or(0, |below| below + 1), (z, layer));
+        place
+    }
+
+    /// Takes `layer` out, for a subsurface that's gone.
+    pub(crate) fn remove(&mut self, layer: &T)
+    where
+        T: PartialEq,
+    {
+        self.layers.retain(|(_, other)| other != layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_layers_by_z_whatever_order_they_come_in() {
+        let mut stacking = Stacking::new();
+        assert_eq!(stacking.add(0, "window"), None);
+        assert_eq!(stacking.add(30, "overlay"), Some(Place::Above("window")));
+        assert_eq!(stacking.add(10, "gopher 0"), Some(Place::Above("window")));
+        assert_eq!(stacking.add(10, "gopher 1"), Some(Place::Above("gopher 0")));
+        assert_eq!(stacking.add(5, "shadow"), Some(Place::Above("window")));
+        assert_eq!(stacking.add(-1, "backdrop"), Some(Place::Below("window")));
+
+        stacking.remove(&"gopher 1");
+        assert_eq!(stacking.add(20, "splash"), Some(Place::Above("gopher 0")));
+        let order: Vec<_> = stacking.layers.iter().map(|&(_, layer)| layer).collect();
+        assert_eq!(
+            order,
+            ["backdrop", "window", "shadow", "gopher 0", "splash", "overlay"]
+        );
+    }
+}
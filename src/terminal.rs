@@ -0,0 +1,154 @@
+//! Renders the gopher as ANSI art in the terminal instead of on Wayland.
+//!
+//! Each character cell shows two pixels stacked on top of each other using
+//! the upper half block, so the picture is made of 1x2 "pixels" in 24-bit
+//! color. The sprite is downsampled to fit a reasonable number of rows and
+//! the animation runs in sprite pixels, exactly as on Wayland, with the
+//! terminal size scaled up to match.
+
+use std::{
+    error::Error,
+    fmt::Write as _,
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::animation::{Animation, TICK_MS};
+
+/// How tall the gopher should be, in character cells.
+const SPRITE_ROWS: u32 = 12;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Runs the animation until interrupted with Ctrl-C.
+pub fn run(mut animation: Animation) -> Result<(), Box<dyn Error>> {
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+
+    let (_, sprite_h) = animation.size();
+    // Sprite pixels per cell column (and per half row).
+    let scale = sprite_h.div_ceil(SPRITE_ROWS * 2).max(1);
+
+    let mut stdout = std::io::stdout().lock();
+    // Switch to the alternate screen and hide the cursor.
+    stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+
+    let mut out = String::new();
+    let result = loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+
+        let (cols, rows) = terminal_size();
+        let (sprite_w, sprite_h) = animation.size();
+        animation.set_area((
+            (cols * scale).max(sprite_w) as u64,
+            (rows * 2 * scale).max(sprite_h) as u64,
+        ));
+
+        render(&animation, scale, (cols, rows), &mut out);
+        if let Err(e) = stdout
+            .write_all(out.as_bytes())
+            .and_then(|_| stdout.flush())
+        {
+            break Err(e);
+        }
+
+        animation.next();
+        thread::sleep(Duration::from_millis(TICK_MS as u64));
+    };
+
+    stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l")?;
+    stdout.flush()?;
+    Ok(result?)
+}
+
+fn terminal_size() -> (u32, u32) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as u32, size.ws_row as u32)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Draws the whole screen into `out` as one string of escape sequences.
+fn render(animation: &Animation, scale: u32, (cols, rows): (u32, u32), out: &mut String) {
+    let (x, y) = animation.position();
+    let (offset_x, offset_y) = animation.frame_offset();
+    let image = &animation.frame().image;
+
+    // The frame's top-left corner in cell columns and half rows.
+    let left = (x + offset_x as f64) / scale as f64;
+    let top = (y + offset_y as f64) / scale as f64;
+
+    // Averages the `scale`-sized block of sprite pixels behind one half
+    // cell, or returns None where it's (mostly) transparent.
+    let sample = |col: u32, half_row: u32| -> Option<[u8; 3]> {
+        if animation.hidden() {
+            return None;
+        }
+        let px = ((col as f64 - left.round()) * scale as f64) as i64;
+        let py = ((half_row as f64 - top.round()) * scale as f64) as i64;
+        let mut sum = [0u32; 4];
+        for sy in py..py + scale as i64 {
+            for sx in px..px + scale as i64 {
+                if sx < 0 || sy < 0 || sx >= image.width() as i64 || sy >= image.height() as i64 {
+                    continue;
+                }
+                let [r, g, b, a] = image.get_pixel(sx as u32, sy as u32).0;
+                let a = a as u32;
+                sum[0] += r as u32 * a;
+                sum[1] += g as u32 * a;
+                sum[2] += b as u32 * a;
+                sum[3] += a;
+            }
+        }
+
+        if sum[3] < 128 * scale * scale / 2 {
+            return None;
+        }
+        Some([
+            (sum[0] / sum[3]) as u8,
+            (sum[1] / sum[3]) as u8,
+            (sum[2] / sum[3]) as u8,
+        ])
+    };
+
+    out.clear();
+    out.push_str("\x1b[H");
+    for row in 0..rows {
+        for col in 0..cols {
+            match (sample(col, row * 2), sample(col, row * 2 + 1)) {
+                (None, None) => out.push_str("\x1b[0m "),
+                (Some([r, g, b]), None) => {
+                    let _ = write!(out, "\x1b[0;38;2;{};{};{}m\u{2580}", r, g, b);
+                }
+                (None, Some([r, g, b])) => {
+                    let _ = write!(out, "\x1b[0;38;2;{};{};{}m\u{2584}", r, g, b);
+                }
+                (Some([r, g, b]), Some([br, bg, bb])) => {
+                    let _ = write!(
+                        out,
+                        "\x1b[38;2;{};{};{};48;2;{};{};{}m\u{2580}",
+                        r, g, b, br, bg, bb
+                    );
+                }
+            }
+        }
+        if row + 1 < rows {
+            out.push_str("\x1b[0m\r\n");
+        }
+    }
+    out.push_str("\x1b[0m");
+}
@@ -0,0 +1,152 @@
+//! `--watch`: notices through inotify when the frames change on disk.
+
+use std::{
+    ffi::{CString, OsStr},
+    io,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::glob;
+
+/// Finished writes, and files appearing or going away. Files that were
+/// only just created are left for the write that follows.
+const MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_DELETE;
+
+struct Watch {
+    descriptor: i32,
+    /// The path as given to `add`.
+    path: PathBuf,
+    /// For a single file, its name within the watched directory, or for a
+    /// pattern, the pattern its name has to match.
+    name: Option<PathBuf>,
+}
+
+pub struct Watcher {
+    fd: OwnedFd,
+    watches: Vec<Watch>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Watcher, String> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(format!("inotify: {}", io::Error::last_os_error()));
+        }
+        Ok(Watcher {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: Vec::new(),
+        })
+    }
+
+    /// Watches `path`, a directory of frames, a single file or a pattern
+    /// in the file name. A file is watched through its directory, as
+    /// editors tend to save by replacing it with a new one.
+    pub fn add(&mut self, path: &Path) -> Result<(), String> {
+        let (dir, name) = if path.is_dir() {
+            (path, None)
+        } else {
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            (
+                dir.unwrap_or(Path::new(".")),
+                path.file_name().map(PathBuf::from),
+            )
+        };
+        let c_dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|_| format!("{}: path contains a NUL byte", dir.display()))?;
+        let descriptor =
+            unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_dir.as_ptr(), MASK) };
+        if descriptor < 0 {
+            return Err(format!("{}: {}", dir.display(), io::Error::last_os_error()));
+        }
+        self.watches.push(Watch {
+            descriptor,
+            path: path.to_owned(),
+            name,
+        });
+        Ok(())
+    }
+
+    /// Takes every event waiting and returns the paths given to `add` that
+    /// they concern, each once.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let mut events = [0u8; 4096];
+        loop {
+            let len = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    events.as_mut_ptr().cast(),
+                    events.len(),
+                )
+            };
+            if len <= 0 {
+                break;
+            }
+
+            let mut events = &events[..len as usize];
+            let header_len = std::mem::size_of::<libc::inotify_event>();
+            while events.len() >= header_len {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(events.as_ptr().cast()) };
+                let name_len = event.len as usize;
+                let name = &events[header_len..header_len + name_len];
+                // The name is padded out with NUL bytes.
+                let name = OsStr::from_bytes(name.split(|&b| b == 0).next().unwrap_or(&[]));
+                events = &events[header_len + name_len..];
+
+                for watch in &self.watches {
+                    let matches = watch.descriptor == event.wd
+                        && (watch.name.as_deref())
+                            .is_none_or(|file| glob::matches(file.as_os_str(), name));
+                    if matches && !changed.contains(&watch.path) {
+                        changed.push(watch.path.clone());
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl AsFd for Watcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reports_changes_to_watched_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = dir.path().join("frames");
+        fs::create_dir(&frames).unwrap();
+        let gif = dir.path().join("walk.gif");
+        fs::write(&gif, "").unwrap();
+
+        let mut watcher = Watcher::new().unwrap();
+        watcher.add(&frames).unwrap();
+        watcher.add(&gif).unwrap();
+        assert!(watcher.changed().is_empty());
+
+        fs::write(frames.join("01.png"), "").unwrap();
+        fs::write(frames.join("02.png"), "").unwrap();
+        assert_eq!(watcher.changed(), std::slice::from_ref(&frames));
+
+        // Other files next to the GIF don't count, but replacing it does.
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        assert!(watcher.changed().is_empty());
+        fs::write(dir.path().join("new.gif"), "").unwrap();
+        watcher.changed();
+        fs::rename(dir.path().join("new.gif"), &gif).unwrap();
+        assert_eq!(watcher.changed(), [gif]);
+    }
+}
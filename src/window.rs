@@ -0,0 +1,4977 @@
+//! The Wayland side: a toplevel window with the gopher on a subsurface.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs::File,
+    ops::Range,
+    os::unix::{
+        io::{AsFd, OwnedFd},
+        net::UnixStream,
+    },
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use wayland_client::{
+    backend::ObjectId,
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_pointer, wl_region,
+        wl_registry, wl_seat, wl_shm, wl_shm_pool, wl_subcompositor, wl_subsurface, wl_surface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+
+use wayland_protocols::{
+    ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+    wp::{
+        content_type::v1::client::{wp_content_type_manager_v1, wp_content_type_v1},
+        idle_inhibit::zv1::client::{zwp_idle_inhibit_manager_v1, zwp_idle_inhibitor_v1},
+        pointer_constraints::zv1::client::{zwp_locked_pointer_v1, zwp_pointer_constraints_v1},
+        relative_pointer::zv1::client::{zwp_relative_pointer_manager_v1, zwp_relative_pointer_v1},
+        viewporter::client::{wp_viewport, wp_viewporter},
+    },
+    xdg::{
+        activation::v1::client::{xdg_activation_token_v1, xdg_activation_v1},
+        foreign::zv2::client::{zxdg_imported_v2, zxdg_importer_v2},
+        shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base},
+    },
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+};
+
+use image::Pixel;
+use memmap2::{MmapMut, MmapOptions};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    animation::{
+        build_animation, fit_size, load_background, load_images, load_splash, Animation,
+        AnimationEvent, TICK_MS,
+    },
+    background::{self, Background},
+    config::{
+        Action, Color, Config, ContentType, DamageMode, GopherConfig, PixelFormat, Region,
+        Rotation, Size,
+    },
+    control::{Command, ControlSocket},
+    event_loop,
+    footprints::Trail,
+    keys::{Bindings, CheatCode, Keymap},
+    metrics::Metrics,
+    overlay,
+    pool::PoolAllocator,
+    profile::{Phase, Profile},
+    quirks::{Compositor, Quirks, QuirksMode},
+    raw_pipe::RawPipe,
+    record::Recorder,
+    remember::SavedState,
+    sound,
+    stacking::{Place, Stacking},
+    watch::Watcher,
+};
+
+/// Connects to the compositor listening on the socket `name`, the way
+/// `$WAYLAND_DISPLAY` would be found, or to the one the environment names.
+pub fn connect(name: Option<&str>) -> Result<Connection, Box<dyn Error>> {
+    let Some(name) = name else {
+        return Ok(Connection::connect_to_env()?);
+    };
+    let path = match Path::new(name) {
+        path if path.is_absolute() => path.to_owned(),
+        path => match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => Path::new(&dir).join(path),
+            None => {
+                return Err(format!(
+                    "--wayland-display {}: XDG_RUNTIME_DIR isn't set to find it in",
+                    name
+                )
+                .into())
+            }
+        },
+    };
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| format!("--wayland-display {}: {}: {}", name, path.display(), e))?;
+    Ok(Connection::from_socket(stream)?)
+}
+
+/// Opens the window on `conn` and animates the gophers until it's closed.
+/// With `parent`, a toplevel of the embedding application on the same
+/// connection, the window opens as its transient child.
+pub fn run(
+    conn: &Connection,
+    config: Config,
+    parent: Option<xdg_toplevel::XdgToplevel>,
+) -> Result<(), Box<dyn Error>> {
+    run_on(conn, config, parent, None)
+}
+
+/// For `--all-outputs`: a window on every output, each with gophers of
+/// its own walking on a thread of its own, until they've all been closed.
+/// Outputs plugged in later get a window too, and a window closes when its
+/// output goes away.
+pub fn run_all_outputs(conn: &Connection, config: Config) -> Result<(), Box<dyn Error>> {
+    let (globals, mut queue) = registry_queue_init::<NewOutputs>(conn)?;
+    let mut new_outputs = NewOutputs(globals.contents().with_list(|list| {
+        (list.iter())
+            .filter(|global| global.interface == wl_output::WlOutput::interface().name)
+            .map(|global| global.name)
+            .collect()
+    }));
+    if new_outputs.0.is_empty() {
+        return Err("--all-outputs found no outputs".into());
+    }
+    verbose!(
+        config,
+        "opening a window on each of {} outputs",
+        new_outputs.0.len()
+    );
+
+    // Each thread holds one end of a socket pair, so the other end reads
+    // as closed once it's done, however it ended.
+    let mut windows: Vec<(thread::JoinHandle<Result<(), String>>, UnixStream)> = Vec::new();
+    let mut opened = 0;
+    let mut result = Ok(());
+    loop {
+        for name in new_outputs.0.drain(..) {
+            if opened > 0 {
+                verbose!(config, "output {} added, opening a window on it", name);
+            }
+            let conn = conn.clone();
+            let config = Config {
+                // The same seed everywhere would have them all walk in step.
+                seed: config.seed.map(|seed| seed.wrapping_add(opened)),
+                ..config.clone()
+            };
+            let (done, finished) = UnixStream::pair()?;
+            let thread = thread::spawn(move || {
+                let _finished = finished;
+                run_on(&conn, config, None, Some(name)).map_err(|e| e.to_string())
+            });
+            windows.push((thread, done));
+            opened += 1;
+        }
+        if windows.is_empty() {
+            break;
+        }
+
+        let fds: Vec<_> = windows.iter().map(|(_, done)| done.as_fd()).collect();
+        let mut ready = event_loop::dispatch(&mut queue, &mut new_outputs, &fds)?.into_iter();
+        let (closed, open) = windows
+            .into_iter()
+            .partition(|_| ready.next() == Some(true));
+        windows = open;
+        // Every window gets to close before the first error is reported.
+        for (thread, _) in closed {
+            let outcome =
+                (thread.join()).unwrap_or_else(|_| Err("a window's thread panicked".into()));
+            if let (Ok(()), Err(e)) = (&result, outcome) {
+                result = Err(e.into());
+            }
+        }
+    }
+    result
+}
+
+/// The registry listener for `--all-outputs`, collecting the outputs a
+/// window has yet to be opened on.
+struct NewOutputs(Vec<u32>);
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for NewOutputs {
+    fn event(
+        outputs: &mut Self,
+        _: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name, interface, ..
+        } = event
+        {
+            if interface == wl_output::WlOutput::interface().name {
+                outputs.0.push(name);
+            }
+        }
+    }
+}
+
+/// `run`, going fullscreen on the output that's registry global `output`
+/// rather than wherever the compositor puts the window, and closing if
+/// that output goes away.
+fn run_on(
+    conn: &Connection,
+    config: Config,
+    parent: Option<xdg_toplevel::XdgToplevel>,
+    output: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let mut state = State::new(config)?;
+    state.parent = parent;
+    state.output_global = output;
+    // Set up here rather than in State::new, which --dry-run and bench go
+    // through too: these listen, make a FIFO or write a file.
+    state.metrics = (state.config.metrics_address)
+        .map(Metrics::serve)
+        .transpose()?;
+    state.raw_pipe = (state.config.pipe_raw.as_deref())
+        .map(RawPipe::new)
+        .transpose()?;
+    state.recorder = (state.config.record_path.as_deref())
+        .map(|path| Recorder::new(path, state.config.record_seconds))
+        .transpose()?;
+
+    let mut event_queue = conn.new_event_queue();
+    let qhandle = event_queue.handle();
+
+    let display = conn.display();
+    display.get_registry(&qhandle, ());
+
+    event_queue.roundtrip(&mut state)?;
+
+    state.registry_post_process(&qhandle)?;
+    event_queue.roundtrip(&mut state)?;
+
+    // Every compositor has to take both, but not every compositor does.
+    let format = shm_format(state.config.format);
+    if !state.shm_formats.contains(&format) {
+        return Err(format!("the compositor doesn't take {:?} buffers", format).into());
+    }
+
+    if let Some(path) = state.config.pipe_raw.as_ref() {
+        let (width, height) = state.sprites[0].buffer_size(state.render_scale);
+        let fps = match state.config.max_fps {
+            Some(fps) => fps as f64,
+            None => 1000.0 / TICK_MS as f64,
+        };
+        eprintln!(
+            "wlgopher: writing {}x{} rgba frames at {} fps to {}",
+            width,
+            height,
+            fps,
+            path.display()
+        );
+    }
+
+    state.draw(&qhandle)?;
+
+    let control = state
+        .config
+        .control
+        .as_deref()
+        .map(ControlSocket::bind)
+        .transpose()?;
+
+    let watcher = match state.config.watch {
+        true => {
+            let mut watcher = Watcher::new()?;
+            let mut paths: Vec<_> = (state.sprites.iter())
+                .filter_map(|sprite| sprite.config.frames.clone())
+                .collect();
+            paths.sort();
+            paths.dedup();
+            for path in paths {
+                watcher.add(&path)?;
+            }
+            Some(watcher)
+        }
+        false => None,
+    };
+
+    // The watchdog is only worth waking up for if someone acts on it.
+    let watchdog = if state.config.throttle_timeout > 0
+        && (state.config.verbose || state.config.pause_when_throttled || state.config.fade_ms > 0)
+    {
+        Some(event_loop::Timer::periodic(Duration::from_millis(
+            state.config.throttle_timeout,
+        ))?)
+    } else {
+        None
+    };
+
+    while state.running {
+        let fds: Vec<_> = (watchdog.iter().map(|timer| timer.as_fd()))
+            .chain(control.iter().map(|control| control.as_fd()))
+            .chain(watcher.iter().map(|watcher| watcher.as_fd()))
+            .collect();
+        let mut ready = event_loop::dispatch(&mut event_queue, &mut state, &fds)?.into_iter();
+
+        if let Some(timer) = watchdog.as_ref() {
+            if ready.next() == Some(true) {
+                timer.acknowledge();
+                state.check_throttling();
+            }
+        }
+
+        if let Some(control) = control.as_ref() {
+            if ready.next() == Some(true) {
+                for command in control.commands() {
+                    match command {
+                        Ok(command) => state.handle_command(command, &qhandle),
+                        Err(e) => eprintln!("wlgopher: warning: {}", e),
+                    }
+                }
+            }
+        }
+
+        if let Some(watcher) = watcher.as_ref() {
+            if ready.next() == Some(true) {
+                for path in watcher.changed() {
+                    state.reload_frames(&path, &qhandle)?;
+                }
+            }
+        }
+
+        if state.repaint_required {
+            state.draw(&qhandle)?;
+        }
+        // Buffers the compositor let go of since get their frames now
+        // rather than when those are due.
+        state.prerender();
+    }
+
+    state.teardown();
+    conn.flush()?;
+
+    Ok(())
+}
+
+/// Goes through the same setup as `run` short of connecting, then prints
+/// what came out of it.
+pub fn dry_run(config: Config) -> Result<(), Box<dyn Error>> {
+    let state = State::new(config)?;
+    let padding = state.child_padding;
+
+    for (i, sprite) in state.sprites.iter().enumerate() {
+        if state.sprites.len() > 1 {
+            println!("gopher {}:", i + 1);
+        }
+        let (width, height) = sprite.buffer_size(state.render_scale);
+        println!("frames: {}", sprite.animation.frame_count());
+        println!(
+            "sprite buffers: {}x{} ({} bytes each)",
+            width + padding,
+            height + padding,
+            (width + padding) * (height + padding) * 4
+        );
+        println!(
+            "walk step: {} pixels per tick",
+            sprite.animation.walk_step()
+        );
+    }
+    println!("initial pool: {} bytes", state.buffer_pool_size);
+    Ok(())
+}
+
+/// The window size `bench` has the gophers walk in.
+const BENCH_SIZE: (u64, u64) = (1920, 1080);
+
+/// Renders `frames` frames of every gopher into buffers of its own, with
+/// no compositor to show them, and returns how long that took.
+pub fn bench(config: Config, frames: u64) -> Result<Duration, Box<dyn Error>> {
+    let mut state = State::new(config)?;
+    state.surface_area = BENCH_SIZE;
+    state.update_area();
+    let padding = state.child_padding;
+    let mut buffers: Vec<_> = (state.sprites.iter())
+        .map(|sprite| {
+            let (width, height) = sprite.buffer_size(state.render_scale);
+            vec![0; ((width + padding) * (height + padding) * 4) as usize]
+        })
+        .collect();
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        for (sprite, buffer) in state.sprites.iter_mut().zip(&mut buffers) {
+            blit_frame(
+                buffer,
+                &sprite.animation,
+                state.render_scale,
+                padding,
+                pixel_filter(sprite.config.tint, 1.0),
+                state.config.format,
+            );
+            sprite.animation.next();
+        }
+    }
+    Ok(start.elapsed())
+}
+
+struct Buffer {
+    buffer: wl_buffer::WlBuffer,
+    mmap_range: Range<usize>,
+    in_use: bool,
+}
+
+struct BufferList(Vec<Buffer>);
+
+impl BufferList {
+    fn new() -> BufferList {
+        BufferList(Vec::new())
+    }
+
+    fn push(&mut self, buffer: Buffer) {
+        self.0.push(buffer);
+    }
+
+    fn free_index(&self) -> Option<usize> {
+        self.0.iter().position(|b| !b.in_use)
+    }
+
+    fn set_in_use(&mut self, wlbuf: &wl_buffer::WlBuffer, in_use: bool) {
+        if let Some(ref mut buffer) = self.0.iter_mut().find(|b| &b.buffer == wlbuf) {
+            buffer.in_use = in_use;
+        }
+    }
+
+    fn destroy_all(&mut self) {
+        for buffer in self.0.drain(..) {
+            buffer.buffer.destroy();
+        }
+    }
+}
+
+/// A window, ours included, as the compositor last described it.
+struct ForeignToplevel {
+    handle: zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+    outputs: Vec<wl_output::WlOutput>,
+    activated: bool,
+}
+
+/// One gopher and the subsurface it's drawn on.
+struct Sprite {
+    animation: Animation,
+    /// The options for this gopher in particular. Its speed here is the
+    /// one before `--focus-react` changes it.
+    config: Config,
+    surface: Option<wl_surface::WlSurface>,
+    subsurface: Option<wl_subsurface::WlSubsurface>,
+    buffers: BufferList,
+    viewport: Option<wp_viewport::WpViewport>,
+    /// Frames rendered but not shown yet, oldest first. Their buffers count
+    /// as in use until they've been shown and released.
+    queue: VecDeque<QueuedFrame>,
+    /// The frame on screen.
+    shown: Option<QueuedFrame>,
+    /// The subsurface position last set.
+    placed: Option<(i32, i32)>,
+    /// The frame sets `--random-skin-per-crossing` picks from, and which
+    /// of them is on.
+    skins: Vec<Vec<image::RgbaImage>>,
+    skin: usize,
+    /// Its `--footprints`, in window surface pixels before any rotation.
+    footprints: Trail,
+}
+
+impl Sprite {
+    /// Size of the sprite within its buffers, without the padding.
+    fn buffer_size(&self, render_scale: f64) -> (u32, u32) {
+        scale_size(self.animation.size(), render_scale)
+    }
+
+    /// Throws away the frames rendered ahead, freeing their buffers.
+    fn drop_queue(&mut self) {
+        for frame in self.queue.drain(..) {
+            self.buffers.0[frame.buffer].in_use = false;
+        }
+    }
+}
+
+/// A frame in one of a sprite's buffers, with what it was rendered from
+/// that isn't in the pixels.
+#[derive(Clone, Copy)]
+struct QueuedFrame {
+    /// Index into the sprite's buffers.
+    buffer: usize,
+    /// Where the animation had the gopher.
+    position: (f64, f64),
+    /// And which frame it was on going which way, for `--emit-position`.
+    frame_index: usize,
+    forward: bool,
+    alpha: f32,
+    /// How much bigger `--pulse` had it.
+    scale: f64,
+}
+
+/// A subsurface above the gophers for `--debug-overlay` or `--splash`.
+/// Its buffers have a pool of their own, which unlike the main one never
+/// grows or moves them about.
+struct Layer {
+    surface: wl_surface::WlSurface,
+    subsurface: wl_subsurface::WlSubsurface,
+    mmap: MmapMut,
+    buffers: BufferList,
+    size: (u32, u32),
+}
+
+/// Where each kind of subsurface goes in the window's [`Stacking`], bottom
+/// to top. The window's own surface is at 0, so anything meant to be under
+/// the gophers but over the background goes between it and them, and
+/// anything below 0 under the background.
+const WINDOW_Z: i32 = 0;
+const GOPHER_Z: i32 = 10;
+const SPLASH_Z: i32 = 20;
+const OVERLAY_Z: i32 = 30;
+
+impl Stacking<wl_surface::WlSurface> {
+    /// Adds `surface` at `z`, with its `subsurface` placed there as of the
+    /// parent's next commit.
+    fn stack(
+        &mut self,
+        z: i32,
+        surface: &wl_surface::WlSurface,
+        subsurface: &wl_subsurface::WlSubsurface,
+    ) {
+        match self.add(z, surface.clone()) {
+            Some(Place::Above(below)) => subsurface.place_above(&below),
+            Some(Place::Below(above)) => subsurface.place_below(&above),
+            None => {}
+        }
+    }
+}
+
+/// Characters the overlay has room for on each line, and its distance from
+/// the corner of the window.
+const OVERLAY_COLUMNS: usize = 64;
+const OVERLAY_MARGIN: i32 = 8;
+
+impl Layer {
+    /// Sets up `buffer_count` buffers of `size` and a subsurface of
+    /// `parent` to show them on.
+    fn new(
+        state: &State,
+        parent: &wl_surface::WlSurface,
+        size: (u32, u32),
+        buffer_count: usize,
+        qh: &QueueHandle<State>,
+    ) -> std::io::Result<Layer> {
+        let len = (size.0 * size.1 * 4) as usize;
+        let mut allocator = PoolAllocator::new();
+        let ranges: Vec<_> = (0..buffer_count).map(|_| allocator.alloc(len)).collect();
+        let pool_size = allocator.end() as u64;
+        let file = pool_file()?;
+        file.set_len(pool_size)?;
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        let pool = (state.shm.as_ref().unwrap()).create_pool(file.as_fd(), pool_size as _, qh, ());
+        let mut buffers = BufferList::new();
+        for range in ranges {
+            let format = wl_shm::Format::Argb8888;
+            buffers.push(pool_buffer(&pool, pool_size, range, size, format, qh));
+        }
+        // The buffers keep the memory alive.
+        pool.destroy();
+
+        let compositor = state.compositor.as_ref().unwrap();
+        let surface = compositor.create_surface(qh, ());
+        let subsurface =
+            (state.subcompositor.as_ref().unwrap()).get_subsurface(&surface, parent, qh, ());
+        if state.mirror {
+            surface.set_buffer_transform(wl_output::Transform::Flipped);
+        }
+        // Clicks go through to the window underneath.
+        let region = compositor.create_region(qh, ());
+        surface.set_input_region(Some(&region));
+        region.destroy();
+
+        Ok(Layer {
+            surface,
+            subsurface,
+            mmap,
+            buffers,
+            size,
+        })
+    }
+
+    /// Shows `buffer` with the next commit of the parent surface.
+    fn show(&mut self, buffer: usize) {
+        let buffer = &mut self.buffers.0[buffer];
+        buffer.in_use = true;
+        self.surface.attach(Some(&buffer.buffer), 0, 0);
+        self.surface
+            .damage(0, 0, self.size.0 as i32, self.size.1 as i32);
+        self.surface.commit();
+    }
+
+    fn destroy(mut self) {
+        self.buffers.destroy_all();
+        self.subsurface.destroy();
+        self.surface.destroy();
+    }
+}
+
+/// Buffers each gopher gets. With only two, one is on screen and the other
+/// is needed for the next frame, which leaves none to render ahead into.
+fn buffers_per_sprite(config: &Config) -> usize {
+    if config.prerender {
+        3
+    } else {
+        2
+    }
+}
+
+struct State {
+    config: Config,
+
+    running: bool,
+    configured: bool,
+    fullscreen_requested: bool,
+    /// Whether the N key asked for the next skin since the last draw.
+    next_skin: bool,
+    /// Whether the window is a normal one rather than fullscreen, which
+    /// `--windowed` starts it as and the F key toggles.
+    windowed: bool,
+    /// The size to go back to on leaving fullscreen, if the compositor
+    /// leaves it to us.
+    windowed_size: Option<(u32, u32)>,
+    /// Waiting to see which output the window is on before going
+    /// fullscreen there, for `--fullscreen-on-focused`.
+    fullscreen_pending: bool,
+    repaint_required: bool,
+    activated: bool,
+    /// Set when `--pause-unfocused` let the frame callback chain run out.
+    frame_loop_stopped: bool,
+    /// The frame callback asked for last.
+    frame_callback: Option<wl_callback::WlCallback>,
+    /// Frames drawn since the start, for `--emit-every`.
+    emitted_frames: u64,
+    /// Whether `--pause-when-idle` ran out and there's been no input since.
+    idle: bool,
+    last_frame_at: Instant,
+    /// No frame callbacks arrived for `--throttle-timeout` even though we
+    /// asked for them.
+    throttled: bool,
+
+    compositor: Option<wl_compositor::WlCompositor>,
+    subcompositor: Option<wl_subcompositor::WlSubcompositor>,
+    shm: Option<wl_shm::WlShm>,
+    /// The formats the compositor said it takes.
+    shm_formats: Vec<wl_shm::Format>,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    idle_inhibit_manager: Option<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1>,
+    idle_notifier: Option<ext_idle_notifier_v1::ExtIdleNotifierV1>,
+    content_type_manager: Option<wp_content_type_manager_v1::WpContentTypeManagerV1>,
+    viewporter: Option<wp_viewporter::WpViewporter>,
+    pointer_constraints: Option<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1>,
+    relative_pointer_manager: Option<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1>,
+    activation: Option<xdg_activation_v1::XdgActivationV1>,
+    importer: Option<zxdg_importer_v2::ZxdgImporterV2>,
+    toplevel_manager: Option<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1>,
+    /// Every window there is, for `--follow-active-window`.
+    foreign_toplevels: Vec<ForeignToplevel>,
+    /// The window's surface and subsurfaces by z index.
+    stacking: Stacking<wl_surface::WlSurface>,
+    /// The window to be a transient child of, given by the library user.
+    parent: Option<xdg_toplevel::XdgToplevel>,
+    /// The window given by `--parent`, for as long as the handle is good.
+    imported_parent: Option<zxdg_imported_v2::ZxdgImportedV2>,
+    seat: Option<wl_seat::WlSeat>,
+    /// Every interface the compositor announced, bound or not.
+    globals: Vec<String>,
+    quirks: Quirks,
+    /// Set by a fullscreen configure with `commit_after_fullscreen`, for
+    /// the ack that follows it.
+    commit_after_ack: bool,
+    /// Whether the parent surface has changes waiting for a commit, be it
+    /// a configure acked, a new background or a gopher moved. Only
+    /// `--desync` goes without the commit otherwise.
+    parent_dirty: bool,
+    /// Serial of the latest key press or click, which compositors may want
+    /// to see before letting `activate` steal focus.
+    input_serial: Option<u32>,
+    /// Whether our surface has the keyboard focus.
+    keyboard_focused: bool,
+    keys: Bindings,
+    cheat: CheatCode,
+    /// Turned on and off by the cheat code.
+    cheat_mode: bool,
+    /// The modifiers held down, which keep a key press out of the cheat code.
+    modifiers: u32,
+    /// Set by the pause key.
+    paused: bool,
+    outputs: Vec<wl_output::WlOutput>,
+
+    /// The output the parent surface was last seen entering.
+    current_output: Option<wl_output::WlOutput>,
+    /// Index into `outputs` of the one to go fullscreen on, for
+    /// `--all-outputs` or `--follow-active-window`.
+    fullscreen_output: Option<usize>,
+    /// The registry name of `--all-outputs`' output for this window.
+    output_global: Option<u32>,
+    /// Size of the parent surface, before any rotation is accounted for.
+    surface_area: (u64, u64),
+    /// Where the area the gophers walk in starts, rotated the same way.
+    area_origin: (u64, u64),
+    rotation: Rotation,
+    /// Whether everything is flipped left to right for `--mirror-output`,
+    /// which takes a compositor that can transform buffers.
+    mirror: bool,
+
+    parent_surface: Option<wl_surface::WlSurface>,
+    parent_xdg_surface: Option<(xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel)>,
+    parent_buffer: Option<wl_buffer::WlBuffer>,
+    /// Full-size buffers for `--bg`, allocated once the area is known.
+    bg_buffers: BufferList,
+    bg_size: (u32, u32),
+    /// The surface size `bg_buffers` were allocated for, which is bigger
+    /// than `bg_size` if they had to be scaled down.
+    bg_area: (u64, u64),
+    bg_dirty: bool,
+    /// The `--background-image` as loaded, and whether it covers what's
+    /// behind it everywhere.
+    bg_image: Option<(image::RgbaImage, bool)>,
+    /// It scaled to `bg_size`, once it's been drawn at that size.
+    bg_image_scaled: Option<image::RgbaImage>,
+    /// Where the gophers went in the last `--single-surface` frame, in
+    /// background buffer pixels.
+    composited: Vec<((i64, i64), (u32, u32))>,
+    /// The size of the parent surface currently marked opaque, if any.
+    opaque_region: Option<(u64, u64)>,
+    parent_viewport: Option<wp_viewport::WpViewport>,
+
+    /// The gophers, each on a subsurface of the parent surface.
+    sprites: Vec<Sprite>,
+    overlay: Option<Layer>,
+    /// The `--splash` image until the splash is up, then the layer it's on
+    /// until it's over.
+    splash_image: Option<image::RgbaImage>,
+    splash: Option<Layer>,
+    /// Timestamp of the first frame callback with the splash up.
+    splash_from: Option<u32>,
+    /// Transparent pixels left of and above the frame in each child buffer,
+    /// giving the viewport room to shift its source by a fraction of a pixel.
+    child_padding: u32,
+    /// Buffer pixels per surface pixel, from `--render-scale` unless there's
+    /// no viewporter to scale the buffers back up.
+    render_scale: f64,
+
+    idle_inhibitor: Option<zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1>,
+    idle_notification: Option<ext_idle_notification_v1::ExtIdleNotificationV1>,
+    content_type: Option<wp_content_type_v1::WpContentTypeV1>,
+
+    /// The pointer `--lock-pointer` locks, along with its relative motion.
+    pointer: Option<(
+        wl_pointer::WlPointer,
+        zwp_relative_pointer_v1::ZwpRelativePointerV1,
+    )>,
+    locked_pointer: Option<zwp_locked_pointer_v1::ZwpLockedPointerV1>,
+    /// Whether `locked_pointer` is in effect rather than just requested.
+    pointer_locked: bool,
+
+    bounce_sound: Option<sound::Player>,
+    metrics: Option<Metrics>,
+    /// Time spent drawing since the last FPS line, for `--profile`.
+    profile: Option<Profile>,
+    skin_rng: StdRng,
+    raw_pipe: Option<RawPipe>,
+    recorder: Option<Recorder>,
+
+    file: File,
+    mmap: MmapMut,
+    pool: Option<wl_shm_pool::WlShmPool>,
+    buffer_pool_size: u64,
+    allocator: PoolAllocator,
+    /// Where the gophers' buffers start within the pool, after the parent's.
+    sprite_offset: usize,
+    /// Where the buffers that depend on the area start within the pool,
+    /// after the gophers'.
+    bg_offset: usize,
+    /// Timestamp of the latest frame callback.
+    frame_time: u32,
+    /// Timestamps of the frame callbacks the `--fade-ms` fades started at.
+    fade_in_from: Option<u32>,
+    fade_out_from: Option<u32>,
+    /// The fade the background was last drawn with.
+    bg_alpha: f32,
+    /// The window size `--remember` saved last time.
+    saved_size: Option<(u32, u32)>,
+    /// Timestamp of the frame callback that last led to a draw, for
+    /// `--max-fps`.
+    last_draw_time: Option<u32>,
+    /// Milliseconds to advance the animation by on the next draw when
+    /// `--max-fps` skips frames; otherwise every draw is one tick.
+    capped_step_ms: Option<u32>,
+    /// Frame callbacks since the last FPS report that came before the
+    /// previous frame could be drawn.
+    dropped_frames: u32,
+}
+
+impl State {
+    fn new(config: Config) -> Result<State, Box<dyn Error>> {
+        let child_padding = if config.subpixel { 1 } else { 0 };
+        let render_scale = config.render_scale;
+
+        let saved = if config.remember {
+            SavedState::load()
+        } else {
+            SavedState::default()
+        };
+
+        let gophers = match config.gophers.is_empty() {
+            true => vec![GopherConfig::default()],
+            false => config.gophers.clone(),
+        };
+        let mut sprites = Vec::new();
+        for (i, gopher) in gophers.iter().enumerate() {
+            let gopher_config = config.for_gopher(i, gopher);
+            let mut animation = build_animation(&gopher_config, child_padding)?;
+            // The gopher's own frames are the first skin.
+            let skins = match !config.skins.is_empty() {
+                true => std::iter::once(gopher_config.frames.clone())
+                    .chain(config.skins.iter().cloned().map(Some))
+                    .map(|frames| {
+                        let skin_config = Config {
+                            frames,
+                            ..gopher_config.clone()
+                        };
+                        load_images(&skin_config, child_padding)
+                    })
+                    .collect::<Result<_, _>>()?,
+                false => Vec::new(),
+            };
+            // --remember only keeps track of the first gopher.
+            if let Some(x) = saved.x.filter(|_| i == 0).or(gopher.start_x) {
+                animation.start_at(x);
+            }
+            sprites.push(Sprite {
+                animation,
+                config: gopher_config,
+                surface: None,
+                subsurface: None,
+                buffers: BufferList::new(),
+                viewport: None,
+                queue: VecDeque::new(),
+                shown: None,
+                placed: None,
+                skins,
+                skin: 0,
+                footprints: Trail::default(),
+            });
+        }
+        let skin_rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ SKIN_SEED),
+            None => StdRng::from_entropy(),
+        };
+
+        let buffer_len: usize = sprites
+            .iter()
+            .map(|sprite| {
+                let (width, height) = sprite.animation.size();
+                ((width + child_padding) * (height + child_padding) * 4) as usize
+            })
+            .sum();
+
+        let bounce_sound = config
+            .bounce_sound
+            .as_deref()
+            .map(sound::Player::new)
+            .transpose()?;
+        let splash_image = (config.splash.as_deref())
+            .map(|path| load_splash(path, config.max_buffer_dim))
+            .transpose()?;
+        let bg_image = (config.bg_image.as_deref())
+            .map(load_background)
+            .transpose()?
+            .map(|image| {
+                let opaque = image.pixels().all(|pixel| pixel.0[3] == 0xff);
+                (image, opaque)
+            });
+        // Missing keys are only worth a warning once the real layout is in.
+        let keys = Bindings::new(&Keymap::fallback(), &config, false);
+        let cheat = CheatCode::new(&Keymap::fallback(), &config, false);
+        let start_paused = config.start_paused;
+        let windowed = config.windowed;
+        let profile = config.profile.then(Profile::default);
+
+        let buffer_pool_size = (buffer_len * buffers_per_sprite(&config) + 4) as _;
+        let file = pool_file()?;
+        file.set_len(buffer_pool_size)?;
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        Ok(State {
+            config,
+
+            running: true,
+            configured: false,
+            fullscreen_requested: false,
+            next_skin: false,
+            windowed,
+            windowed_size: None,
+            fullscreen_pending: false,
+            repaint_required: false,
+            activated: false,
+            frame_loop_stopped: false,
+            frame_callback: None,
+            emitted_frames: 0,
+            idle: false,
+            last_frame_at: Instant::now(),
+            throttled: false,
+
+            compositor: None,
+            subcompositor: None,
+            shm: None,
+            shm_formats: Vec::new(),
+            wm_base: None,
+            idle_inhibit_manager: None,
+            idle_notifier: None,
+            content_type_manager: None,
+            viewporter: None,
+            pointer_constraints: None,
+            relative_pointer_manager: None,
+            activation: None,
+            importer: None,
+            toplevel_manager: None,
+            stacking: Stacking::new(),
+            foreign_toplevels: Vec::new(),
+            parent: None,
+            imported_parent: None,
+            seat: None,
+            globals: Vec::new(),
+            quirks: Quirks::default(),
+            commit_after_ack: false,
+            parent_dirty: false,
+            input_serial: None,
+            keyboard_focused: false,
+            keys,
+            cheat,
+            cheat_mode: false,
+            modifiers: 0,
+            // Frames are still drawn, just without the animation moving on.
+            paused: start_paused,
+            outputs: Vec::new(),
+
+            current_output: None,
+            fullscreen_output: None,
+            output_global: None,
+            surface_area: (0, 0),
+            area_origin: (0, 0),
+            rotation: Rotation::Normal,
+            mirror: false,
+
+            parent_surface: None,
+            parent_xdg_surface: None,
+            parent_buffer: None,
+            bg_buffers: BufferList::new(),
+            bg_size: (0, 0),
+            bg_area: (0, 0),
+            bg_dirty: false,
+            bg_image,
+            bg_image_scaled: None,
+            composited: Vec::new(),
+            opaque_region: None,
+            parent_viewport: None,
+
+            sprites,
+            overlay: None,
+            splash_image,
+            splash: None,
+            splash_from: None,
+            child_padding,
+            render_scale,
+
+            idle_inhibitor: None,
+            idle_notification: None,
+            content_type: None,
+
+            pointer: None,
+            locked_pointer: None,
+            pointer_locked: false,
+
+            bounce_sound,
+            metrics: None,
+            profile,
+            skin_rng,
+            raw_pipe: None,
+            recorder: None,
+
+            file,
+            mmap,
+            pool: None,
+            buffer_pool_size,
+            allocator: PoolAllocator::new(),
+            sprite_offset: 0,
+            bg_offset: 0,
+            frame_time: 0,
+            fade_in_from: None,
+            fade_out_from: None,
+            bg_alpha: 1.0,
+            saved_size: saved.size,
+            last_draw_time: None,
+            capped_step_ms: None,
+            dropped_frames: 0,
+        })
+    }
+
+    fn registry_post_process(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        self.quirks = match self.config.compositor_quirks {
+            QuirksMode::Auto => {
+                let desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+                let globals = self.globals.iter().map(String::as_str);
+                match Compositor::detect(globals, desktop.as_deref()) {
+                    Some(compositor) => {
+                        verbose!(self.config, "the compositor looks like {}", compositor);
+                        compositor.quirks()
+                    }
+                    None => Quirks::default(),
+                }
+            }
+            QuirksMode::None => Quirks::default(),
+            QuirksMode::Compositor(compositor) => compositor.quirks(),
+        };
+        let quirks = self.quirks.names();
+        if !quirks.is_empty() {
+            verbose!(self.config, "compositor workarounds: {}", quirks.join(", "));
+        }
+
+        let compositor = self.compositor.as_ref().unwrap();
+        let parent_surface = compositor.create_surface(qh, ());
+        self.parent_surface = Some(parent_surface.clone());
+        self.stacking.add(WINDOW_Z, parent_surface.clone());
+        if self.config.mirror_output {
+            // Buffer transforms came with version 2 of wl_surface.
+            match parent_surface.version() >= 2 {
+                true => {
+                    parent_surface.set_buffer_transform(wl_output::Transform::Flipped);
+                    self.mirror = true;
+                }
+                false => eprintln!(
+                    "wlgopher: warning: the compositor can't flip buffers, ignoring --mirror-output"
+                ),
+            }
+        }
+
+        let wm_base = self.wm_base.as_ref().unwrap();
+        let parent_xdg_surface = wm_base.get_xdg_surface(&parent_surface, qh, ());
+        let toplevel = parent_xdg_surface.get_toplevel(qh, ());
+        toplevel.set_title("Gopher on Wayland".into());
+        if let Some(parent) = &self.parent {
+            toplevel.set_parent(Some(parent));
+        } else if let Some(handle) = &self.config.parent {
+            match &self.importer {
+                Some(importer) => {
+                    let imported = importer.import_toplevel(handle.clone(), qh, ());
+                    imported.set_parent_of(&parent_surface);
+                    self.imported_parent = Some(imported);
+                }
+                None => eprintln!(
+                    "wlgopher: warning: compositor does not support zxdg_importer_v2, \
+                     ignoring --parent"
+                ),
+            }
+        }
+        if !self.windowed && self.config.fullscreen_on_focused && self.fullscreen_output.is_none() {
+            // Which output that is only shows once the window is mapped.
+            self.windowed = true;
+            self.fullscreen_pending = true;
+        }
+        if self.windowed {
+            let (min, max) = self.size_limits();
+            toplevel.set_min_size(min.0 as i32, min.1 as i32);
+            toplevel.set_max_size(max.0 as i32, max.1 as i32);
+        } else {
+            self.request_fullscreen(&toplevel);
+            self.fullscreen_requested = true;
+        }
+        parent_surface.commit();
+
+        // Gophers stack in the order they're listed. With --single-surface
+        // they get no subsurfaces at all.
+        let subcompositor = self.subcompositor.as_ref().unwrap();
+        for sprite in (self.sprites.iter_mut()).filter(|_| !self.config.single_surface) {
+            let surface = compositor.create_surface(qh, ());
+            let subsurface = subcompositor.get_subsurface(&surface, &parent_surface, qh, ());
+            if self.config.desync {
+                subsurface.set_desync();
+            } else {
+                subsurface.set_sync();
+            }
+            // An XRGB sprite is opaque all over, whatever size it's shown at.
+            if self.config.format == PixelFormat::Xrgb {
+                let region = compositor.create_region(qh, ());
+                region.add(0, 0, i32::MAX, i32::MAX);
+                surface.set_opaque_region(Some(&region));
+                region.destroy();
+            }
+            if self.mirror {
+                surface.set_buffer_transform(buffer_transform(self.rotation, true));
+            }
+            self.stacking.stack(GOPHER_Z, &surface, &subsurface);
+            sprite.surface = Some(surface);
+            sprite.subsurface = Some(subsurface);
+        }
+        if let Some(image) = self.splash_image.take() {
+            match Layer::new(self, &parent_surface, image.dimensions(), 1, qh) {
+                Ok(mut splash) => {
+                    (self.stacking).stack(SPLASH_Z, &splash.surface, &splash.subsurface);
+                    let pixels = splash.mmap.chunks_exact_mut(4);
+                    for (dst, src) in pixels.zip(image.pixels()) {
+                        dst.copy_from_slice(&shm_pixel(src.channels(), [1.0; 4]));
+                    }
+                    self.splash = Some(splash);
+                }
+                Err(e) => eprintln!("wlgopher: warning: no --splash: {}", e),
+            }
+        }
+        if self.config.debug_overlay {
+            let size = overlay::text_size(OVERLAY_COLUMNS, self.sprites.len());
+            match Layer::new(self, &parent_surface, size, 2, qh) {
+                Ok(overlay) => {
+                    (self.stacking).stack(OVERLAY_Z, &overlay.surface, &overlay.subsurface);
+                    overlay
+                        .subsurface
+                        .set_position(OVERLAY_MARGIN, OVERLAY_MARGIN);
+                    self.overlay = Some(overlay);
+                }
+                Err(e) => eprintln!("wlgopher: warning: no --debug-overlay: {}", e),
+            }
+        }
+        self.request_frame(
+            qh,
+            FrameDone {
+                base_time: None,
+                count: 0,
+            },
+        );
+
+        let shm = self.shm.as_ref().unwrap();
+        let pool = shm.create_pool(self.file.as_fd(), self.buffer_pool_size as _, qh, ());
+
+        let (init_w, init_h) = (1, 1);
+        let range = self.allocator.alloc((init_w * init_h * 4) as usize);
+        self.sprite_offset = self.allocator.end();
+        self.parent_buffer = Some(pool.create_buffer(
+            range.start as _,
+            init_w,
+            init_h,
+            init_w * 4,
+            wl_shm::Format::Argb8888,
+            qh,
+            (),
+        ));
+        self.mmap[range].fill(0);
+        parent_surface.attach(self.parent_buffer.as_ref(), 0, 0);
+
+        if self.config.subpixel || self.render_scale != 1.0 {
+            match self.viewporter.as_ref() {
+                Some(viewporter) => {
+                    for sprite in &mut self.sprites {
+                        let surface = sprite.surface.as_ref().unwrap();
+                        sprite.viewport = Some(viewporter.get_viewport(surface, qh, ()));
+                    }
+                }
+                None => {
+                    eprintln!(
+                        "wlgopher: warning: compositor does not support wp_viewporter, \
+                         falling back to integer positioning at full resolution"
+                    );
+                    self.child_padding = 0;
+                    self.render_scale = 1.0;
+                }
+            }
+        }
+
+        if self.config.pulse.is_some() && self.viewporter.is_none() {
+            eprintln!(
+                "wlgopher: warning: compositor does not support wp_viewporter, ignoring --pulse"
+            );
+        }
+
+        self.pool = Some(pool);
+        self.create_sprite_buffers(qh)?;
+
+        match self.content_type_manager.as_ref() {
+            Some(manager) => {
+                let content_type = manager.get_surface_content_type(&parent_surface, qh, ());
+                content_type.set_content_type(match self.config.content_type {
+                    ContentType::Photo => wp_content_type_v1::Type::Photo,
+                    ContentType::Video => wp_content_type_v1::Type::Video,
+                    ContentType::Game => wp_content_type_v1::Type::Game,
+                });
+                self.content_type = Some(content_type);
+            }
+            None => verbose!(
+                self.config,
+                "compositor does not support wp_content_type_manager_v1, \
+                 not hinting at the content type"
+            ),
+        }
+
+        if self.config.inhibit_idle {
+            match self.idle_inhibit_manager.as_ref() {
+                Some(manager) => {
+                    self.idle_inhibitor = Some(manager.create_inhibitor(&parent_surface, qh, ()));
+                }
+                None => eprintln!(
+                    "wlgopher: warning: compositor does not support zwp_idle_inhibit_manager_v1, \
+                     --inhibit-idle has no effect"
+                ),
+            }
+        }
+
+        if let Some(seconds) = self.config.pause_when_idle {
+            match (self.idle_notifier.as_ref(), self.seat.as_ref()) {
+                (Some(notifier), Some(seat)) => {
+                    self.idle_notification =
+                        Some(notifier.get_idle_notification(seconds * 1000, seat, qh, ()));
+                }
+                _ => eprintln!(
+                    "wlgopher: warning: compositor does not support ext_idle_notifier_v1, \
+                     --pause-when-idle has no effect"
+                ),
+            }
+        }
+
+        if self.config.follow_active_window && self.toplevel_manager.is_none() {
+            eprintln!(
+                "wlgopher: warning: compositor does not support \
+                 zwlr_foreign_toplevel_manager_v1, --follow-active-window has no effect"
+            );
+        }
+
+        if self.config.lock_pointer
+            && (self.pointer_constraints.is_none() || self.relative_pointer_manager.is_none())
+        {
+            eprintln!(
+                "wlgopher: warning: compositor does not support zwp_pointer_constraints_v1 \
+                 and zwp_relative_pointer_manager_v1, --lock-pointer has no effect"
+            );
+        }
+
+        self.parent_xdg_surface = Some((parent_xdg_surface, toplevel));
+        Ok(())
+    }
+
+    /// Sets up two or three buffers per gopher, right after the parent's,
+    /// growing the pool to fit them. Whatever came after them in the pool
+    /// is overwritten.
+    fn create_sprite_buffers(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        self.allocator.truncate(self.sprite_offset);
+        let count = buffers_per_sprite(&self.config);
+        let layouts: Vec<_> = (self.sprites.iter())
+            .map(|sprite| {
+                let (width, height) = sprite.buffer_size(self.render_scale);
+                let size = (width + self.child_padding, height + self.child_padding);
+                let len = (size.0 * size.1 * 4) as usize;
+                let ranges: Vec<_> = (0..count).map(|_| self.allocator.alloc(len)).collect();
+                (size, ranges)
+            })
+            .collect();
+        self.grow_pool(self.allocator.end() as _)?;
+
+        let pool = self.pool.as_ref().unwrap();
+        let format = shm_format(self.config.format);
+        for (sprite, (size, ranges)) in self.sprites.iter_mut().zip(layouts) {
+            for range in ranges {
+                let buffer = pool_buffer(pool, self.buffer_pool_size, range, size, format, qh);
+                sprite.buffers.push(buffer);
+            }
+        }
+        self.bg_offset = self.allocator.end();
+        Ok(())
+    }
+
+    /// Puts a random other one of their skins on the gophers at `indices`
+    /// for `--random-skin-per-crossing`, or unless `random` is set the next
+    /// one, for the N key.
+    fn change_skins(
+        &mut self,
+        indices: &[usize],
+        random: bool,
+        qh: &QueueHandle<Self>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut resized = false;
+        for &index in indices {
+            let sprite = &mut self.sprites[index];
+            let count = sprite.skins.len();
+            if count < 2 {
+                continue;
+            }
+            let step = match random {
+                true => self.skin_rng.gen_range(1..count),
+                false => 1,
+            };
+            sprite.skin = (sprite.skin + step) % count;
+            let size = sprite.animation.size();
+            sprite
+                .animation
+                .set_frames(sprite.skins[sprite.skin].clone());
+            sprite.drop_queue();
+            resized |= sprite.animation.size() != size;
+            verbose!(
+                self.config,
+                "gopher {} changed to skin {}",
+                index + 1,
+                sprite.skin
+            );
+        }
+        if resized {
+            self.resize_sprite_buffers(qh)?;
+        }
+        Ok(())
+    }
+
+    /// Makes new buffers for gophers whose frames changed size, and moves
+    /// the background out of their way.
+    fn resize_sprite_buffers(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        for sprite in &mut self.sprites {
+            sprite.buffers.destroy_all();
+            sprite.shown = None;
+        }
+        self.create_sprite_buffers(qh)?;
+        self.bg_buffers.destroy_all();
+        self.bg_area = (0, 0);
+
+        if self.windowed {
+            let (min, max) = self.size_limits();
+            let (_, toplevel) = self.parent_xdg_surface.as_ref().unwrap();
+            toplevel.set_min_size(min.0 as i32, min.1 as i32);
+            toplevel.set_max_size(max.0 as i32, max.1 as i32);
+        }
+        Ok(())
+    }
+
+    /// Loads the frames at `path` again for every gopher showing them,
+    /// making new buffers if they changed size. Frames that fail to load
+    /// leave the old ones in place, as they're often caught halfway
+    /// through being saved.
+    fn reload_frames(&mut self, path: &Path, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        let mut resized = false;
+        for sprite in &mut self.sprites {
+            if sprite.config.frames.as_deref() != Some(path) {
+                continue;
+            }
+            let images = match load_images(&sprite.config, self.child_padding) {
+                Ok(images) => images,
+                Err(e) => {
+                    eprintln!("wlgopher: warning: keeping the old frames: {}", e);
+                    continue;
+                }
+            };
+            let size = sprite.animation.size();
+            sprite.animation.set_frames(images);
+            sprite.drop_queue();
+            resized |= sprite.animation.size() != size;
+            verbose!(self.config, "reloaded the frames from {}", path.display());
+        }
+        // The tiles are the first gopher's frames too.
+        self.bg_dirty |= self.config.tile_gophers.is_some();
+
+        if resized {
+            self.resize_sprite_buffers(qh)?;
+        }
+        self.repaint_required = true;
+        Ok(())
+    }
+
+    /// The surface whose frame callbacks pace the animation, which is the
+    /// first gopher's, or the window's if the gophers have none.
+    fn frame_surface(&self) -> &wl_surface::WlSurface {
+        match self.config.single_surface {
+            true => self.parent_surface.as_ref().unwrap(),
+            false => self.sprites[0].surface.as_ref().unwrap(),
+        }
+    }
+
+    fn request_frame(&mut self, qh: &QueueHandle<Self>, info: FrameDone) {
+        let surface = self.frame_surface();
+        trace!(self.config, "-> {}: frame", self.describe(&surface.id()));
+        self.frame_callback = Some(surface.frame(qh, info));
+    }
+
+    /// What the object `id` is to us, for `--trace-protocol`: the window,
+    /// which gopher's surface or buffer and so on, along with its protocol
+    /// name.
+    fn describe(&self, id: &ObjectId) -> String {
+        let is = |other: Option<ObjectId>| other.as_ref() == Some(id);
+        let buffer = |buffers: &BufferList| (buffers.0.iter()).position(|b| b.buffer.id() == *id);
+
+        let sprite = (self.sprites.iter().enumerate()).find_map(|(n, sprite)| {
+            if is(sprite.surface.as_ref().map(Proxy::id)) {
+                Some(format!("gopher {} surface", n))
+            } else if is(sprite.subsurface.as_ref().map(Proxy::id)) {
+                Some(format!("gopher {} subsurface", n))
+            } else {
+                buffer(&sprite.buffers).map(|index| format!("gopher {} buffer {}", n, index))
+            }
+        });
+        let layer = [("overlay", &self.overlay), ("splash", &self.splash)]
+            .into_iter()
+            .find_map(|(name, layer)| {
+                let layer = layer.as_ref()?;
+                if layer.surface.id() == *id {
+                    Some(format!("{} surface", name))
+                } else {
+                    buffer(&layer.buffers).map(|index| format!("{} buffer {}", name, index))
+                }
+            });
+        let what = if is(self.parent_surface.as_ref().map(Proxy::id)) {
+            "window surface".to_owned()
+        } else if is(self.parent_buffer.as_ref().map(Proxy::id)) {
+            "window placeholder buffer".to_owned()
+        } else if let Some(index) = buffer(&self.bg_buffers) {
+            format!("background buffer {}", index)
+        } else if is(self.frame_callback.as_ref().map(Proxy::id)) {
+            "frame callback".to_owned()
+        } else if let Some(what) = sprite.or(layer) {
+            what
+        } else {
+            return id.to_string();
+        };
+        format!("{} ({})", what, id)
+    }
+
+    /// Logs an event received, for `--trace-protocol`.
+    fn trace_event(&self, proxy: &impl Proxy, event: &impl std::fmt::Debug) {
+        trace!(
+            self.config,
+            "<- {}: {:?}",
+            self.describe(&proxy.id()),
+            event
+        );
+    }
+
+    /// The smallest and largest size a `--windowed` window may have.
+    fn size_limits(&self) -> ((u32, u32), (u32, u32)) {
+        let max = self.config.max_size.map_or(
+            (self.config.max_buffer_dim, self.config.max_buffer_dim),
+            |Size(w, h)| (w, h),
+        );
+        let min = self.config.min_size.map_or_else(
+            || {
+                let w = (self.sprites.iter())
+                    .map(|sprite| sprite.animation.size().0)
+                    .max()
+                    .unwrap_or(0);
+                let h = (self.sprites.iter())
+                    .map(|sprite| sprite.animation.size().1 + sprite.animation.jump_height() as u32)
+                    .max()
+                    .unwrap_or(0);
+                let margin = self.config.size_margin;
+                (
+                    w + margin * 2 + self.config.margin * 2,
+                    h + margin + self.config.ground,
+                )
+            },
+            |Size(w, h)| (w, h),
+        );
+        ((min.0.min(max.0), min.1.min(max.1)), max)
+    }
+
+    /// Takes the size from a configure of a `--windowed` window, picking
+    /// one ourselves if the compositor leaves it to us.
+    fn resize_window(&mut self, qh: &QueueHandle<Self>, (width, height): (i32, i32)) {
+        let (min, max) = self.size_limits();
+        let restore = self.windowed_size.take();
+        let (width, height) = match (width, height) {
+            (1.., 1..) => (width as u32, height as u32),
+            _ if restore.is_some() => restore.unwrap(),
+            _ if self.surface_area.0 > 0 => {
+                (self.surface_area.0 as u32, self.surface_area.1 as u32)
+            }
+            _ => self.saved_size.unwrap_or(WINDOWED_SIZE),
+        };
+        let size = (width.clamp(min.0, max.0), height.clamp(min.1, max.1));
+        if (size.0 as u64, size.1 as u64) == self.surface_area {
+            return;
+        }
+
+        // Without a background the parent surface only has a 1x1 buffer,
+        // which the viewport stretches to the window size.
+        if !self.has_background() {
+            match self.viewporter.as_ref() {
+                Some(viewporter) => {
+                    let parent_surface = self.parent_surface.as_ref().unwrap();
+                    self.parent_viewport
+                        .get_or_insert_with(|| viewporter.get_viewport(parent_surface, qh, ()))
+                        .set_destination(size.0 as i32, size.1 as i32);
+                }
+                None if self.surface_area == (0, 0) => eprintln!(
+                    "wlgopher: warning: compositor does not support wp_viewporter, \
+                     --windowed without --bg only gets a window as big as the gopher"
+                ),
+                None => {}
+            }
+        }
+
+        self.surface_area = (size.0 as u64, size.1 as u64);
+        self.update_area();
+    }
+
+    fn effective_rotation(&self) -> Rotation {
+        // The gophers are copied into the window upright.
+        if self.config.single_surface {
+            return Rotation::Normal;
+        }
+        // Compositors already hand out surface coordinates upright on a
+        // rotated output, so only --transform turns anything.
+        self.config.transform.unwrap_or(Rotation::Normal)
+    }
+
+    /// Recomputes the area the gophers walk in after the surface size or
+    /// the rotation has changed.
+    fn update_area(&mut self) {
+        let rotation = self.effective_rotation();
+        let (width, height) = match rotation {
+            Rotation::Normal | Rotation::Rotate180 => self.surface_area,
+            Rotation::Rotate90 | Rotation::Rotate270 => (self.surface_area.1, self.surface_area.0),
+        };
+        // --region is cut down to what's left of it within the window.
+        let (x, y, width, height) = match self.config.region {
+            Some(Region { x, y, size }) => {
+                let (x, y) = ((x as u64).min(width), (y as u64).min(height));
+                let width = (size.0 as u64).min(width - x);
+                (x, y, width, (size.1 as u64).min(height - y))
+            }
+            None => (0, 0, width, height),
+        };
+        let area = (
+            width.saturating_sub(self.config.margin as u64 * 2),
+            height.saturating_sub(self.config.ground as u64),
+        );
+        self.area_origin = (x + self.config.margin as u64, y);
+        for (i, sprite) in self.sprites.iter_mut().enumerate() {
+            // Frames rendered for the old area may be past the new edges.
+            if sprite.animation.area() != area {
+                sprite.drop_queue();
+                // The ground they were left on has moved.
+                sprite.footprints.clear();
+                // Not for the first area, which is where it starts anyway.
+                if self.config.reset_position_on_resize && sprite.animation.area().0 != 0 {
+                    let start_x = self.config.gophers.get(i).and_then(|gopher| gopher.start_x);
+                    sprite.animation.restart(start_x);
+                }
+            }
+            sprite.animation.set_area(area);
+        }
+
+        if rotation != self.rotation {
+            for surface in self
+                .sprites
+                .iter()
+                .filter_map(|sprite| sprite.surface.as_ref())
+            {
+                surface.set_buffer_transform(buffer_transform(rotation, self.mirror));
+            }
+            self.rotation = rotation;
+        }
+
+        self.repaint_required = true;
+    }
+
+    /// Quits, after fading out for `--fade-ms` if there are frame callbacks
+    /// to time that with. Asking again while fading quits right away.
+    fn quit(&mut self) {
+        let can_fade = self.configured && !self.throttled && !self.frame_loop_stopped;
+        if self.config.fade_ms == 0 || !can_fade || self.fade_out_from.is_some() {
+            self.running = false;
+            return;
+        }
+
+        // Start from wherever a fade-in got to rather than jumping to opaque.
+        let alpha = self.fade_alpha();
+        let head_start = ((1.0 - alpha) * self.config.fade_ms as f32) as u32;
+        self.fade_out_from = Some(self.frame_time.wrapping_sub(head_start));
+    }
+
+    /// Opacity for `--fade-ms` as of the latest frame callback.
+    fn fade_alpha(&self) -> f32 {
+        let fade_ms = self.config.fade_ms;
+        if fade_ms == 0 {
+            return 1.0;
+        }
+
+        let progress =
+            |from: u32| (ms_since(from, self.frame_time) as f32 / fade_ms as f32).min(1.0);
+        let fade_in = self.fade_in_from.map_or(0.0, progress);
+        let fade_out = self.fade_out_from.map_or(1.0, |from| 1.0 - progress(from));
+        fade_in.min(fade_out)
+    }
+
+    /// Whether the frame callback at `time` should lead to a draw, which is
+    /// always unless `--max-fps` says it's too soon after the last one.
+    fn frame_due(&mut self, time: u32) -> bool {
+        let Some(max_fps) = self.config.max_fps else {
+            return true;
+        };
+        let interval = 1000 / max_fps;
+
+        let elapsed = self.last_draw_time.map(|last| ms_since(last, time));
+        // A little slack keeps timestamp jitter from costing a whole frame.
+        if elapsed.is_some_and(|elapsed| elapsed + FPS_SLACK_MS < interval) {
+            return false;
+        }
+
+        // The animation keeps its pace by catching up on the skipped time,
+        // but not on a long pause before this frame.
+        self.capped_step_ms = Some(elapsed.unwrap_or(interval).min(interval * 2));
+        self.last_draw_time = Some(time);
+        true
+    }
+
+    fn check_throttling(&mut self) {
+        let idle = self.last_frame_at.elapsed();
+        if self.throttled
+            || self.frame_loop_stopped
+            || !self.configured
+            || idle < Duration::from_millis(self.config.throttle_timeout)
+        {
+            return;
+        }
+
+        self.throttled = true;
+        // A fade-out would never finish without frame callbacks.
+        if self.fade_out_from.is_some() {
+            self.running = false;
+        }
+        verbose!(
+            self.config,
+            "no frame callback for {} ms, the window is probably occluded or throttled",
+            idle.as_millis()
+        );
+    }
+
+    /// Asks for the pointer to be locked to the window for `--lock-pointer`,
+    /// unless that's already been done.
+    fn lock_pointer(&mut self, qh: &QueueHandle<Self>) {
+        if self.locked_pointer.is_some() || !self.activated {
+            return;
+        }
+        let (Some(constraints), Some((pointer, _)), Some(surface)) = (
+            self.pointer_constraints.as_ref(),
+            self.pointer.as_ref(),
+            self.parent_surface.as_ref(),
+        ) else {
+            return;
+        };
+
+        self.locked_pointer = Some(constraints.lock_pointer(
+            surface,
+            pointer,
+            None,
+            zwp_pointer_constraints_v1::Lifetime::Oneshot,
+            qh,
+            (),
+        ));
+    }
+
+    /// Lets go of the pointer, and the gopher goes back to walking on its own.
+    fn unlock_pointer(&mut self) {
+        if let Some(locked_pointer) = self.locked_pointer.take() {
+            locked_pointer.destroy();
+        }
+        self.pointer_locked = false;
+        for sprite in &mut self.sprites {
+            sprite.animation.stop_chasing();
+        }
+    }
+
+    fn handle_command(&mut self, command: Command, qh: &QueueHandle<Self>) {
+        match command {
+            Command::Activate => self.activate(qh),
+            Command::Teleport => self.teleport(),
+        }
+    }
+
+    /// Reads the keymap the compositor sent and looks the keys up in it.
+    fn read_keymap(&mut self, fd: OwnedFd, size: u32) {
+        let file = File::from(fd);
+        // Only a private mapping is allowed from wl_seat version 7 on.
+        let keymap = unsafe {
+            MmapOptions::new()
+                .len(size as usize)
+                .map_copy_read_only(&file)
+        };
+        match keymap {
+            Ok(keymap) => {
+                let text = String::from_utf8_lossy(&keymap);
+                let keymap = Keymap::parse(text.trim_end_matches('\0'));
+                self.keys = Bindings::new(&keymap, &self.config, true);
+                self.cheat = CheatCode::new(&keymap, &self.config, true);
+            }
+            Err(e) => eprintln!("wlgopher: warning: couldn't read the keymap: {}", e),
+        }
+    }
+
+    /// Sets each gopher's walk speed for the focus (with `--focus-react`)
+    /// and the cheat mode.
+    fn update_speed(&mut self) {
+        let mut factor = match (self.config.focus_react, self.activated) {
+            (false, _) => 1.0,
+            (true, true) => FOCUSED_SPEED,
+            (true, false) => UNFOCUSED_SPEED,
+        };
+        if self.cheat_mode {
+            factor *= CHEAT_SPEED;
+        }
+        for sprite in &mut self.sprites {
+            sprite.animation.speed = sprite.config.speed * factor;
+        }
+    }
+
+    /// Moves every gopher on by one tick or back by a frame while paused,
+    /// for going through an animation a pose at a time.
+    fn step_paused(&mut self, forward: bool) {
+        for sprite in &mut self.sprites {
+            // The frames rendered ahead are of the pose before.
+            sprite.drop_queue();
+            match forward {
+                true => sprite.animation.next(),
+                false => sprite.animation.previous_frame(),
+            }
+        }
+        self.repaint_required = true;
+    }
+
+    /// Asks for the window to be made fullscreen on `--all-outputs`' output
+    /// for it, the one it's on for `--fullscreen-on-focused`, or wherever
+    /// the compositor likes.
+    fn request_fullscreen(&self, toplevel: &xdg_toplevel::XdgToplevel) {
+        let output = match self.fullscreen_output {
+            Some(index) => self.outputs.get(index),
+            None if self.config.fullscreen_on_focused => self.current_output.as_ref(),
+            None => None,
+        };
+        trace!(
+            self.config,
+            "-> xdg_toplevel: set_fullscreen {}",
+            output.map_or("None".to_owned(), |output| output.id().to_string())
+        );
+        toplevel.set_fullscreen(output);
+    }
+
+    /// Moves to the output the focused window is on, if it's fullscreen
+    /// and isn't there already. A window on several outputs counts as on
+    /// the first it entered.
+    fn follow_active_window(&mut self) {
+        let Some(output) = (self.foreign_toplevels.iter())
+            .find(|toplevel| toplevel.activated)
+            .and_then(|toplevel| toplevel.outputs.first())
+        else {
+            return;
+        };
+        let Some(index) = (self.outputs.iter()).position(|known| known == output) else {
+            return;
+        };
+        if self.windowed || self.fullscreen_output == Some(index) {
+            return;
+        }
+        verbose!(self.config, "following the focus to output {}", index);
+        self.fullscreen_output = Some(index);
+        if let Some((_, toplevel)) = self.parent_xdg_surface.as_ref() {
+            self.request_fullscreen(toplevel);
+        }
+    }
+
+    /// Goes from fullscreen to a window or back, for the F key. The area
+    /// follows once the compositor configures the new size.
+    fn toggle_fullscreen(&mut self) {
+        let Some((_, toplevel)) = self.parent_xdg_surface.as_ref() else {
+            return;
+        };
+        if self.windowed {
+            self.windowed_size = Some((self.surface_area.0 as u32, self.surface_area.1 as u32));
+            // The limits are for the window, not whatever the output is.
+            toplevel.set_min_size(0, 0);
+            toplevel.set_max_size(0, 0);
+            self.request_fullscreen(toplevel);
+            self.windowed = false;
+            self.fullscreen_requested = true;
+        } else {
+            let (min, max) = self.size_limits();
+            toplevel.set_min_size(min.0 as i32, min.1 as i32);
+            toplevel.set_max_size(max.0 as i32, max.1 as i32);
+            trace!(self.config, "-> xdg_toplevel: unset_fullscreen");
+            toplevel.unset_fullscreen();
+            self.windowed_size = self
+                .windowed_size
+                .or(self.saved_size)
+                .or(Some(WINDOWED_SIZE));
+            self.windowed = true;
+            self.fullscreen_requested = false;
+        }
+        self.parent_dirty = true;
+    }
+
+    /// Starts a hop for every gopher, for `--hop-on-key` or the jump key.
+    fn hop(&mut self) {
+        for sprite in &mut self.sprites {
+            sprite.animation.hop();
+        }
+    }
+
+    /// Puts every gopher down somewhere random within the area.
+    fn teleport(&mut self) {
+        for sprite in &mut self.sprites {
+            // Frames rendered ahead would still show it at the old spot.
+            sprite.drop_queue();
+            sprite.animation.teleport();
+        }
+        self.repaint_required = true;
+    }
+
+    /// Asks the compositor to raise and focus the window. The token comes
+    /// back as an event, which is where the actual `activate` happens.
+    fn activate(&mut self, qh: &QueueHandle<Self>) {
+        let (Some(activation), Some(surface)) = (&self.activation, &self.parent_surface) else {
+            eprintln!(
+                "wlgopher: warning: compositor does not support xdg_activation_v1, \
+                 ignoring 'activate'"
+            );
+            return;
+        };
+        let token = activation.get_activation_token(qh, ());
+        token.set_surface(surface);
+        if let (Some(seat), Some(serial)) = (&self.seat, self.input_serial) {
+            token.set_serial(serial, seat);
+        }
+        token.commit();
+    }
+
+    /// Asks for frames again after the frame callback stopped doing so.
+    fn restart_frame_loop(&mut self, qh: &QueueHandle<Self>) {
+        if !self.frame_loop_stopped {
+            return;
+        }
+        // The FPS window restarts too, as the pause would otherwise count
+        // against it.
+        self.request_frame(
+            qh,
+            FrameDone {
+                base_time: None,
+                count: 0,
+            },
+        );
+        self.frame_loop_stopped = false;
+        self.repaint_required = true;
+    }
+
+    fn teardown(&mut self) {
+        self.save_recording();
+
+        if let Some(inhibitor) = self.idle_inhibitor.take() {
+            inhibitor.destroy();
+        }
+        if let Some(notification) = self.idle_notification.take() {
+            notification.destroy();
+        }
+
+        if self.config.remember && self.configured {
+            let (width, height) = self.surface_area;
+            let saved = SavedState {
+                size: (self.windowed)
+                    .then_some((width as u32, height as u32))
+                    .or(self.windowed_size),
+                x: Some(match self.sprites[0].shown {
+                    Some(frame) => frame.position.0,
+                    None => self.sprites[0].animation.position().0,
+                }),
+            };
+            if let Err(e) = saved.save() {
+                eprintln!("wlgopher: warning: couldn't save the state: {}", e);
+            }
+        }
+    }
+
+    /// Makes the pool at least `size` bytes long, remapping it if needed.
+    fn grow_pool(&mut self, size: u64) -> Result<(), Box<dyn Error>> {
+        if size <= self.buffer_pool_size {
+            return Ok(());
+        }
+
+        self.file.set_len(size)?;
+        self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        self.pool.as_ref().unwrap().resize(size as _);
+        self.buffer_pool_size = size;
+        Ok(())
+    }
+
+    /// (Re)allocates the background buffers when the surface size changes.
+    fn ensure_bg_buffers(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        if self.surface_area == self.bg_area || self.surface_area.0 == 0 || self.surface_area.1 == 0
+        {
+            return Ok(());
+        }
+
+        let area = (self.surface_area.0 as u32, self.surface_area.1 as u32);
+        let scaled = scale_size(area, self.render_scale);
+        let size = fit_size(scaled, self.config.max_buffer_dim);
+        if size != scaled {
+            match self.viewporter.as_ref() {
+                Some(_) => eprintln!(
+                    "wlgopher: warning: a {}x{} background exceeds --max-buffer-dim {}, \
+                     drawing it at {}x{} and scaling it up",
+                    scaled.0, scaled.1, self.config.max_buffer_dim, size.0, size.1
+                ),
+                None => eprintln!(
+                    "wlgopher: warning: a {}x{} background exceeds --max-buffer-dim {} and \
+                     wp_viewporter is unavailable to scale it, only drawing {}x{} of it",
+                    area.0, area.1, self.config.max_buffer_dim, size.0, size.1
+                ),
+            }
+        }
+        if size != area {
+            if let Some(viewporter) = self.viewporter.as_ref() {
+                let parent_surface = self.parent_surface.as_ref().unwrap();
+                self.parent_viewport
+                    .get_or_insert_with(|| viewporter.get_viewport(parent_surface, qh, ()))
+                    .set_destination(area.0 as i32, area.1 as i32);
+            }
+        } else if let Some(viewport) = self.parent_viewport.as_ref() {
+            viewport.set_destination(-1, -1);
+        }
+
+        let len = (size.0 * size.1 * 4) as usize;
+        self.allocator.truncate(self.bg_offset);
+        let ranges = [self.allocator.alloc(len), self.allocator.alloc(len)];
+        self.grow_pool(self.allocator.end() as _)?;
+
+        self.bg_buffers.destroy_all();
+        let pool = self.pool.as_ref().unwrap();
+        let format = shm_format(self.config.format);
+        for range in ranges {
+            (self.bg_buffers).push(pool_buffer(
+                pool,
+                self.buffer_pool_size,
+                range,
+                size,
+                format,
+                qh,
+            ));
+        }
+
+        self.bg_size = size;
+        self.bg_area = self.surface_area;
+        self.bg_dirty = true;
+        Ok(())
+    }
+
+    /// Shows the `--splash` in the middle of the window, or takes it down
+    /// once it's been up for `--splash-ms`. Returns whether it's still up.
+    fn draw_splash(&mut self) -> bool {
+        let Some(splash) = self.splash.as_mut() else {
+            return false;
+        };
+
+        let elapsed = self.splash_from.map(|from| ms_since(from, self.frame_time));
+        if elapsed.is_some_and(|elapsed| elapsed >= self.config.splash_ms) {
+            let splash = self.splash.take().unwrap();
+            self.stacking.remove(&splash.surface);
+            splash.destroy();
+            self.parent_dirty = true;
+            // Frames rendered ahead are as invisible as the ones before.
+            for sprite in &mut self.sprites {
+                sprite.drop_queue();
+            }
+            // Any --fade-ms fade-in starts from here.
+            self.fade_in_from = Some(self.frame_time);
+            return false;
+        }
+
+        let (width, height) = (splash.size.0 as i64, splash.size.1 as i64);
+        let (area_w, area_h) = (self.surface_area.0 as i64, self.surface_area.1 as i64);
+        (splash.subsurface).set_position(
+            ((area_w - width) / 2) as i32,
+            ((area_h - height) / 2) as i32,
+        );
+        if !splash.buffers.0[0].in_use {
+            splash.show(0);
+        }
+        self.parent_dirty = true;
+        true
+    }
+
+    /// Writes out where every gopher is, for `--debug-overlay`. This is the
+    /// state the next frame is rendered from.
+    fn draw_overlay(&mut self) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            return;
+        };
+        let Some(buffer) = overlay.buffers.free_index() else {
+            return;
+        };
+
+        let lines: Vec<_> = (self.sprites.iter().enumerate())
+            .map(|(i, sprite)| match self.sprites.len() {
+                1 => sprite.animation.describe(),
+                _ => format!("{} {}", i + 1, sprite.animation.describe()),
+            })
+            .collect();
+        let range = overlay.buffers.0[buffer].mmap_range.clone();
+        overlay::draw_text(&mut overlay.mmap[range], overlay.size.0, &lines);
+        overlay.show(buffer);
+        self.parent_dirty = true;
+    }
+
+    /// Whether the parent surface has anything to draw, be it a `--bg`, a
+    /// `--background-image`, `--tile-gophers` or, for `--single-surface`,
+    /// the gophers themselves.
+    fn has_background(&self) -> bool {
+        self.config.single_surface
+            || self.config.bg != Background::None
+            || self.bg_image.is_some()
+            || self.config.tile_gophers.is_some()
+            || self.config.footprints
+    }
+
+    /// Adds the time since `started` to `phase` for `--profile`.
+    fn profiled(&mut self, phase: Phase, started: Instant) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.add(phase, started.elapsed());
+        }
+    }
+
+    /// Whether any `--footprints` are still there to draw, or to fade.
+    fn has_footprints(&self) -> bool {
+        (self.sprites.iter()).any(|sprite| !sprite.footprints.is_empty())
+    }
+
+    /// Leaves prints where the gophers on screen are standing, and forgets
+    /// the ones that have faded away.
+    fn leave_footprints(&mut self) {
+        if !self.config.footprints {
+            return;
+        }
+        let origin = (self.area_origin.0 as f64, self.area_origin.1 as f64);
+        for sprite in self.sprites.iter_mut() {
+            sprite.footprints.fade(self.frame_time);
+            // Nothing walks under the splash or dug down out of sight.
+            let Some(frame) = sprite.shown.filter(|frame| frame.alpha > 0.0) else {
+                continue;
+            };
+            let (width, height) = sprite.animation.size();
+            let feet = (
+                frame.position.0 + width as f64 / 2.0,
+                frame.position.1 + height as f64,
+            );
+            let on_ground = feet.1 >= sprite.animation.area().1 as f64 - 0.5;
+            let feet = (feet.0 + origin.0, feet.1 + origin.1);
+            sprite.footprints.step(feet, on_ground, self.frame_time);
+        }
+    }
+
+    fn draw_background(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        // With --single-surface the gophers are drawn along with it.
+        if !self.has_background() || self.config.single_surface {
+            return Ok(());
+        }
+
+        self.ensure_bg_buffers(qh)?;
+        let alpha = self.fade_alpha();
+        if !self.bg_dirty
+            && !self.config.bg.is_animated()
+            && alpha == self.bg_alpha
+            && !self.has_footprints()
+        {
+            return Ok(());
+        }
+
+        // If the compositor still holds both buffers, keep showing the old
+        // background and try again next frame.
+        let Some(buffer) = self.bg_buffers.free_index() else {
+            return Ok(());
+        };
+        self.paint_background(self.bg_buffers.0[buffer].mmap_range.clone(), alpha);
+        self.bg_alpha = alpha;
+
+        let parent_surface = self.parent_surface.as_ref().unwrap();
+        let buffer = &mut self.bg_buffers.0[buffer];
+        buffer.in_use = true;
+        trace!(
+            self.config,
+            "-> window surface: attach background {}",
+            buffer.buffer.id()
+        );
+        parent_surface.attach(Some(&buffer.buffer), 0, 0);
+        parent_surface.damage(0, 0, self.bg_area.0 as i32, self.bg_area.1 as i32);
+        self.bg_dirty = false;
+        self.parent_dirty = true;
+        self.update_opaque_region(qh, alpha);
+        Ok(())
+    }
+
+    /// Fills `range` of the pool, a background buffer, with the background
+    /// and whatever goes on it, faded to `alpha`.
+    fn paint_background(&mut self, range: Range<usize>, alpha: f32) {
+        background::fill(
+            self.config.bg,
+            self.config.bg_color.0,
+            &mut self.mmap[range.clone()],
+            self.bg_size,
+            self.frame_time,
+        );
+        // Like the gophers below, these are drawn only along with the rest
+        // of the background, which unless it's animated means once per
+        // resize rather than every frame.
+        if let Some((image, _)) = self.bg_image.as_ref() {
+            let scaled = match self.bg_image_scaled.take() {
+                Some(scaled) if scaled.dimensions() == self.bg_size => scaled,
+                _ => background::cover(image, self.bg_size),
+            };
+            background::blit(&mut self.mmap[range.clone()], &scaled);
+            self.bg_image_scaled = Some(scaled);
+        }
+        let scale = match self.parent_viewport {
+            Some(_) => self.bg_size.0 as f64 / self.bg_area.0 as f64,
+            None => 1.0,
+        };
+        if let Some(spacing) = self.config.tile_gophers {
+            background::tile(
+                &mut self.mmap[range.clone()],
+                self.bg_size,
+                &self.sprites[0].animation.still_frame().image,
+                spacing,
+                scale,
+            );
+        }
+        let radius = match self.rotation {
+            Rotation::Normal | Rotation::Rotate180 => FOOTPRINT_RADIUS,
+            Rotation::Rotate90 | Rotation::Rotate270 => (FOOTPRINT_RADIUS.1, FOOTPRINT_RADIUS.0),
+        };
+        for sprite in self.sprites.iter() {
+            for (print, strength) in sprite.footprints.prints(self.frame_time) {
+                // Sitting on the ground, the left foot's a little further
+                // back and up than the right.
+                let (x, y) = print.at;
+                let (x, y) = match print.left {
+                    true => (x - FOOTPRINT_RADIUS.0, y - FOOTPRINT_RADIUS.1 * 2.0),
+                    false => (x + FOOTPRINT_RADIUS.0, y - FOOTPRINT_RADIUS.1),
+                };
+                let (x, y) = surface_position(self.rotation, (x, y), (0, 0), self.surface_area);
+                background::footprint(
+                    &mut self.mmap[range.clone()],
+                    self.bg_size,
+                    (x * scale, y * scale),
+                    (radius.0 * scale, radius.1 * scale),
+                    strength,
+                );
+            }
+        }
+        if alpha < 1.0 {
+            for byte in self.mmap[range].iter_mut() {
+                *byte = (*byte as f32 * alpha).round() as u8;
+            }
+        }
+    }
+
+    /// Marks the background opaque where it's drawn fully opaque, which
+    /// spares the compositor blending whatever is behind the window.
+    fn update_opaque_region(&mut self, qh: &QueueHandle<Self>, alpha: f32) {
+        let opaque = !self.config.no_opaque_region
+            && alpha == 1.0
+            && (self.config.format == PixelFormat::Xrgb
+                || self.config.bg.is_opaque(self.config.bg_color.0)
+                || self.bg_image.as_ref().is_some_and(|(_, opaque)| *opaque));
+        // Without a viewport to stretch it, a scaled-down background only
+        // covers its own size.
+        let covered = match self.parent_viewport {
+            Some(_) => self.bg_area,
+            None => (self.bg_size.0 as u64, self.bg_size.1 as u64),
+        };
+        let region = opaque.then_some(covered);
+        if region == self.opaque_region {
+            return;
+        }
+
+        let parent_surface = self.parent_surface.as_ref().unwrap();
+        match region {
+            Some((width, height)) => {
+                let wl_region = self.compositor.as_ref().unwrap().create_region(qh, ());
+                // A mirrored background covers the other side.
+                let x = match self.mirror {
+                    true => self.bg_area.0.saturating_sub(width),
+                    false => 0,
+                };
+                wl_region.add(x as i32, 0, width as i32, height as i32);
+                parent_surface.set_opaque_region(Some(&wl_region));
+                wl_region.destroy();
+            }
+            None => parent_surface.set_opaque_region(None),
+        }
+        self.opaque_region = region;
+    }
+
+    /// Adds the first gopher's frame on screen to the `--record-path`
+    /// recording, saving it once it's full.
+    fn record(&mut self) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        let sprite = &self.sprites[0];
+        let Some(frame) = sprite.shown else {
+            return;
+        };
+        let size = sprite.buffer_size(self.render_scale);
+        let mmap = &self.mmap[sprite.buffers.0[frame.buffer].mmap_range.clone()];
+        let mut pixels = Vec::new();
+        buffer_rgba(mmap, size, self.child_padding, &mut pixels);
+        if !recorder.add_frame(self.frame_time, size, pixels) {
+            self.save_recording();
+        }
+    }
+
+    /// Writes out the `--record-path` recording, if it's still going.
+    fn save_recording(&mut self) {
+        match self.recorder.take().map(Recorder::save) {
+            Some(Ok((path, count))) => {
+                eprintln!("wlgopher: recorded {} frames to {}", count, path.display())
+            }
+            Some(Err(e)) => eprintln!("wlgopher: warning: couldn't save the recording: {}", e),
+            None => {}
+        }
+    }
+
+    /// Renders sprite `index`'s current frame into one of its free buffers
+    /// and queues it to be shown, then moves the animation on to the next
+    /// frame. Returns false if there's no free buffer.
+    fn render(&mut self, index: usize, alpha: f32, step_ms: Option<u32>) -> bool {
+        let padding = self.child_padding;
+        let render_scale = self.render_scale;
+        let sprite = &mut self.sprites[index];
+        let Some(buffer_index) = sprite.buffers.free_index() else {
+            return false;
+        };
+        let buffer = &mut sprite.buffers.0[buffer_index];
+        // Dug down under --dig-every, it's drawn see-through all over.
+        let alpha = match sprite.animation.hidden() {
+            true => 0.0,
+            false => alpha,
+        };
+
+        let (buffer_w, buffer_h) = scale_size(sprite.animation.size(), render_scale);
+        let tint = match self.cheat_mode {
+            true => Some(rainbow(self.frame_time)),
+            false => sprite.config.tint,
+        };
+        let mmap = &mut self.mmap[buffer.mmap_range.clone()];
+        let started = Instant::now();
+        blit_frame(
+            mmap,
+            &sprite.animation,
+            render_scale,
+            padding,
+            pixel_filter(tint, alpha),
+            self.config.format,
+        );
+        if self.config.debug_alpha {
+            checkerboard(mmap, buffer_w + padding, padding);
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.add(Phase::Blit, started.elapsed());
+        }
+
+        // Only the first gopher goes down the pipe.
+        if let Some(pipe) = self.raw_pipe.as_mut().filter(|_| index == 0) {
+            pipe.write_frame(|bytes| buffer_rgba(mmap, (buffer_w, buffer_h), padding, bytes));
+        }
+
+        buffer.in_use = true;
+        sprite.queue.push_back(QueuedFrame {
+            buffer: buffer_index,
+            position: sprite.animation.position(),
+            frame_index: sprite.animation.frame_index(),
+            forward: sprite.animation.forward(),
+            alpha,
+            scale: sprite.animation.pulse_scale(),
+        });
+
+        let held = self.paused || self.splash.is_some();
+        if !(held || self.throttled && self.config.pause_when_throttled) {
+            let started = Instant::now();
+            match step_ms {
+                Some(ms) => sprite.animation.step(ms),
+                None => sprite.animation.next(),
+            }
+            if let Some(profile) = self.profile.as_mut() {
+                profile.add(Phase::Position, started.elapsed());
+            }
+        }
+        true
+    }
+
+    /// Renders the frames that come next into every free buffer for
+    /// `--prerender`, so the next draw only has to attach them.
+    fn prerender(&mut self) {
+        if !self.config.prerender || !self.configured {
+            return;
+        }
+
+        let alpha = self.fade_alpha();
+        // How far apart these frames end up being shown is anyone's guess,
+        // so assume the pace --max-fps asks for, or one tick.
+        let step_ms = self.config.max_fps.map(|fps| 1000 / fps);
+        for index in 0..self.sprites.len() {
+            while self.render(index, alpha, step_ms) {}
+        }
+    }
+
+    /// Attaches every gopher's next frame to its surface and moves its
+    /// subsurface to where the frame goes.
+    fn show_sprites(&mut self, qh: &QueueHandle<Self>) {
+        let rotation = self.rotation;
+        let padding = self.child_padding;
+        let render_scale = self.render_scale;
+        let (origin_x, origin_y) = (self.area_origin.0 as f64, self.area_origin.1 as f64);
+        let keep_aspect = self.config.keep_aspect;
+        let damage = self.config.damage;
+        let mirror = self.mirror;
+
+        for (index, sprite) in self.sprites.iter_mut().enumerate() {
+            let frame = sprite.queue.pop_front().unwrap();
+            let buffer = &sprite.buffers.0[frame.buffer];
+
+            let size = sprite.animation.size();
+            let (buffer_w, buffer_h) = scale_size(size, render_scale);
+            let stride = buffer_w + padding;
+
+            // Shrinking a gopher that doesn't fit, or pulsing one, takes a
+            // viewport, and without one it's cut off or stays as it is.
+            let (position, fitted) =
+                fit_sprite(frame.position, size, sprite.animation.area(), keep_aspect);
+            let (position, fitted) = pulse_sprite(position, fitted, frame.scale);
+            let ((x, y), (width, height)) = match (&sprite.viewport, &self.viewporter) {
+                _ if fitted == size => (frame.position, size),
+                (Some(_), _) => (position, fitted),
+                (None, Some(viewporter)) => {
+                    let surface = sprite.surface.as_ref().unwrap();
+                    sprite.viewport = Some(viewporter.get_viewport(surface, qh, ()));
+                    (position, fitted)
+                }
+                (None, None) => (frame.position, size),
+            };
+            if index == 0 {
+                emit_position(
+                    &self.config,
+                    &mut self.emitted_frames,
+                    (x + origin_x, y + origin_y),
+                    &frame,
+                );
+            }
+            let (x, y) = surface_position(
+                rotation,
+                (x + origin_x, y + origin_y),
+                (width, height),
+                self.surface_area,
+            );
+            // The buffer transform turns the sprite on its side for 90/270.
+            let (surface_w, surface_h) = match rotation {
+                Rotation::Normal | Rotation::Rotate180 => (width, height),
+                Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+            };
+            let x = match mirror {
+                true => self.surface_area.0 as f64 - x - surface_w as f64,
+                false => x,
+            };
+
+            let subsurface = sprite.subsurface.as_ref().unwrap();
+            let placed = match sprite.viewport.as_ref() {
+                Some(viewport) => {
+                    let (src_x, src_y, src_w, src_h) = transform_rect(
+                        rotation,
+                        (padding, padding, buffer_w, buffer_h),
+                        (stride, buffer_h + padding),
+                    );
+                    // Flipped, the padding ends up on the right.
+                    let src_x = match (mirror, rotation) {
+                        (false, _) => src_x,
+                        (true, Rotation::Normal | Rotation::Rotate180) => stride - src_x - src_w,
+                        (true, _) => buffer_h + padding - src_x - src_w,
+                    };
+                    // Sliding the source window back into the padding by the
+                    // fractional part moves the visible frame forward by it.
+                    // The padding only sits on the leading edges when nothing
+                    // is rotated, so other rotations stick to whole pixels.
+                    let (fx, fy) = match rotation {
+                        Rotation::Normal if padding > 0 && !mirror => {
+                            (x - x.floor(), y - y.floor())
+                        }
+                        _ => (0.0, 0.0),
+                    };
+                    // The source is in buffer pixels, which --render-scale
+                    // makes smaller than surface pixels.
+                    viewport.set_source(
+                        src_x as f64 - fx * render_scale,
+                        src_y as f64 - fy * render_scale,
+                        src_w as f64,
+                        src_h as f64,
+                    );
+                    viewport.set_destination(surface_w as i32, surface_h as i32);
+                    ((x - fx).round() as i32, (y - fy).round() as i32)
+                }
+                None => (x.round() as i32, y.round() as i32),
+            };
+            // A new position only takes effect with the parent's commit.
+            if sprite.placed != Some(placed) {
+                trace!(
+                    self.config,
+                    "-> gopher {} subsurface: set_position {} {}",
+                    index,
+                    placed.0,
+                    placed.1
+                );
+                subsurface.set_position(placed.0, placed.1);
+                sprite.placed = Some(placed);
+                self.parent_dirty = true;
+            }
+
+            let surface = sprite.surface.as_ref().unwrap();
+            trace!(
+                self.config,
+                "-> gopher {} surface: attach buffer {}, damage, commit",
+                index,
+                frame.buffer
+            );
+            surface.attach(Some(&buffer.buffer), 0, 0);
+            // Comparing with the frame on screen takes damage_buffer, which
+            // is in buffer pixels, so the viewport and rotation don't matter.
+            let previous = (sprite.shown)
+                .filter(|_| damage == DamageMode::Minimal && surface.version() >= 4)
+                .map(|shown| &sprite.buffers.0[shown.buffer].mmap_range)
+                .filter(|range| range.len() == buffer.mmap_range.len());
+            match previous {
+                Some(range) => {
+                    let old = &self.mmap[range.clone()];
+                    let new = &self.mmap[buffer.mmap_range.clone()];
+                    if let Some((x, y, w, h)) = changed_rect(old, new, stride) {
+                        surface.damage_buffer(x as i32, y as i32, w as i32, h as i32);
+                    }
+                }
+                // Damage is in surface coordinates, which the viewport (if
+                // any) has already mapped back to the frame size.
+                None => surface.damage(0, 0, surface_w as i32, surface_h as i32),
+            }
+            surface.commit();
+            sprite.shown = Some(frame);
+        }
+    }
+
+    /// Copies every gopher's next frame over the background into one of
+    /// the window's own buffers, for `--single-surface`. If the compositor
+    /// still holds both, the frames wait for the next draw.
+    fn draw_composited(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        self.ensure_bg_buffers(qh)?;
+        let Some(buffer) = self.bg_buffers.free_index() else {
+            return Ok(());
+        };
+        let range = self.bg_buffers.0[buffer].mmap_range.clone();
+
+        let alpha = self.fade_alpha();
+        let redrawn = self.bg_dirty
+            || self.config.bg.is_animated()
+            || alpha != self.bg_alpha
+            || self.has_footprints();
+        self.paint_background(range.clone(), alpha);
+
+        // Only a background drawn smaller than the window makes this other
+        // than 1, which puts the gophers at the same spots on it.
+        let scale = self.bg_size.0 as f64 / self.bg_area.0 as f64;
+        let (origin_x, origin_y) = (self.area_origin.0 as f64, self.area_origin.1 as f64);
+        let padding = self.child_padding;
+        let mut drawn = Vec::new();
+        for (index, sprite) in self.sprites.iter_mut().enumerate() {
+            let frame = sprite.queue.pop_front().unwrap();
+            let (x, y) = (frame.position.0 + origin_x, frame.position.1 + origin_y);
+            if index == 0 {
+                emit_position(&self.config, &mut self.emitted_frames, (x, y), &frame);
+            }
+
+            let (width, height) = sprite.animation.size();
+            let at = ((x * scale).round() as i64, (y * scale).round() as i64);
+            // Copied out, the gopher's buffer is free for the next frame.
+            let sprite_buffer = &mut sprite.buffers.0[frame.buffer];
+            sprite_buffer.in_use = false;
+            let pixels = self.mmap[sprite_buffer.mmap_range.clone()].to_vec();
+            background::composite(
+                &mut self.mmap[range.clone()],
+                self.bg_size,
+                &pixels,
+                (width + padding, padding),
+                (width, height),
+                at,
+            );
+            drawn.push((at, (width, height)));
+            sprite.shown = Some(frame);
+        }
+
+        let parent_surface = self.parent_surface.as_ref().unwrap();
+        let buffer = &mut self.bg_buffers.0[buffer];
+        buffer.in_use = true;
+        trace!(
+            self.config,
+            "-> window surface: attach composited {}",
+            buffer.buffer.id()
+        );
+        parent_surface.attach(Some(&buffer.buffer), 0, 0);
+        // Where the gophers were and now are, unless all of it changed.
+        let previous = std::mem::replace(&mut self.composited, drawn);
+        // Damage is in surface coordinates, which a mirrored buffer's aren't.
+        if redrawn || scale != 1.0 || self.mirror {
+            parent_surface.damage(0, 0, self.bg_area.0 as i32, self.bg_area.1 as i32);
+        } else {
+            for ((x, y), (width, height)) in previous.iter().chain(&self.composited) {
+                parent_surface.damage(*x as i32, *y as i32, *width as i32, *height as i32);
+            }
+        }
+        self.bg_alpha = alpha;
+        self.bg_dirty = false;
+        self.parent_dirty = true;
+        self.update_opaque_region(qh, alpha);
+        Ok(())
+    }
+
+    fn draw(&mut self, qh: &QueueHandle<Self>) -> Result<(), Box<dyn Error>> {
+        if !self.configured {
+            return Ok(());
+        }
+
+        // The gophers are drawn together, so wait until each of them has a
+        // frame ready or a buffer to render one into.
+        if (self.sprites.iter())
+            .any(|sprite| sprite.queue.is_empty() && sprite.buffers.free_index().is_none())
+        {
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        self.draw_background(qh)?;
+        self.profiled(Phase::Blit, started);
+
+        // The gophers are drawn invisible under the splash, which keeps the
+        // frame callbacks coming.
+        let alpha = match self.draw_splash() {
+            true => 0.0,
+            false => self.fade_alpha(),
+        };
+        let capped_step_ms = self.capped_step_ms.take();
+        for index in 0..self.sprites.len() {
+            if self.sprites[index].queue.is_empty() {
+                self.render(index, alpha, capped_step_ms);
+            }
+        }
+
+        let started = Instant::now();
+        if self.config.single_surface {
+            self.draw_composited(qh)?;
+            self.profiled(Phase::Blit, started);
+        } else {
+            self.show_sprites(qh);
+            self.profiled(Phase::Commit, started);
+        }
+        self.leave_footprints();
+
+        self.record();
+        self.draw_overlay();
+        let started = Instant::now();
+        if std::mem::take(&mut self.parent_dirty) || !self.config.desync {
+            trace!(self.config, "-> window surface: commit");
+            self.parent_surface.as_ref().unwrap().commit();
+        }
+        self.profiled(Phase::Commit, started);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.frame_drawn();
+        }
+        self.prerender();
+
+        let mut crossed = Vec::new();
+        for (index, sprite) in self.sprites.iter_mut().enumerate() {
+            for event in sprite.animation.take_events() {
+                if let AnimationEvent::Landed | AnimationEvent::Bounced = event {
+                    if let Some(player) = self.bounce_sound.as_ref() {
+                        player.play();
+                    }
+                }
+                if let AnimationEvent::Bounced | AnimationEvent::Crossed = event {
+                    if let Some(metrics) = self.metrics.as_ref() {
+                        metrics.crossed();
+                    }
+                    crossed.push(index);
+                }
+            }
+        }
+        if self.config.random_skin_per_crossing {
+            crossed.dedup();
+            self.change_skins(&crossed, true, qh)?;
+        }
+        if std::mem::take(&mut self.next_skin) {
+            let all: Vec<_> = (0..self.sprites.len()).collect();
+            self.change_skins(&all, false, qh)?;
+        }
+
+        let shown_alpha = self.sprites[0].shown.map_or(alpha, |frame| frame.alpha);
+        if self.fade_out_from.is_some() && shown_alpha == 0.0 {
+            self.running = false;
+        }
+
+        self.repaint_required = false;
+        Ok(())
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(registry, &event);
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            state.globals.push(interface.clone());
+            match &interface[..] {
+                "wl_compositor" => {
+                    state.compositor = Some(registry.bind::<wl_compositor::WlCompositor, _, _>(
+                        name,
+                        version,
+                        qh,
+                        (),
+                    ));
+                }
+                "wl_subcompositor" => {
+                    state.subcompositor =
+                        Some(registry.bind::<wl_subcompositor::WlSubcompositor, _, _>(
+                            name,
+                            version,
+                            qh,
+                            (),
+                        ));
+                }
+                "wl_shm" => {
+                    state.shm = Some(registry.bind::<wl_shm::WlShm, _, _>(name, version, qh, ()));
+                }
+                "wl_seat" => {
+                    state.seat = Some(registry.bind(name, version, qh, ()));
+                }
+                "xdg_wm_base" => {
+                    state.wm_base =
+                        Some(registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, version, qh, ()));
+                }
+                "wl_output" => {
+                    let output = registry.bind(name, version.min(4), qh, ());
+                    if state.output_global == Some(name) {
+                        state.fullscreen_output = Some(state.outputs.len());
+                    }
+                    state.outputs.push(output);
+                }
+                "wp_viewporter" => {
+                    state.viewporter = Some(registry.bind(name, version, qh, ()));
+                }
+                "wp_content_type_manager_v1" => {
+                    state.content_type_manager = Some(registry.bind(name, version, qh, ()));
+                }
+                "zwp_idle_inhibit_manager_v1" => {
+                    state.idle_inhibit_manager = Some(registry.bind(name, version, qh, ()));
+                }
+                "ext_idle_notifier_v1" => {
+                    state.idle_notifier = Some(registry.bind(name, 1, qh, ()));
+                }
+                "zwp_pointer_constraints_v1" => {
+                    state.pointer_constraints = Some(registry.bind(name, version, qh, ()));
+                }
+                "zwp_relative_pointer_manager_v1" => {
+                    state.relative_pointer_manager = Some(registry.bind(name, version, qh, ()));
+                }
+                "xdg_activation_v1" => {
+                    state.activation = Some(registry.bind(name, version, qh, ()));
+                }
+                "zxdg_importer_v2" => {
+                    state.importer = Some(registry.bind(name, version, qh, ()));
+                }
+                // Only bound when asked for, as it tells about every window.
+                "zwlr_foreign_toplevel_manager_v1" if state.config.follow_active_window => {
+                    state.toplevel_manager = Some(registry.bind(name, version.min(3), qh, ()));
+                }
+                _ => {}
+            }
+        } else if let wl_registry::Event::GlobalRemove { name } = event {
+            if state.output_global == Some(name) {
+                verbose!(
+                    state.config,
+                    "output {} went away, closing its window",
+                    name
+                );
+                state.running = false;
+            }
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_compositor::WlCompositor);
+delegate_noop!(State: ignore wl_region::WlRegion);
+delegate_noop!(State: ignore wl_subcompositor::WlSubcompositor);
+delegate_noop!(State: ignore wl_subsurface::WlSubsurface);
+
+impl Dispatch<wl_shm::WlShm, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_shm::WlShm,
+        event: wl_shm::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        if let wl_shm::Event::Format {
+            format: WEnum::Value(format),
+        } = event
+        {
+            state.shm_formats.push(format);
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_shm_pool::WlShmPool);
+delegate_noop!(State: ignore wp_viewporter::WpViewporter);
+delegate_noop!(State: ignore wp_viewport::WpViewport);
+delegate_noop!(State: ignore zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1);
+delegate_noop!(State: ignore zwp_pointer_constraints_v1::ZwpPointerConstraintsV1);
+delegate_noop!(State: ignore zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1);
+delegate_noop!(State: ignore zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1);
+delegate_noop!(State: ignore ext_idle_notifier_v1::ExtIdleNotifierV1);
+
+impl Dispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.foreign_toplevels.push(ForeignToplevel {
+                    handle: toplevel,
+                    outputs: Vec::new(),
+                    activated: false,
+                });
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                state.toplevel_manager = None;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        let Some(index) =
+            (state.foreign_toplevels.iter()).position(|toplevel| &toplevel.handle == proxy)
+        else {
+            return;
+        };
+        let toplevel = &mut state.foreign_toplevels[index];
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                toplevel.outputs.push(output);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                toplevel.outputs.retain(|entered| *entered != output);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+                toplevel.activated = (states.chunks_exact(4))
+                    .any(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()) == activated);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => state.follow_active_window(),
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.foreign_toplevels.remove(index).handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        match event {
+            ext_idle_notification_v1::Event::Idled => {
+                verbose!(state.config, "idle, pausing until there's input");
+                // The frame callback stops the loop, if the compositor
+                // still sends it with the display off.
+                state.idle = true;
+            }
+            ext_idle_notification_v1::Event::Resumed => {
+                verbose!(state.config, "input again, resuming");
+                state.idle = false;
+                if state.activated || !state.config.pause_unfocused {
+                    state.restart_frame_loop(qh);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+delegate_noop!(State: ignore wp_content_type_manager_v1::WpContentTypeManagerV1);
+delegate_noop!(State: ignore wp_content_type_v1::WpContentTypeV1);
+delegate_noop!(State: ignore xdg_activation_v1::XdgActivationV1);
+delegate_noop!(State: ignore zxdg_importer_v2::ZxdgImporterV2);
+
+struct FrameDone {
+    base_time: Option<u32>,
+    count: u32,
+}
+
+impl Dispatch<wl_callback::WlCallback, FrameDone> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        info: &FrameDone,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        if let wl_callback::Event::Done {
+            callback_data: time,
+        } = event
+        {
+            if state.config.pause_unfocused && !state.activated || state.idle {
+                // Stop asking for frames; the configure that reactivates
+                // the window, or the input that ends the idle time, starts
+                // the chain again.
+                state.frame_loop_stopped = true;
+                return;
+            }
+
+            if std::mem::take(&mut state.fullscreen_pending) {
+                // Shown without entering any output, so there's none to
+                // pick; the compositor has to.
+                verbose!(state.config, "no output entered, going fullscreen anywhere");
+                state.toggle_fullscreen();
+            }
+
+            state.last_frame_at = Instant::now();
+            let resumed = std::mem::replace(&mut state.throttled, false);
+            if resumed {
+                verbose!(state.config, "frame callbacks resumed");
+            }
+            // Callbacks skipped for --max-fps don't count as frames.
+            let drawn = state.frame_due(time);
+
+            let frame_info = match info {
+                // Don't let the gap skew the FPS figures either.
+                _ if resumed => FrameDone {
+                    base_time: Some(time),
+                    count: 0,
+                },
+                FrameDone {
+                    base_time: Some(base),
+                    count,
+                } if ms_since(*base, time) >= 5000 => {
+                    let frames = count + drawn as u32;
+                    let duration_ms = ms_since(*base, time) as f64;
+                    let fps = (frames * 1000) as f64 / duration_ms;
+                    if !(state.config.quiet || state.config.emit_position) {
+                        println!(
+                            "{} frames in {:.3} seconds = {:.3} FPS, {} dropped",
+                            frames,
+                            duration_ms / 1000.0,
+                            fps,
+                            state.dropped_frames
+                        );
+                    }
+                    if let Some(profile) = state.profile.as_mut() {
+                        let report = profile.report();
+                        if !state.config.emit_position {
+                            println!("{}", report);
+                        }
+                    }
+                    if let Some(metrics) = state.metrics.as_ref() {
+                        metrics.set_fps(fps);
+                    }
+                    state.dropped_frames = 0;
+
+                    FrameDone {
+                        base_time: Some(time),
+                        count: 0,
+                    }
+                }
+                FrameDone {
+                    base_time: Some(base),
+                    count,
+                } => FrameDone {
+                    base_time: Some(*base),
+                    count: count + drawn as u32,
+                },
+                FrameDone {
+                    base_time: None, ..
+                } => FrameDone {
+                    base_time: Some(time),
+                    count: 0,
+                },
+            };
+
+            state.request_frame(qh, frame_info);
+            if !drawn {
+                // Nothing gets drawn this time, but the frame asked for
+                // only comes once the surface is committed, and a synced
+                // subsurface's commit once the window's is.
+                let surface = state.frame_surface();
+                trace!(state.config, "-> {}: commit", state.describe(&surface.id()));
+                surface.commit();
+                if !state.config.single_surface && !state.config.desync {
+                    trace!(state.config, "-> window surface: commit");
+                    state.parent_surface.as_ref().unwrap().commit();
+                }
+            }
+            state.frame_time = time;
+            state.fade_in_from.get_or_insert(time);
+            if state.splash.is_some() {
+                state.splash_from.get_or_insert(time);
+            }
+            if drawn {
+                // The last frame due never made it to the screen.
+                if state.repaint_required {
+                    state.dropped_frames += 1;
+                    if let Some(metrics) = state.metrics.as_ref() {
+                        metrics.frame_dropped();
+                    }
+                }
+                if let Some(metrics) = state.metrics.as_ref() {
+                    metrics.frame_drawn();
+                }
+                state.repaint_required = true;
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+    fn event(
+        state: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(buffer, &event);
+        if let wl_buffer::Event::Release = event {
+            for sprite in &mut state.sprites {
+                sprite.buffers.set_in_use(buffer, false);
+            }
+            state.bg_buffers.set_in_use(buffer, false);
+            for layer in [&mut state.overlay, &mut state.splash] {
+                if let Some(layer) = layer.as_mut() {
+                    layer.buffers.set_in_use(buffer, false);
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for State {
+    fn event(
+        state: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(wm_base, &event);
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for State {
+    fn event(
+        state: &mut Self,
+        xdg_surface: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(xdg_surface, &event);
+        if let xdg_surface::Event::Configure { serial, .. } = event {
+            trace!(state.config, "-> xdg_surface: ack_configure {}", serial);
+            xdg_surface.ack_configure(serial);
+            state.configured = true;
+            state.parent_dirty = true;
+            if std::mem::take(&mut state.commit_after_ack) {
+                state.parent_surface.as_ref().unwrap().commit();
+            }
+        }
+    }
+}
+
+impl Dispatch<xdg_toplevel::XdgToplevel, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &xdg_toplevel::XdgToplevel,
+        event: xdg_toplevel::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        match event {
+            xdg_toplevel::Event::Configure {
+                width,
+                height,
+                states,
+            } => {
+                let states: Vec<_> = toplevel_states(&states).collect();
+
+                if state.windowed {
+                    state.resize_window(qh, (width, height));
+                } else if states.contains(&xdg_toplevel::State::Fullscreen)
+                    && state.fullscreen_requested
+                {
+                    // Coming from a window, the 1x1 buffer is still only
+                    // stretched as far as the window went.
+                    if let Some(viewport) =
+                        (state.parent_viewport.as_ref()).filter(|_| !state.has_background())
+                    {
+                        viewport.set_destination(width, height);
+                    }
+                    state.surface_area = (width as _, height as _);
+                    state.update_area();
+
+                    state.fullscreen_requested = false;
+                    state.commit_after_ack = state.quirks.commit_after_fullscreen;
+                }
+
+                let activated = states.contains(&xdg_toplevel::State::Activated);
+                if activated != state.activated {
+                    state.activated = activated;
+                    if state.config.focus_react {
+                        state.update_speed();
+                    }
+                    if activated {
+                        state.lock_pointer(qh);
+                    } else {
+                        state.unlock_pointer();
+                    }
+                    if activated && !state.idle {
+                        state.restart_frame_loop(qh);
+                    }
+                }
+            }
+            xdg_toplevel::Event::Close => state.quit(),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for State {
+    fn event(
+        state: &mut Self,
+        surface: &wl_surface::WlSurface,
+        event: wl_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(surface, &event);
+        if let wl_surface::Event::Enter { output } = event {
+            if state.parent_surface.as_ref() == Some(surface) {
+                state.current_output = Some(output);
+                if std::mem::take(&mut state.fullscreen_pending) {
+                    state.toggle_fullscreen();
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for State {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(output, &event);
+    }
+}
+
+/// The buffer transform that turns an upright sprite by `rotation` and,
+/// for `--mirror-output`, then flips it left to right on the surface.
+fn buffer_transform(rotation: Rotation, mirror: bool) -> wl_output::Transform {
+    match (rotation, mirror) {
+        (Rotation::Normal, false) => wl_output::Transform::Normal,
+        (Rotation::Rotate90, false) => wl_output::Transform::_90,
+        (Rotation::Rotate180, false) => wl_output::Transform::_180,
+        (Rotation::Rotate270, false) => wl_output::Transform::_270,
+        (Rotation::Normal, true) => wl_output::Transform::Flipped,
+        (Rotation::Rotate90, true) => wl_output::Transform::Flipped90,
+        (Rotation::Rotate180, true) => wl_output::Transform::Flipped180,
+        (Rotation::Rotate270, true) => wl_output::Transform::Flipped270,
+    }
+}
+
+/// Where to draw a gopher of `size` at `position` and how big, when `area`
+/// may be too small for it: shrunk on each axis to fit, or with
+/// `keep_aspect` evenly on both and centered in the space it has.
+fn fit_sprite(
+    (x, y): (f64, f64),
+    (width, height): (u32, u32),
+    (area_w, area_h): (u64, u64),
+    keep_aspect: bool,
+) -> ((f64, f64), (u32, u32)) {
+    let fits = width as u64 <= area_w && height as u64 <= area_h;
+    if fits || area_w == 0 || area_h == 0 {
+        return ((x, y), (width, height));
+    }
+
+    let scale_w = (area_w as f64 / width as f64).min(1.0);
+    let scale_h = (area_h as f64 / height as f64).min(1.0);
+    let (scale_w, scale_h) = match keep_aspect {
+        true => (scale_w.min(scale_h), scale_w.min(scale_h)),
+        false => (scale_w, scale_h),
+    };
+    let size = (
+        ((width as f64 * scale_w).round() as u32).max(1),
+        ((height as f64 * scale_h).round() as u32).max(1),
+    );
+
+    // The part of the gopher's box that's within the area.
+    let (box_w, box_h) = ((width as u64).min(area_w), (height as u64).min(area_h));
+    let position = (
+        x + (box_w as f64 - size.0 as f64) / 2.0,
+        y + (box_h as f64 - size.1 as f64) / 2.0,
+    );
+    (position, size)
+}
+
+/// Where to draw a gopher of `size` at `position` and how big, grown by
+/// `scale` around the middle of its bottom edge so its feet stay put.
+fn pulse_sprite(
+    (x, y): (f64, f64),
+    (width, height): (u32, u32),
+    scale: f64,
+) -> ((f64, f64), (u32, u32)) {
+    if scale == 1.0 {
+        return ((x, y), (width, height));
+    }
+    let size = (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    );
+    let position = (
+        x + (width as f64 - size.0 as f64) / 2.0,
+        y + (height as f64 - size.1 as f64),
+    );
+    (position, size)
+}
+
+/// Maps the top-left corner of a `size` box at `position` in the gopher's
+/// upright coordinate space onto a surface of `surface_area`. A buffer
+/// transform of 90 shows the buffer turned clockwise, so walking right
+/// becomes moving down the surface.
+fn surface_position(
+    rotation: Rotation,
+    (x, y): (f64, f64),
+    (w, h): (u32, u32),
+    (surface_w, surface_h): (u64, u64),
+) -> (f64, f64) {
+    let (w, h, surface_w, surface_h) = (w as f64, h as f64, surface_w as f64, surface_h as f64);
+    match rotation {
+        Rotation::Normal => (x, y),
+        Rotation::Rotate90 => (surface_w - y - h, x),
+        Rotation::Rotate180 => (surface_w - x - w, surface_h - y - h),
+        Rotation::Rotate270 => (y, surface_h - x - w),
+    }
+}
+
+/// Creates the file backing the shm pool: an anonymous memfd with the
+/// `memfd` feature (the default), falling back to a temporary file where
+/// that's unsupported, and only the temporary file without it.
+///
+/// The memfd is sealed against shrinking, so the compositor can rely on the
+/// pool never getting shorter under its mapping. Growing it in `grow_pool`
+/// is still allowed and the seal carries over, so there's nothing to redo.
+#[cfg(feature = "memfd")]
+fn pool_file() -> std::io::Result<File> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let fd = unsafe {
+        libc::memfd_create(
+            c"wlgopher".as_ptr(),
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        )
+    };
+    if fd < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOSYS) {
+            return tempfile::tempfile();
+        }
+        return Err(err);
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+#[cfg(not(feature = "memfd"))]
+fn pool_file() -> std::io::Result<File> {
+    tempfile::tempfile()
+}
+
+/// Scales `size` by `scale`, rounding up so nothing gets cut off.
+fn scale_size((width, height): (u32, u32), scale: f64) -> (u32, u32) {
+    let scale = |side: u32| ((side as f64 * scale).ceil() as u32).max(1);
+    (scale(width), scale(height))
+}
+
+/// Maps a rectangle in a buffer of `buffer_size` into the buffer's
+/// coordinates after `rotation` has been applied as its buffer transform.
+fn transform_rect(
+    rotation: Rotation,
+    (x, y, w, h): (u32, u32, u32, u32),
+    (buffer_w, buffer_h): (u32, u32),
+) -> (u32, u32, u32, u32) {
+    match rotation {
+        Rotation::Normal => (x, y, w, h),
+        Rotation::Rotate90 => (buffer_h - y - h, x, h, w),
+        Rotation::Rotate180 => (buffer_w - x - w, buffer_h - y - h, w, h),
+        Rotation::Rotate270 => (y, buffer_w - x - w, h, w),
+    }
+}
+
+/// Prints where the first gopher is drawn, at `position` on the surface,
+/// every `--emit-every`th frame for `--emit-position`.
+fn emit_position(config: &Config, emitted: &mut u64, (x, y): (f64, f64), frame: &QueuedFrame) {
+    if !config.emit_position {
+        return;
+    }
+    if emitted.is_multiple_of(config.emit_every as u64) {
+        println!(
+            "{:.0} {:.0} {} {}",
+            x,
+            y,
+            frame.frame_index,
+            if frame.forward { "forward" } else { "backward" }
+        );
+    }
+    *emitted += 1;
+}
+
+/// How early a frame callback may come and still be drawn under `--max-fps`.
+const FPS_SLACK_MS: u32 = 2;
+
+/// Milliseconds from `from` to `to` on the compositor's clock, which wraps
+/// around every 49 days. More than half of that apart, `to` is taken to be
+/// the earlier one, come in out of order, and no time has passed.
+pub(crate) fn ms_since(from: u32, to: u32) -> u32 {
+    match to.wrapping_sub(from) {
+        elapsed if elapsed > u32::MAX / 2 => 0,
+        elapsed => elapsed,
+    }
+}
+
+/// Converts an RGBA frame pixel to the shm buffer's byte order, with each
+/// channel multiplied by `filter`, also in RGBA order.
+fn shm_pixel(p: &[u8], filter: [f32; 4]) -> [u8; 4] {
+    let [r, g, b, a] = [p[0], p[1], p[2], p[3]];
+    if filter == [1.0; 4] {
+        return [b, g, r, a];
+    }
+    let apply = |c: u8, f: f32| (c as f32 * f).round() as u8;
+    [
+        apply(b, filter[2]),
+        apply(g, filter[1]),
+        apply(r, filter[0]),
+        apply(a, filter[3]),
+    ]
+}
+
+/// The smallest rectangle, as x, y, width and height, holding every pixel
+/// that differs between the buffers `old` and `new`, which are `width`
+/// pixels wide. None if they're the same.
+fn changed_rect(old: &[u8], new: &[u8], width: u32) -> Option<(u32, u32, u32, u32)> {
+    let row_len = (width * 4) as usize;
+    let mut rect: Option<(u32, u32, u32, u32)> = None;
+    for (y, (old_row, new_row)) in old.chunks(row_len).zip(new.chunks(row_len)).enumerate() {
+        if old_row == new_row {
+            continue;
+        }
+        let differs = |(a, b): (&[u8], &[u8])| a != b;
+        let pixels = || old_row.chunks(4).zip(new_row.chunks(4));
+        let left = pixels().position(differs).unwrap() as u32;
+        let right = pixels().rposition(differs).unwrap() as u32;
+        let y = y as u32;
+        rect = Some(match rect {
+            Some((x0, y0, x1, _)) => (x0.min(left), y0, x1.max(right), y),
+            None => (left, y, right, y),
+        });
+    }
+    rect.map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
+
+/// Appends the `width`x`height` sprite in a buffer, which is BGRA with
+/// `padding` along its top and left, to `bytes` as RGBA.
+fn buffer_rgba(mmap: &[u8], (width, height): (u32, u32), padding: u32, bytes: &mut Vec<u8>) {
+    let rows = mmap.chunks_exact(((width + padding) * 4) as usize);
+    for row in rows.skip(padding as usize).take(height as usize) {
+        for p in row[(padding * 4) as usize..].chunks_exact(4) {
+            bytes.extend_from_slice(&[p[2], p[1], p[0], p[3]]);
+        }
+    }
+}
+
+/// The cheat mode's tint at `time`, which goes round the color wheel.
+fn rainbow(time: u32) -> Color {
+    let hue = (time % RAINBOW_MS) as f32 / RAINBOW_MS as f32 * 6.0;
+    let channel = |n: f32| {
+        let k = (n + hue) % 6.0;
+        ((1.0 - k.min(4.0 - k).clamp(0.0, 1.0)) * 255.0) as u8
+    };
+    Color([channel(5.0), channel(3.0), channel(1.0), 0xff])
+}
+
+/// A buffer of `size` over `range` of `pool`, which is `pool_size` bytes
+/// long.
+fn pool_buffer(
+    pool: &wl_shm_pool::WlShmPool,
+    pool_size: u64,
+    range: Range<usize>,
+    (width, height): (u32, u32),
+    format: wl_shm::Format,
+    qh: &QueueHandle<State>,
+) -> Buffer {
+    assert!(
+        range.end as u64 <= pool_size && range.len() == (width * height * 4) as usize,
+        "a {}x{} buffer doesn't fit {:?} of a {} byte pool",
+        width,
+        height,
+        range,
+        pool_size
+    );
+    Buffer {
+        buffer: pool.create_buffer(
+            range.start as _,
+            width as i32,
+            height as i32,
+            (width * 4) as i32,
+            format,
+            qh,
+            (),
+        ),
+        mmap_range: range,
+        in_use: false,
+    }
+}
+
+/// The shm format buffers of `format` are created with.
+fn shm_format(format: PixelFormat) -> wl_shm::Format {
+    match format {
+        PixelFormat::Argb => wl_shm::Format::Argb8888,
+        PixelFormat::Xrgb => wl_shm::Format::Xrgb8888,
+    }
+}
+
+/// A BGRA pixel drawn over black, for XRGB buffers where the alpha byte
+/// doesn't count.
+fn opaque_pixel([b, g, r, a]: [u8; 4]) -> [u8; 4] {
+    let over_black = |c: u8| (c as u16 * a as u16 / 255) as u8;
+    [over_black(b), over_black(g), over_black(r), 0xff]
+}
+
+/// Draws the animation's frame into `dst`, a sprite buffer as big as the
+/// frames at `render_scale` plus `padding` on the leading edges, with
+/// `shm_pixel` and `filter`.
+fn blit_frame(
+    dst: &mut [u8],
+    animation: &Animation,
+    render_scale: f64,
+    padding: u32,
+    filter: [f32; 4],
+    format: PixelFormat,
+) {
+    let (buffer_w, buffer_h) = scale_size(animation.size(), render_scale);
+    let stride = buffer_w + padding;
+    let (offset_x, offset_y) = animation.frame_offset();
+    let frame = animation.frame();
+    let xrgb = format == PixelFormat::Xrgb;
+    let convert = |p: &[u8]| match xrgb {
+        true => opaque_pixel(shm_pixel(p, filter)),
+        false => shm_pixel(p, filter),
+    };
+
+    // Frames may be smaller than the buffer, so clear whatever the
+    // previous frame left behind before placing this one at its anchor.
+    match xrgb {
+        true => (dst.chunks_exact_mut(4)).for_each(|p| p.copy_from_slice(&[0, 0, 0, 0xff])),
+        false => dst.fill(0),
+    }
+    if render_scale == 1.0 {
+        for (x, y, pixel) in frame.image.enumerate_pixels() {
+            let i = (((y + offset_y + padding) * stride + x + offset_x + padding) * 4) as usize;
+            dst[i..i + 4].copy_from_slice(&convert(pixel.channels()));
+        }
+    } else {
+        // Sample the frame pixel nearest to each buffer pixel's center.
+        let source = |i: u32, offset: u32, len: u32| {
+            let at = ((i as f64 + 0.5) / render_scale) as u32;
+            at.checked_sub(offset).filter(|&at| at < len)
+        };
+        let columns: Vec<_> = (0..buffer_w)
+            .map(|x| source(x, offset_x, frame.image.width()))
+            .collect();
+        for y in 0..buffer_h {
+            let Some(src_y) = source(y, offset_y, frame.image.height()) else {
+                continue;
+            };
+            for (x, src_x) in columns.iter().enumerate() {
+                let Some(src_x) = *src_x else {
+                    continue;
+                };
+                let i = (((y + padding) * stride + x as u32 + padding) * 4) as usize;
+                let p = frame.image.get_pixel(src_x, src_y).channels();
+                dst[i..i + 4].copy_from_slice(&convert(p));
+            }
+        }
+    }
+}
+
+/// Half the width and height of a `--footprints` print, in surface pixels.
+const FOOTPRINT_RADIUS: (f64, f64) = (5.0, 2.5);
+
+/// Side of a `--debug-alpha` square, in buffer pixels.
+const CHECKER_SIZE: u32 = 8;
+
+/// Draws a sprite buffer `stride` pixels wide, as `blit_frame` left it,
+/// over light and dark gray squares for `--debug-alpha`. The `padding`
+/// along the top and left stays clear.
+fn checkerboard(dst: &mut [u8], stride: u32, padding: u32) {
+    for (i, pixel) in dst.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i as u32 % stride, i as u32 / stride);
+        let (Some(x), Some(y)) = (x.checked_sub(padding), y.checked_sub(padding)) else {
+            continue;
+        };
+        let gray = match (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 {
+            0 => 0x99,
+            _ => 0x66,
+        };
+        let rest = 1.0 - pixel[3] as f32 / 255.0;
+        for c in &mut pixel[..3] {
+            *c = c.saturating_add((gray as f32 * rest).round() as u8);
+        }
+        pixel[3] = 0xff;
+    }
+}
+
+/// The `shm_pixel` filter for a gopher with `tint`, faded to `alpha`.
+fn pixel_filter(tint: Option<Color>, alpha: f32) -> [f32; 4] {
+    let [r, g, b, _] = tint.map_or([0xff; 4], |Color(tint)| tint);
+    let channel = |c: u8| c as f32 / 255.0 * alpha;
+    [channel(r), channel(g), channel(b), alpha]
+}
+
+/// Size of a `--windowed` window when the compositor lets us choose.
+const WINDOWED_SIZE: (u32, u32) = (640, 480);
+
+/// Mixed into `--seed` for the `--random-skin-per-crossing` choices.
+const SKIN_SEED: u64 = 0x736b_696e;
+
+/// Walk speed multipliers for `--focus-react`.
+const FOCUSED_SPEED: f64 = 2.0;
+const UNFOCUSED_SPEED: f64 = 0.5;
+
+/// Walk speed multiplier in the cheat mode, and how long its rainbow takes
+/// to go through every hue.
+const CHEAT_SPEED: f64 = 2.0;
+const RAINBOW_MS: u32 = 2000;
+
+/// Decodes the `states` array of a toplevel configure, which is a packed
+/// list of native-endian `u32` values rather than one state per byte.
+fn toplevel_states(states: &[u8]) -> impl Iterator<Item = xdg_toplevel::State> + '_ {
+    states
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+        .filter_map(|state| xdg_toplevel::State::try_from(state).ok())
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(seat, &event);
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Keyboard) {
+                seat.get_keyboard(qh, ());
+            }
+            if capabilities.contains(wl_seat::Capability::Pointer)
+                && state.config.lock_pointer
+                && state.pointer.is_none()
+            {
+                if let Some(manager) = state.relative_pointer_manager.as_ref() {
+                    let pointer = seat.get_pointer(qh, ());
+                    let relative_pointer = manager.get_relative_pointer(&pointer, qh, ());
+                    state.pointer = Some((pointer, relative_pointer));
+                    state.lock_pointer(qh);
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        match event {
+            // Keys only count between these two. A fullscreen window usually
+            // has the focus from the start, but the compositor still sends
+            // an Enter for it before any key.
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => state.read_keymap(fd, size),
+            wl_keyboard::Event::Enter { .. } => state.keyboard_focused = true,
+            wl_keyboard::Event::Leave { .. } => state.keyboard_focused = false,
+            wl_keyboard::Event::Modifiers { mods_depressed, .. } => {
+                state.modifiers = mods_depressed;
+            }
+            wl_keyboard::Event::Key {
+                serial,
+                key,
+                state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+                ..
+            } if state.keyboard_focused => {
+                state.input_serial = Some(serial);
+                if state.modifiers != 0 {
+                    state.cheat.reset();
+                } else if state.cheat.press(key) {
+                    state.cheat_mode = !state.cheat_mode;
+                    state.update_speed();
+                    verbose!(
+                        state.config,
+                        "cheat mode {}",
+                        if state.cheat_mode { "on" } else { "off" }
+                    );
+                }
+                match state.keys.action(key) {
+                    // The first press only frees a locked pointer.
+                    Some(Action::Quit) if state.locked_pointer.is_some() => state.unlock_pointer(),
+                    Some(Action::Quit) => state.quit(),
+                    Some(Action::Pause) => state.paused = !state.paused,
+                    Some(Action::Teleport) => state.teleport(),
+                    Some(Action::NextSkin) => state.next_skin = true,
+                    Some(Action::ToggleFullscreen) => state.toggle_fullscreen(),
+                    Some(Action::StepForward) if state.paused => state.step_paused(true),
+                    Some(Action::StepBack) if state.paused => state.step_paused(false),
+                    Some(Action::Jump) => state.hop(),
+                    _ if state.config.hop_on_key => state.hop(),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        // Clicking the window locks the pointer again after ESC freed it.
+        if let wl_pointer::Event::Button {
+            serial,
+            state: WEnum::Value(wl_pointer::ButtonState::Pressed),
+            ..
+        } = event
+        {
+            state.input_serial = Some(serial);
+            state.lock_pointer(qh);
+        }
+    }
+}
+
+impl Dispatch<xdg_activation_token_v1::XdgActivationTokenV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        token: &xdg_activation_token_v1::XdgActivationTokenV1,
+        event: xdg_activation_token_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(token, &event);
+        if let xdg_activation_token_v1::Event::Done { token: name } = event {
+            if let (Some(activation), Some(surface)) = (&state.activation, &state.parent_surface) {
+                activation.activate(name, surface);
+            }
+            token.destroy();
+        }
+    }
+}
+
+impl Dispatch<zxdg_imported_v2::ZxdgImportedV2, ()> for State {
+    fn event(
+        state: &mut Self,
+        imported: &zxdg_imported_v2::ZxdgImportedV2,
+        event: zxdg_imported_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(imported, &event);
+        // Sent for a handle that was never good as well as once the parent
+        // closes, and the window stays open either way.
+        if let zxdg_imported_v2::Event::Destroyed = event {
+            eprintln!("wlgopher: warning: the --parent window is gone or was never there");
+            imported.destroy();
+            state.imported_parent = None;
+        }
+    }
+}
+
+impl Dispatch<zwp_locked_pointer_v1::ZwpLockedPointerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        event: zwp_locked_pointer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        match event {
+            zwp_locked_pointer_v1::Event::Locked => state.pointer_locked = true,
+            // A oneshot lock is gone for good once it's lifted.
+            zwp_locked_pointer_v1::Event::Unlocked => state.unlock_pointer(),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwp_relative_pointer_v1::ZwpRelativePointerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        event: zwp_relative_pointer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.trace_event(proxy, &event);
+        if let zwp_relative_pointer_v1::Event::RelativeMotion { dx, dy, .. } = event {
+            if !state.pointer_locked {
+                return;
+            }
+            // Only motion along the direction the gopher walks counts.
+            let along = match state.rotation {
+                Rotation::Normal => dx,
+                Rotation::Rotate90 => dy,
+                Rotation::Rotate180 => -dx,
+                Rotation::Rotate270 => -dy,
+            };
+            // Mirrored, the gopher goes left on the screen walking right.
+            let along = match state.mirror
+                && matches!(state.rotation, Rotation::Normal | Rotation::Rotate180)
+            {
+                true => -along,
+                false => along,
+            };
+            for sprite in &mut state.sprites {
+                sprite.animation.chase(along);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use wayland_client::EventQueue;
+
+    use super::*;
+
+    /// Stands in for the compositor by handing events straight to `State`'s
+    /// `Dispatch` impls. Requests go out on a socket nobody reads, which is
+    /// all the client side needs to create its objects.
+    struct FakeCompositor {
+        server: UnixStream,
+        conn: Connection,
+        _queue: EventQueue<State>,
+        qh: QueueHandle<State>,
+        state: State,
+    }
+
+    impl FakeCompositor {
+        /// Comes up the way `run` does: globals announced, surfaces and
+        /// buffers set up, but nothing configured yet.
+        fn new(config: Config) -> FakeCompositor {
+            let (client, server) = UnixStream::pair().unwrap();
+            let conn = Connection::from_socket(client).unwrap();
+            let queue = conn.new_event_queue();
+            let qh = queue.handle();
+            let mut compositor = FakeCompositor {
+                server,
+                conn,
+                _queue: queue,
+                qh,
+                state: State::new(config).unwrap(),
+            };
+
+            let registry = compositor.conn.display().get_registry(&compositor.qh, ());
+            for (name, interface) in [
+                "wl_compositor",
+                "wl_subcompositor",
+                "wl_shm",
+                "xdg_wm_base",
+                "wl_output",
+                "wp_viewporter",
+                "zxdg_importer_v2",
+                "wp_content_type_manager_v1",
+                "wl_seat",
+                "ext_idle_notifier_v1",
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                compositor.send(
+                    &registry,
+                    wl_registry::Event::Global {
+                        name: name as u32 + 1,
+                        interface: interface.into(),
+                        version: 1,
+                    },
+                );
+            }
+
+            compositor
+                .state
+                .registry_post_process(&compositor.qh)
+                .unwrap();
+            compositor
+        }
+
+        fn send<I, U>(&mut self, proxy: &I, event: I::Event)
+        where
+            I: Proxy,
+            U: Send + Sync + 'static,
+            State: Dispatch<I, U>,
+        {
+            let data = proxy.data::<U>().unwrap();
+            <State as Dispatch<I, U>>::event(
+                &mut self.state,
+                proxy,
+                event,
+                data,
+                &self.conn,
+                &self.qh,
+            );
+        }
+
+        /// The requests sent since the last call, as the object and opcode
+        /// of each.
+        fn requests(&mut self) -> Vec<(u32, u16)> {
+            use std::io::Read;
+
+            self.conn.flush().unwrap();
+            self.server.set_nonblocking(true).unwrap();
+            let mut bytes = Vec::new();
+            if let Err(e) = self.server.read_to_end(&mut bytes) {
+                assert_eq!(e.kind(), std::io::ErrorKind::WouldBlock);
+            }
+            let mut requests = Vec::new();
+            let mut rest = &bytes[..];
+            while rest.len() >= 8 {
+                let word = |i: usize| u32::from_ne_bytes(rest[i..i + 4].try_into().unwrap());
+                let (object, size_and_opcode) = (word(0), word(4));
+                requests.push((object, size_and_opcode as u16));
+                rest = &rest[(size_and_opcode >> 16) as usize..];
+            }
+            requests
+        }
+
+        fn configure(&mut self, (width, height): (i32, i32), states: &[xdg_toplevel::State]) {
+            let (xdg_surface, toplevel) = self.state.parent_xdg_surface.clone().unwrap();
+            let states = states
+                .iter()
+                .flat_map(|state| (*state as u32).to_ne_bytes())
+                .collect();
+            self.send(
+                &toplevel,
+                xdg_toplevel::Event::Configure {
+                    width,
+                    height,
+                    states,
+                },
+            );
+            self.send(&xdg_surface, xdg_surface::Event::Configure { serial: 1 });
+        }
+
+        /// Resizes the window and draws it again, returning the opaque
+        /// region it ends up with.
+        fn opaque_region_after_resize(&mut self, size: (i32, i32)) -> Option<(u64, u64)> {
+            let qh = self.qh.clone();
+            // Resizing allocates new background buffers, and the gopher
+            // still has a free one.
+            self.configure(size, &[]);
+            self.state.draw(&qh).unwrap();
+            self.state.opaque_region
+        }
+
+        fn buffers_in_use(&self) -> usize {
+            (self.state.sprites.iter())
+                .flat_map(|sprite| &sprite.buffers.0)
+                .filter(|buffer| buffer.in_use)
+                .count()
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            quiet: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn fullscreen_configure_sets_the_area() {
+        let mut compositor = FakeCompositor::new(config());
+        assert!(!compositor.state.configured);
+
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        assert!(compositor.state.configured);
+        assert!(compositor.state.repaint_required);
+        assert_eq!(compositor.state.surface_area, (800, 600));
+        assert!(!compositor.state.fullscreen_requested);
+
+        // Only the configure answering the fullscreen request sets the area.
+        compositor.configure((640, 480), &[xdg_toplevel::State::Fullscreen]);
+        assert_eq!(compositor.state.surface_area, (800, 600));
+    }
+
+    #[test]
+    fn draws_every_gopher_above_the_ground() {
+        let mut compositor = FakeCompositor::new(Config {
+            ground: 40,
+            margin: 20,
+            gophers: vec![GopherConfig::default(), GopherConfig::default()],
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        for sprite in &compositor.state.sprites {
+            let height = sprite.animation.size().1 as f64;
+            assert_eq!(sprite.animation.position().1, 600.0 - 40.0 - height);
+        }
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.buffers_in_use(), 2);
+    }
+
+    #[test]
+    fn changes_skins_at_the_edges() {
+        let dir = tempfile::tempdir().unwrap();
+        let skin = dir.path().join("skin.png");
+        image::RgbaImage::new(30, 40).save(&skin).unwrap();
+        let mut compositor = FakeCompositor::new(Config {
+            skins: vec![skin],
+            random_skin_per_crossing: true,
+            seed: Some(1),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((200, 600), &[xdg_toplevel::State::Fullscreen]);
+        let size = compositor.state.sprites[0].animation.size();
+
+        // Two skins means every turn goes to the other one.
+        let mut sizes = vec![size];
+        for _ in 0..200 {
+            compositor.state.repaint_required = true;
+            compositor.state.draw(&qh).unwrap();
+            let sprite = &mut compositor.state.sprites[0];
+            for buffer in &mut sprite.buffers.0 {
+                buffer.in_use = false;
+            }
+            if sizes.last() != Some(&sprite.animation.size()) {
+                sizes.push(sprite.animation.size());
+            }
+        }
+        assert_eq!(sizes[..3], [size, (30, 40), size]);
+        let sprite = &compositor.state.sprites[0];
+        let (width, height) = sprite.animation.size();
+        assert_eq!(
+            sprite.buffers.0[0].mmap_range.len(),
+            (width * height * 4) as usize
+        );
+    }
+
+    #[test]
+    fn hints_at_the_content_type() {
+        let compositor = FakeCompositor::new(config());
+        assert!(compositor.state.content_type.is_some());
+    }
+
+    #[test]
+    fn walks_within_the_region() {
+        let mut compositor = FakeCompositor::new(Config {
+            region: Some(Region {
+                x: 100,
+                y: 50,
+                size: Size(1000, 200),
+            }),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        // Cut down to the 700 pixels the window has right of x = 100.
+        let sprite = &compositor.state.sprites[0];
+        assert_eq!(sprite.animation.area(), (700, 200));
+        let (width, height) = sprite.animation.size();
+        let (x, _) = sprite.animation.position();
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(
+            compositor.state.sprites[0].placed,
+            Some((x.round() as i32 + 100, 250 - height as i32))
+        );
+        assert!(x + width as f64 <= 700.0);
+    }
+
+    #[test]
+    fn mirrors_where_the_gopher_is_placed() {
+        // The fake wl_surface is version 1, which can't flip buffers.
+        let mut compositor = FakeCompositor::new(Config {
+            mirror_output: true,
+            ..config()
+        });
+        assert!(!compositor.state.mirror);
+
+        let qh = compositor.qh.clone();
+        compositor.state.mirror = true;
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        let sprite = &compositor.state.sprites[0];
+        let (width, height) = sprite.animation.size();
+        let (x, _) = sprite.animation.position();
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(
+            compositor.state.sprites[0].placed,
+            Some((
+                (800.0 - x - width as f64).round() as i32,
+                600 - height as i32
+            ))
+        );
+    }
+
+    #[test]
+    fn prerender_keeps_a_frame_ready_without_falling_behind() {
+        // Runs 30 frames on a compositor that releases every buffer `hold`
+        // frames after it's been replaced, returning where the gopher was
+        // shown and how many frames it missed.
+        let run = |prerender, hold| {
+            let mut compositor = FakeCompositor::new(Config {
+                prerender,
+                seed: Some(1),
+                ..config()
+            });
+            let qh = compositor.qh.clone();
+            compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+            let (mut shown, mut dropped) = (Vec::new(), 0);
+            let mut replaced = VecDeque::new();
+            let mut on_screen: Option<wl_buffer::WlBuffer> = None;
+            for frame in 0..30 {
+                compositor.state.repaint_required = true;
+                compositor.state.draw(&qh).unwrap();
+                if compositor.state.repaint_required {
+                    dropped += 1;
+                } else {
+                    let sprite = &compositor.state.sprites[0];
+                    let queued = sprite.shown.unwrap();
+                    shown.push(queued.position);
+                    let buffer = sprite.buffers.0[queued.buffer].buffer.clone();
+                    if let Some(old) = on_screen.replace(buffer) {
+                        replaced.push_back((old, frame));
+                    }
+                }
+
+                while let Some((buffer, _)) = replaced.front().filter(|(_, at)| frame - at >= hold)
+                {
+                    let buffer = buffer.clone();
+                    replaced.pop_front();
+                    compositor.send(&buffer, wl_buffer::Event::Release);
+                }
+                compositor.state.prerender();
+            }
+            (shown, dropped)
+        };
+
+        let (on_time, dropped) = run(false, 0);
+        assert_eq!(dropped, 0);
+        assert!(run(false, 1).1 > 0);
+        // Rendering ahead shows the very same frames, and none go missing
+        // when buffers come back late.
+        assert_eq!(run(true, 0), (on_time.clone(), 0));
+        assert_eq!(run(true, 1), (on_time, 0));
+    }
+
+    #[test]
+    fn reloads_frames_in_place_and_keeps_them_on_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame = dir.path().join("01.png");
+        image::RgbaImage::new(20, 10).save(&frame).unwrap();
+        let mut compositor = FakeCompositor::new(Config {
+            frames: Some(dir.path().to_owned()),
+            watch: true,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+        compositor.state.draw(&qh).unwrap();
+        let x = compositor.state.sprites[0].animation.position().0;
+
+        image::RgbaImage::new(30, 40).save(&frame).unwrap();
+        compositor.state.reload_frames(dir.path(), &qh).unwrap();
+        let sprite = &compositor.state.sprites[0];
+        assert_eq!(sprite.animation.size(), (30, 40));
+        assert_eq!(sprite.animation.position(), (x, 600.0 - 40.0));
+        // New buffers at the new size, with the background after them.
+        assert_eq!(compositor.buffers_in_use(), 0);
+        assert_eq!(sprite.buffers.0[0].mmap_range.len(), 30 * 40 * 4);
+        assert_eq!(compositor.state.bg_offset, 4 + 2 * 30 * 40 * 4);
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.buffers_in_use(), 1);
+
+        // Caught halfway through being saved.
+        std::fs::write(&frame, b"\x89PNG").unwrap();
+        compositor.state.reload_frames(dir.path(), &qh).unwrap();
+        assert_eq!(compositor.state.sprites[0].animation.size(), (30, 40));
+    }
+
+    #[test]
+    fn opaque_backgrounds_get_an_opaque_region() {
+        let opaque_region = |config: Config, size| {
+            let mut compositor = FakeCompositor::new(Config {
+                windowed: true,
+                ..config
+            });
+            let qh = compositor.qh.clone();
+            compositor.configure(size, &[]);
+            compositor.state.draw(&qh).unwrap();
+            compositor.opaque_region_after_resize((700, 500))
+        };
+        let solid = || Config {
+            bg: Background::Solid,
+            ..config()
+        };
+
+        assert_eq!(opaque_region(solid(), (800, 600)), Some((700, 500)));
+        let translucent = Config {
+            bg_color: Color([0, 0, 0, 0x80]),
+            ..solid()
+        };
+        assert_eq!(opaque_region(translucent, (800, 600)), None);
+        let opted_out = Config {
+            no_opaque_region: true,
+            ..solid()
+        };
+        assert_eq!(opaque_region(opted_out, (800, 600)), None);
+        let xrgb = Config {
+            format: PixelFormat::Xrgb,
+            bg_color: Color([0, 0, 0, 0x80]),
+            ..solid()
+        };
+        assert_eq!(opaque_region(xrgb, (800, 600)), Some((700, 500)));
+    }
+
+    #[test]
+    fn xrgb_gophers_are_drawn_opaque() {
+        let mut compositor = FakeCompositor::new(Config {
+            format: PixelFormat::Xrgb,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+
+        let sprite = &compositor.state.sprites[0];
+        let range = sprite.buffers.0[sprite.shown.unwrap().buffer]
+            .mmap_range
+            .clone();
+        let pixels = &compositor.state.mmap[range];
+        assert!(pixels.chunks_exact(4).all(|p| p[3] == 0xff));
+        // The default gopher has see-through corners, which come out black.
+        assert_eq!(pixels[..4], [0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn tiles_are_drawn_once_behind_the_walker() {
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            tile_gophers: Some(10),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((400, 300), &[]);
+        let bg_in_use = |compositor: &FakeCompositor| {
+            (compositor.state.bg_buffers.0.iter())
+                .filter(|buffer| buffer.in_use)
+                .count()
+        };
+
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(bg_in_use(&compositor), 1);
+        let drawn = compositor.state.bg_buffers.0[0].mmap_range.clone();
+        assert!(compositor.state.mmap[drawn].iter().any(|&byte| byte != 0));
+
+        // The walker moves on, the tiles stay as they were.
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(bg_in_use(&compositor), 1);
+        assert_eq!(compositor.buffers_in_use(), 2);
+    }
+
+    #[test]
+    fn background_image_is_rescaled_with_the_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backdrop.png");
+        image::RgbaImage::from_pixel(8, 8, image::Rgba([0xff, 0, 0, 0xff]))
+            .save(&path)
+            .unwrap();
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            bg_image: Some(path),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+
+        let mut sizes = Vec::new();
+        for size in [(400, 300), (200, 100)] {
+            compositor.configure(size, &[]);
+            compositor.state.draw(&qh).unwrap();
+            let scaled = compositor.state.bg_image_scaled.as_ref().unwrap();
+            assert_eq!(scaled.dimensions(), compositor.state.bg_size);
+            sizes.push(scaled.dimensions());
+            let buffer = (compositor.state.bg_buffers.0.iter())
+                .find(|buffer| buffer.in_use)
+                .unwrap();
+            let drawn = &compositor.state.mmap[buffer.mmap_range.clone()];
+            assert!(drawn.chunks_exact(4).all(|p| p == [0, 0, 0xff, 0xff]));
+            compositor
+                .state
+                .bg_buffers
+                .0
+                .iter_mut()
+                .for_each(|b| b.in_use = false);
+        }
+        assert_ne!(sizes[0], sizes[1]);
+        assert!(compositor.state.opaque_region.is_some());
+    }
+
+    #[test]
+    fn toggles_fullscreen_and_back_to_the_window_size() {
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            ..config()
+        });
+        compositor.configure((600, 400), &[]);
+        assert_eq!(compositor.state.surface_area, (600, 400));
+
+        compositor.state.toggle_fullscreen();
+        assert!(compositor.state.fullscreen_requested);
+        compositor.configure((1920, 1080), &[xdg_toplevel::State::Fullscreen]);
+        assert_eq!(compositor.state.surface_area, (1920, 1080));
+        let area = compositor.state.sprites[0].animation.area();
+        assert_eq!(area.0, 1920);
+
+        compositor.state.toggle_fullscreen();
+        assert!(!compositor.state.fullscreen_requested);
+        // Left to pick a size, it goes back to the one it had.
+        compositor.configure((0, 0), &[]);
+        assert_eq!(compositor.state.surface_area, (600, 400));
+    }
+
+    #[test]
+    fn fullscreen_on_focused_waits_for_the_output() {
+        let mut compositor = FakeCompositor::new(Config {
+            fullscreen_on_focused: true,
+            ..config()
+        });
+        assert!(compositor.state.fullscreen_pending);
+        assert!(!compositor.state.fullscreen_requested);
+        compositor.configure((600, 400), &[]);
+        assert_eq!(compositor.state.surface_area, (600, 400));
+
+        let output = compositor.state.outputs[0].clone();
+        let surface = compositor.state.parent_surface.clone().unwrap();
+        compositor.send(&surface, wl_surface::Event::Enter { output });
+        assert!(!compositor.state.fullscreen_pending);
+        assert!(compositor.state.fullscreen_requested);
+        compositor.configure((1920, 1080), &[xdg_toplevel::State::Fullscreen]);
+        assert_eq!(compositor.state.surface_area, (1920, 1080));
+    }
+
+    #[test]
+    fn all_outputs_window_goes_with_its_output() {
+        let mut compositor = FakeCompositor::new(config());
+        compositor.state.output_global = Some(20);
+        let registry = compositor.conn.display().get_registry(&compositor.qh, ());
+        for name in [20, 21] {
+            compositor.send(
+                &registry,
+                wl_registry::Event::Global {
+                    name,
+                    interface: "wl_output".into(),
+                    version: 1,
+                },
+            );
+        }
+        // After the output that was there from the start.
+        assert_eq!(compositor.state.fullscreen_output, Some(1));
+
+        compositor.send(&registry, wl_registry::Event::GlobalRemove { name: 21 });
+        assert!(compositor.state.running);
+        compositor.send(&registry, wl_registry::Event::GlobalRemove { name: 20 });
+        assert!(!compositor.state.running);
+    }
+
+    #[test]
+    fn follows_the_focus_to_its_output() {
+        let mut compositor = FakeCompositor::new(Config {
+            follow_active_window: true,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        let registry = compositor.conn.display().get_registry(&qh, ());
+        let second = registry.bind::<wl_output::WlOutput, _, _>(100, 1, &qh, ());
+        compositor.state.outputs.push(second.clone());
+        let first = compositor.state.outputs[0].clone();
+
+        let backend = compositor.conn.backend().downgrade();
+        let window = |outputs: Vec<wl_output::WlOutput>, activated| ForeignToplevel {
+            handle: Proxy::inert(backend.clone()),
+            outputs,
+            activated,
+        };
+        compositor.state.foreign_toplevels = vec![
+            window(vec![first.clone()], false),
+            window(vec![second, first], true),
+        ];
+        compositor.state.follow_active_window();
+        assert_eq!(compositor.state.fullscreen_output, Some(1));
+
+        compositor.state.foreign_toplevels[1].activated = false;
+        compositor.state.foreign_toplevels[0].activated = true;
+        compositor.state.follow_active_window();
+        assert_eq!(compositor.state.fullscreen_output, Some(0));
+
+        // A window stays where it is.
+        compositor.state.windowed = true;
+        compositor.state.foreign_toplevels[0].activated = false;
+        compositor.state.foreign_toplevels[1].activated = true;
+        compositor.state.follow_active_window();
+        assert_eq!(compositor.state.fullscreen_output, Some(0));
+    }
+
+    #[test]
+    fn single_surface_copies_the_gophers_into_the_window() {
+        let mut compositor = FakeCompositor::new(Config {
+            single_surface: true,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        assert!(compositor.state.sprites[0].surface.is_none());
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+
+        let state = &compositor.state;
+        assert_eq!(state.bg_size, (800, 600));
+        assert_eq!(state.bg_buffers.0.iter().filter(|b| b.in_use).count(), 1);
+        // The gopher's own buffers are only somewhere to render into.
+        assert!(state.sprites[0].buffers.0.iter().all(|b| !b.in_use));
+
+        let [((x, y), (width, height))] = state.composited[..] else {
+            panic!("{:?}", state.composited);
+        };
+        assert_eq!(y + height as i64, 600);
+        let buffer = state.bg_buffers.0.iter().find(|b| b.in_use).unwrap();
+        let pixels = &state.mmap[buffer.mmap_range.clone()];
+        let opaque = |x: i64, y: i64| pixels[((y * 800 + x) * 4 + 3) as usize] != 0;
+        let mut gopher =
+            (y..y + height as i64).flat_map(|y| (x..x + width as i64).map(move |x| (x, y)));
+        assert!(gopher.any(|(x, y)| opaque(x, y)));
+        assert!(!opaque(799, 0));
+    }
+
+    #[test]
+    fn describes_objects_for_the_protocol_trace() {
+        let mut compositor = FakeCompositor::new(config());
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        let state = &compositor.state;
+
+        let surface = state.parent_surface.as_ref().unwrap().id();
+        assert_eq!(
+            state.describe(&surface),
+            format!("window surface ({})", surface)
+        );
+        let buffer = state.sprites[0].buffers.0[1].buffer.id();
+        assert_eq!(
+            state.describe(&buffer),
+            format!("gopher 0 buffer 1 ({})", buffer)
+        );
+        let output = state.outputs[0].id();
+        assert_eq!(state.describe(&output), output.to_string());
+    }
+
+    #[test]
+    fn benches_without_a_compositor() {
+        let config = Config {
+            seed: Some(1),
+            ..config()
+        };
+        bench(config.clone(), 10).unwrap();
+        // The same frames as the window gets.
+        let mut compositor = FakeCompositor::new(config.clone());
+        let qh = compositor.qh.clone();
+        compositor.configure((1920, 1080), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+        let sprite = &compositor.state.sprites[0];
+        let drawn = &compositor.state.mmap[sprite.buffers.0[0].mmap_range.clone()];
+        let mut buffer = vec![0xaa; drawn.len()];
+        let animation = build_animation(&config, compositor.state.child_padding).unwrap();
+        blit_frame(
+            &mut buffer,
+            &animation,
+            1.0,
+            compositor.state.child_padding,
+            pixel_filter(None, 1.0),
+            PixelFormat::Argb,
+        );
+        assert!(buffer == drawn);
+    }
+
+    #[test]
+    fn debug_overlay_is_redrawn_every_frame() {
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            debug_overlay: true,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[]);
+
+        // Both gopher buffers get used, and both overlay ones with them.
+        compositor.state.draw(&qh).unwrap();
+        compositor.state.draw(&qh).unwrap();
+        let overlay = compositor.state.overlay.as_ref().unwrap();
+        assert!(overlay.buffers.0.iter().all(|buffer| buffer.in_use));
+    }
+
+    #[test]
+    fn debug_alpha_shows_a_checkerboard_through_the_gopher() {
+        // 18x17 with a pixel of padding, half see-through in the middle.
+        let mut buffer = vec![0; 18 * 17 * 4];
+        buffer[(9 * 18 + 9) * 4..(9 * 18 + 10) * 4].copy_from_slice(&[0x40, 0, 0, 0x80]);
+        checkerboard(&mut buffer, 18, 1);
+        let pixel = |x: usize, y: usize| &buffer[(y * 18 + x) * 4..(y * 18 + x + 1) * 4];
+        assert_eq!(pixel(0, 0), [0; 4]);
+        assert_eq!(pixel(1, 1), [0x99, 0x99, 0x99, 0xff]);
+        assert_eq!(pixel(9, 1), [0x66, 0x66, 0x66, 0xff]);
+        assert_eq!(pixel(9, 9), [0x40 + 0x4c, 0x4c, 0x4c, 0xff]);
+    }
+
+    #[test]
+    fn damages_only_the_pixels_that_changed() {
+        // A 4x3 buffer with two pixels changed.
+        let old = vec![0u8; 4 * 3 * 4];
+        let mut new = old.clone();
+        assert_eq!(changed_rect(&old, &new, 4), None);
+
+        new[(4 + 2) * 4] = 0xff;
+        new[(2 * 4 + 1) * 4 + 3] = 0xff;
+        assert_eq!(changed_rect(&old, &new, 4), Some((1, 1, 2, 2)));
+    }
+
+    #[test]
+    fn shrinks_gophers_too_big_for_the_area() {
+        assert_eq!(
+            fit_sprite((5.0, 0.0), (100, 100), (400, 300), false),
+            ((5.0, 0.0), (100, 100))
+        );
+        // Too short: squashed to the area's height, or shrunk evenly and
+        // centered in the gopher's own width.
+        assert_eq!(
+            fit_sprite((5.0, 0.0), (100, 100), (400, 50), false),
+            ((5.0, 0.0), (100, 50))
+        );
+        assert_eq!(
+            fit_sprite((5.0, 0.0), (100, 100), (400, 50), true),
+            ((30.0, 0.0), (50, 50))
+        );
+        assert_eq!(
+            fit_sprite((0.0, 0.0), (100, 100), (80, 40), true),
+            ((20.0, 0.0), (40, 40))
+        );
+    }
+
+    #[test]
+    fn pulses_around_the_feet() {
+        assert_eq!(
+            pulse_sprite((10.0, 20.0), (100, 50), 1.0),
+            ((10.0, 20.0), (100, 50))
+        );
+        assert_eq!(
+            pulse_sprite((10.0, 20.0), (100, 50), 1.2),
+            ((0.0, 10.0), (120, 60))
+        );
+        assert_eq!(
+            pulse_sprite((10.0, 20.0), (100, 50), 0.8),
+            ((20.0, 30.0), (80, 40))
+        );
+    }
+
+    #[test]
+    fn splash_holds_the_gophers_until_its_time_is_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let splash = dir.path().join("logo.png");
+        image::RgbaImage::from_pixel(40, 20, image::Rgba([0xff; 4]))
+            .save(&splash)
+            .unwrap();
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            splash: Some(splash),
+            splash_ms: 1000,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((400, 300), &[]);
+
+        let start = compositor.state.sprites[0].animation.position();
+        compositor.state.draw(&qh).unwrap();
+        let splash = compositor.state.splash.as_ref().unwrap();
+        assert!(splash.buffers.0[0].in_use);
+        assert_eq!(compositor.state.sprites[0].shown.unwrap().alpha, 0.0);
+
+        compositor.state.splash_from = Some(500);
+        compositor.state.frame_time = 1400;
+        compositor.state.draw(&qh).unwrap();
+        assert!(compositor.state.splash.is_some());
+        assert_eq!(compositor.state.sprites[0].animation.position(), start);
+
+        compositor.state.frame_time = 1500;
+        let released = compositor.state.sprites[0].buffers.0[0].buffer.clone();
+        compositor.send(&released, wl_buffer::Event::Release);
+        compositor.state.draw(&qh).unwrap();
+        assert!(compositor.state.splash.is_none());
+        assert_eq!(compositor.state.sprites[0].shown.unwrap().alpha, 1.0);
+    }
+
+    #[test]
+    fn windowed_configures_stay_within_the_size_limits() {
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            max_size: Some(Size(1000, 1000)),
+            ..config()
+        });
+        let ((min_w, min_h), _) = compositor.state.size_limits();
+
+        compositor.configure((0, 0), &[]);
+        assert_eq!(compositor.state.surface_area, (640, 480));
+
+        compositor.configure((10, 10), &[]);
+        assert_eq!(compositor.state.surface_area, (min_w as u64, min_h as u64));
+
+        compositor.configure((5000, 700), &[]);
+        assert_eq!(compositor.state.surface_area, (1000, 700));
+    }
+
+    #[cfg(feature = "memfd")]
+    #[test]
+    fn pool_file_grows_but_never_shrinks() {
+        let file = pool_file().unwrap();
+        file.set_len(4096).unwrap();
+        file.set_len(8192).unwrap();
+        assert!(file.set_len(4096).is_err());
+        assert_eq!(file.metadata().unwrap().len(), 8192);
+    }
+
+    #[test]
+    fn draws_into_free_buffers_until_released() {
+        let mut compositor = FakeCompositor::new(config());
+        let qh = compositor.qh.clone();
+
+        // Nothing is drawn before the first configure.
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.buffers_in_use(), 0);
+
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.buffers_in_use(), 2);
+        assert!(!compositor.state.repaint_required);
+
+        // With both buffers held by the compositor, drawing waits.
+        compositor.state.repaint_required = true;
+        compositor.state.draw(&qh).unwrap();
+        assert!(compositor.state.repaint_required);
+
+        let buffer = compositor.state.sprites[0].buffers.0[0].buffer.clone();
+        compositor.send(&buffer, wl_buffer::Event::Release);
+        assert_eq!(compositor.buffers_in_use(), 1);
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.buffers_in_use(), 2);
+    }
+
+    #[test]
+    fn only_rotates_for_transform() {
+        let mut compositor = FakeCompositor::new(config());
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        let output = compositor.state.outputs[0].clone();
+        compositor.send(
+            &output,
+            wl_output::Event::Geometry {
+                x: 0,
+                y: 0,
+                physical_width: 0,
+                physical_height: 0,
+                subpixel: WEnum::Value(wl_output::Subpixel::Unknown),
+                make: String::new(),
+                model: String::new(),
+                transform: WEnum::Value(wl_output::Transform::_90),
+            },
+        );
+        let surface = compositor.state.parent_surface.clone().unwrap();
+        compositor.send(&surface, wl_surface::Event::Enter { output });
+        // The compositor has already turned the surface upright.
+        assert_eq!(compositor.state.rotation, Rotation::Normal);
+        assert_eq!(compositor.state.sprites[0].animation.area(), (800, 600));
+
+        let mut compositor = FakeCompositor::new(Config {
+            transform: Some(Rotation::Rotate90),
+            ..config()
+        });
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        assert_eq!(compositor.state.rotation, Rotation::Rotate90);
+        assert_eq!(compositor.state.sprites[0].animation.area(), (600, 800));
+    }
+
+    #[test]
+    fn pause_unfocused_stops_and_restarts_the_frame_loop() {
+        let mut compositor = FakeCompositor::new(Config {
+            pause_unfocused: true,
+            ..config()
+        });
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        let callback = compositor.state.frame_surface().frame(
+            &compositor.qh,
+            FrameDone {
+                base_time: None,
+                count: 0,
+            },
+        );
+        compositor.state.repaint_required = false;
+        compositor.send(&callback, wl_callback::Event::Done { callback_data: 16 });
+        assert!(compositor.state.frame_loop_stopped);
+        assert!(!compositor.state.repaint_required);
+
+        compositor.configure((800, 600), &[xdg_toplevel::State::Activated]);
+        assert!(compositor.state.activated);
+        assert!(!compositor.state.frame_loop_stopped);
+        assert!(compositor.state.repaint_required);
+    }
+
+    #[test]
+    fn pause_when_idle_stops_the_frame_loop_until_input() {
+        let mut compositor = FakeCompositor::new(Config {
+            pause_when_idle: Some(60),
+            ..config()
+        });
+        compositor.configure((800, 600), &[xdg_toplevel::State::Activated]);
+        let notification = compositor.state.idle_notification.clone().unwrap();
+
+        compositor.send(&notification, ext_idle_notification_v1::Event::Idled);
+        let callback = compositor.state.frame_surface().frame(
+            &compositor.qh,
+            FrameDone {
+                base_time: None,
+                count: 0,
+            },
+        );
+        compositor.state.repaint_required = false;
+        compositor.send(&callback, wl_callback::Event::Done { callback_data: 16 });
+        assert!(compositor.state.frame_loop_stopped);
+        assert!(!compositor.state.repaint_required);
+
+        compositor.send(&notification, ext_idle_notification_v1::Event::Resumed);
+        assert!(!compositor.state.frame_loop_stopped);
+        assert!(compositor.state.repaint_required);
+    }
+
+    #[test]
+    fn max_fps_skips_frame_callbacks() {
+        let mut compositor = FakeCompositor::new(Config {
+            max_fps: Some(30),
+            ..config()
+        });
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        // wl_surface.commit
+        const COMMIT: u16 = 6;
+        let sprite = (compositor.state.frame_surface().id()).protocol_id();
+        let window = (compositor.state.parent_surface.as_ref().unwrap().id()).protocol_id();
+        compositor.requests();
+
+        let mut drawn = Vec::new();
+        for i in 0..6 {
+            let callback = compositor.state.frame_callback.clone().unwrap();
+            compositor.state.repaint_required = false;
+            // A 60 Hz output, with some jitter in the timestamps.
+            let time = 1000 + i * 50 / 3;
+            compositor.send(
+                &callback,
+                wl_callback::Event::Done {
+                    callback_data: time,
+                },
+            );
+            assert_ne!(compositor.state.frame_callback, Some(callback));
+            let repaint = compositor.state.repaint_required;
+            drawn.push(repaint);
+
+            // Drawing commits the frames due; the skipped ones have to be
+            // committed right away for the next callback to come.
+            let requests = compositor.requests();
+            assert_eq!(requests.contains(&(sprite, COMMIT)), !repaint);
+            assert_eq!(requests.contains(&(window, COMMIT)), !repaint);
+        }
+        assert_eq!(drawn, [true, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn frame_timing_survives_the_clock_wrapping() {
+        assert_eq!(ms_since(u32::MAX - 9, 10), 20);
+        assert_eq!(ms_since(10, 4), 0);
+
+        let mut compositor = FakeCompositor::new(Config {
+            max_fps: Some(30),
+            ..config()
+        });
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        let mut done = |time: u32, base_time: Option<u32>| {
+            let callback = compositor.state.frame_surface().frame(
+                &compositor.qh,
+                FrameDone {
+                    base_time,
+                    count: 0,
+                },
+            );
+            compositor.state.repaint_required = false;
+            compositor.state.dropped_frames = 1;
+            compositor.send(
+                &callback,
+                wl_callback::Event::Done {
+                    callback_data: time,
+                },
+            );
+            (
+                compositor.state.repaint_required,
+                compositor.state.dropped_frames,
+            )
+        };
+        // Drawn across the wrap at the --max-fps pace, ...
+        assert!(done(u32::MAX - 20, None).0);
+        assert!(!done(u32::MAX - 4, None).0);
+        assert!(done(13, None).0);
+        // ... with the FPS line due 5 seconds on, and not for a timestamp
+        // from before its window started.
+        assert_eq!(done(5000, Some(u32::MAX - 20)).1, 0);
+        assert_eq!(done(5040, Some(5100)).1, 1);
+    }
+
+    #[test]
+    fn imports_the_parent_until_it_goes_away() {
+        let mut compositor = FakeCompositor::new(Config {
+            windowed: true,
+            parent: Some("exported-handle".into()),
+            ..config()
+        });
+        let imported = compositor.state.imported_parent.clone().unwrap();
+
+        compositor.send(&imported, zxdg_imported_v2::Event::Destroyed);
+        assert!(compositor.state.imported_parent.is_none());
+        assert!(compositor.state.running);
+    }
+
+    #[test]
+    fn close_and_escape_quit() {
+        let mut compositor = FakeCompositor::new(config());
+        let (_, toplevel) = compositor.state.parent_xdg_surface.clone().unwrap();
+        compositor.send(&toplevel, xdg_toplevel::Event::Close);
+        assert!(!compositor.state.running);
+
+        let mut compositor = FakeCompositor::new(config());
+        let keyboard = wl_keyboard::WlKeyboard::inert(compositor.conn.backend().downgrade());
+        let send = |compositor: &mut FakeCompositor, event| {
+            <State as Dispatch<wl_keyboard::WlKeyboard, ()>>::event(
+                &mut compositor.state,
+                &keyboard,
+                event,
+                &(),
+                &compositor.conn,
+                &compositor.qh,
+            )
+        };
+        let escape = || wl_keyboard::Event::Key {
+            serial: 1,
+            time: 0,
+            key: 1,
+            state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+        };
+        // Without the focus the key isn't ours.
+        send(&mut compositor, escape());
+        assert!(compositor.state.running);
+
+        let surface = compositor.state.parent_surface.clone().unwrap();
+        send(
+            &mut compositor,
+            wl_keyboard::Event::Enter {
+                serial: 1,
+                surface,
+                keys: Vec::new(),
+            },
+        );
+        send(&mut compositor, escape());
+        assert!(!compositor.state.running);
+    }
+
+    #[test]
+    fn pause_and_quit_keys_follow_the_keymap() {
+        let mut compositor = FakeCompositor::new(Config {
+            quit_key: "q".to_owned(),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        let keyboard = wl_keyboard::WlKeyboard::inert(compositor.conn.backend().downgrade());
+        let send = |compositor: &mut FakeCompositor, event| {
+            <State as Dispatch<wl_keyboard::WlKeyboard, ()>>::event(
+                &mut compositor.state,
+                &keyboard,
+                event,
+                &(),
+                &compositor.conn,
+                &compositor.qh,
+            )
+        };
+        let press = |key| wl_keyboard::Event::Key {
+            serial: 1,
+            time: 0,
+            key,
+            state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+        };
+
+        // A layout with Q where A is on a US keyboard.
+        let mut keymap = tempfile::tempfile().unwrap();
+        let text = "xkb_keymap { xkb_keycodes { <AC01> = 38; <SPCE> = 65; }; \
+                    xkb_symbols { key <AC01> { [ q, Q ] }; key <SPCE> { [ space ] }; }; };";
+        std::io::Write::write_all(&mut keymap, text.as_bytes()).unwrap();
+        let surface = compositor.state.parent_surface.clone().unwrap();
+        send(
+            &mut compositor,
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd: keymap.into(),
+                size: text.len() as u32,
+            },
+        );
+        send(
+            &mut compositor,
+            wl_keyboard::Event::Enter {
+                serial: 1,
+                surface,
+                keys: Vec::new(),
+            },
+        );
+
+        send(&mut compositor, press(57));
+        assert!(compositor.state.paused);
+        compositor.state.draw(&qh).unwrap();
+        let x = compositor.state.sprites[0].animation.position().0;
+        compositor.state.repaint_required = true;
+        compositor.state.draw(&qh).unwrap();
+        assert_eq!(compositor.state.sprites[0].animation.position().0, x);
+        send(&mut compositor, press(57));
+        assert!(!compositor.state.paused);
+
+        send(&mut compositor, press(16));
+        assert!(compositor.state.running);
+        send(&mut compositor, press(30));
+        assert!(!compositor.state.running);
+    }
+
+    #[test]
+    fn starts_paused_on_the_first_frame() {
+        let mut compositor = FakeCompositor::new(Config {
+            start_paused: true,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+
+        let position = compositor.state.sprites[0].animation.position();
+        for _ in 0..3 {
+            compositor.state.repaint_required = true;
+            compositor.state.draw(&qh).unwrap();
+        }
+        let sprite = &compositor.state.sprites[0];
+        assert_eq!(sprite.shown.unwrap().position, position);
+        assert_eq!(sprite.animation.position(), position);
+        assert!(sprite.animation.describe().contains(" frame 0/"));
+    }
+
+    #[test]
+    fn steps_a_frame_at_a_time_while_paused() {
+        let mut compositor = FakeCompositor::new(Config {
+            start_paused: true,
+            seed: Some(1),
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        compositor.state.draw(&qh).unwrap();
+        compositor.state.keyboard_focused = true;
+        let keyboard = wl_keyboard::WlKeyboard::inert(compositor.conn.backend().downgrade());
+        let press = |compositor: &mut FakeCompositor, key| {
+            <State as Dispatch<wl_keyboard::WlKeyboard, ()>>::event(
+                &mut compositor.state,
+                &keyboard,
+                wl_keyboard::Event::Key {
+                    serial: 1,
+                    time: 0,
+                    key,
+                    state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+                },
+                &(),
+                &compositor.conn,
+                &compositor.qh,
+            );
+            compositor.state.draw(&qh).unwrap();
+            compositor.state.sprites[0].animation.describe()
+        };
+
+        let (x, _) = compositor.state.sprites[0].animation.position();
+        assert!(press(&mut compositor, 52).contains(" frame 1/"));
+        assert_ne!(compositor.state.sprites[0].animation.position().0, x);
+        assert!(compositor.state.paused);
+        assert!(press(&mut compositor, 51).contains(" frame 0/"));
+        assert!(press(&mut compositor, 51).contains(&format!(
+            " frame {}/",
+            compositor.state.sprites[0].animation.frame_count() - 1
+        )));
+    }
+
+    #[test]
+    fn quitting_fades_out_first() {
+        let mut compositor = FakeCompositor::new(Config {
+            fade_ms: 100,
+            ..config()
+        });
+        let qh = compositor.qh.clone();
+        compositor.configure((800, 600), &[xdg_toplevel::State::Fullscreen]);
+        let frame_done = |compositor: &mut FakeCompositor, time| {
+            let callback = compositor.state.frame_surface().frame(
+                &qh,
+                FrameDone {
+                    base_time: None,
+                    count: 0,
+                },
+            );
+            compositor.send(
+                &callback,
+                wl_callback::Event::Done {
+                    callback_data: time,
+                },
+            );
+        };
+
+        frame_done(&mut compositor, 1000);
+        assert_eq!(compositor.state.fade_alpha(), 0.0);
+        frame_done(&mut compositor, 1100);
+        assert_eq!(compositor.state.fade_alpha(), 1.0);
+
+        let (_, toplevel) = compositor.state.parent_xdg_surface.clone().unwrap();
+        compositor.send(&toplevel, xdg_toplevel::Event::Close);
+        assert!(compositor.state.running);
+        frame_done(&mut compositor, 1150);
+        assert_eq!(compositor.state.fade_alpha(), 0.5);
+        compositor.state.draw(&qh).unwrap();
+        assert!(compositor.state.running);
+
+        frame_done(&mut compositor, 1250);
+        compositor.state.repaint_required = true;
+        compositor.state.draw(&qh).unwrap();
+        assert!(!compositor.state.running);
+    }
+
+    #[test]
+    fn says_which_wayland_display_it_cant_reach() {
+        let error = connect(Some("/nonexistent/wayland-gopher"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("--wayland-display /nonexistent/wayland-gopher: "));
+    }
+}